@@ -0,0 +1,326 @@
+use chrono::prelude::*;
+use json::JsonValue;
+
+use crate::charging_profiles;
+use crate::clock;
+use crate::components;
+use crate::errors::EmulatorError;
+use crate::ids;
+use crate::plug_and_charge;
+use crate::requests;
+use crate::storage;
+use crate::transactions;
+
+/// Present an id token at the EVSE to start or stop a local session.
+///
+/// Tokens accepted by the local list or the authorization cache start a transaction immediately if
+/// `AuthCtrlr.LocalPreAuthorize` (online) or `AuthCtrlr.LocalAuthorizeOffline` (offline) is enabled,
+/// other tokens are authorized with an Authorize request first. Offline station starts transactions for
+/// unknown tokens only if `AuthCtrlr.OfflineTxForUnknownIdEnabled` is enabled. The token which started
+/// an ongoing transaction on the EVSE or another token of its group stops it.
+/// Returns `Accepted` if the token is accepted or waits for authorization, `Rejected` otherwise.
+pub fn present(evse_id: usize, id_token: &JsonValue) -> Result<&'static str, EmulatorError> {
+    if evse_id == 0 || evse_id > storage::evse_count() || id_token["idToken"].is_null() {
+        return Ok("Rejected");
+    }
+
+    let token: &str = &id_token["idToken"].to_string();
+    let online = storage::is_online();
+
+    let id_token_info = if is_enabled(if online { "LocalPreAuthorize" } else { "LocalAuthorizeOffline" }) {
+        known_entry(token)?
+    } else {
+        None
+    };
+
+    // Ongoing transaction can be stopped with its own token or a token of the same group.
+    if let Some((transaction_id, transaction)) = ongoing_transaction(evse_id - 1) {
+        if transaction["idToken"]["idToken"] == token {
+            transactions::stop(&transaction_id, "StopAuthorized", "Local")?;
+
+            return Ok("Accepted");
+        }
+
+        match id_token_info {
+            Some(id_token_info) => {
+                if id_token_info["status"] != "Accepted" || !same_group(&transaction, &id_token_info) {
+                    return Ok("Rejected");
+                }
+
+                transactions::stop(&transaction_id, "StopAuthorized", "Local")?;
+            },
+            // Group of an unknown token is received with its authorization.
+            None => {
+                storage::set_pending_authorization(token.to_string(), Some(evse_id - 1));
+
+                queue_authorize(id_token.clone(), None);
+            },
+        }
+
+        return Ok("Accepted");
+    }
+
+    let connector_index = match transactions::free_connector(evse_id - 1) {
+        Some(res) => res,
+        None => return Ok("Rejected"),
+    };
+
+    // Reserved connector can be used only with the id token of the reservation.
+    let reserved_for_token = storage::get_reservations().iter()
+        .filter_map(|(_, reservation)| json::parse(reservation).ok())
+        .any(|reservation| reservation["evseId"].as_usize() == Some(evse_id) && reservation["idToken"]["idToken"] == token);
+
+    if storage::get_connector(evse_id - 1, connector_index).is_none_or(|connector| connector.status == "Reserved" && !reserved_for_token) {
+        return Ok("Rejected");
+    }
+
+    match id_token_info {
+        Some(id_token_info) if id_token_info["status"] == "Accepted" => {
+            transactions::start(evse_id - 1, id_token, &id_token_info["groupIdToken"], "Authorized")?;
+        },
+        Some(_) => return Ok("Rejected"),
+        // Transaction of an unknown token is authorized by CSMS when the station is back online.
+        None if !online => {
+            if !is_enabled("OfflineTxForUnknownIdEnabled") {
+                return Ok("Rejected");
+            }
+
+            transactions::start(evse_id - 1, id_token, &JsonValue::Null, "Authorized")?;
+        },
+        None => {
+            storage::set_pending_authorization(token.to_string(), Some(evse_id - 1));
+
+            queue_authorize(id_token.clone(), None);
+        },
+    }
+
+    Ok("Accepted")
+}
+
+/// Start a transaction requested by CSMS with RequestStartTransaction.
+///
+/// The id token is authorized with an Authorize request first if `AuthCtrlr.AuthorizeRemoteStart` is enabled
+/// unless `AuthCtrlr.LocalPreAuthorize` is enabled and the local list or the authorization cache accepts it.
+pub fn remote_start(evse_index: usize, connector_index: usize, mut details: JsonValue) -> Result<(), EmulatorError> {
+    let token: &str = &details["idToken"]["idToken"].to_string();
+
+    let pre_authorized = is_enabled("LocalPreAuthorize") && known_entry(token)?.is_some_and(|id_token_info| id_token_info["status"] == "Accepted");
+
+    if is_enabled("AuthorizeRemoteStart") && !pre_authorized {
+        details["evseIndex"] = evse_index.into();
+        details["connectorIndex"] = connector_index.into();
+
+        storage::set_pending_remote_start(token.to_string(), Some(details.dump()));
+
+        queue_authorize(details["idToken"].clone(), None);
+
+        return Ok(());
+    }
+
+    start_remote(evse_index, connector_index, details)
+}
+
+fn start_remote(evse_index: usize, connector_index: usize, details: JsonValue) -> Result<(), EmulatorError> {
+    let transaction_id: &str = &transactions::authorize(evse_index, details.clone(), "RemoteStart")?;
+
+    // Bind charging profile of the request to the transaction.
+    if !details["chargingProfile"].is_null() {
+        let status = charging_profiles::set_for_transaction(evse_index + 1, transaction_id, &details["chargingProfile"])?;
+
+        println!("Charging profile of transaction {} is {}.", transaction_id, status);
+    }
+
+    // Start charging once the EV is connected.
+    transactions::connect(evse_index, connector_index, transaction_id)
+}
+
+/// Queue an Authorize request.
+pub fn queue_authorize(id_token: JsonValue, certificate_hash_data: Option<JsonValue>) {
+    let additional_info = if id_token["additionalInfo"].is_null() { None } else { Some(id_token["additionalInfo"].clone()) };
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::authorize(msg_id, &id_token["idToken"].to_string(), &id_token["type"].to_string(), additional_info, certificate_hash_data);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+/// Handle an Authorize response: cache the result and start the waiting session if the token is accepted.
+pub fn authorized(id_token: &JsonValue, id_token_info: &JsonValue) -> Result<(), EmulatorError> {
+    let token: &str = &id_token["idToken"].to_string();
+
+    if components::get_variable("AuthCacheCtrlr", "Enabled").1.as_deref() == Some("true") {
+        storage::set_auth_cache_entry(token.to_string(), id_token_info.dump());
+    }
+
+    // Remote start waits for the authorization of its id token.
+    if let Some(remote_start) = storage::get_pending_remote_start(token) {
+        storage::set_pending_remote_start(token.to_string(), None);
+
+        let mut details = json::parse(&remote_start)?;
+
+        if id_token_info["status"] != "Accepted" {
+            println!("Remote start with id token {} was not authorized: {}", token, id_token_info["status"]);
+
+            return Ok(());
+        }
+
+        let evse_index = details.remove("evseIndex").as_usize().unwrap_or(0);
+        let connector_index = details.remove("connectorIndex").as_usize().unwrap_or(0);
+
+        if !id_token_info["groupIdToken"].is_null() {
+            details["groupIdToken"] = id_token_info["groupIdToken"].clone();
+        }
+
+        return start_remote(evse_index, connector_index, details);
+    }
+
+    let evse_index = match storage::get_pending_authorization(token) {
+        Some(res) => res,
+        None => return Ok(()),
+    };
+
+    storage::set_pending_authorization(token.to_string(), None);
+
+    if id_token_info["status"] != "Accepted" {
+        println!("Id token {} was not authorized: {}", token, id_token_info["status"]);
+
+        return Ok(());
+    }
+
+    // Token of the same group stops the ongoing transaction.
+    if let Some((transaction_id, transaction)) = ongoing_transaction(evse_index) {
+        if same_group(&transaction, id_token_info) {
+            transactions::stop(&transaction_id, "StopAuthorized", "Local")?;
+        } else {
+            println!("Id token {} is not allowed to stop transaction {}.", token, transaction_id);
+        }
+
+        return Ok(());
+    }
+
+    let transaction_id = transactions::start(evse_index, id_token, &id_token_info["groupIdToken"], "Authorized")?;
+
+    // Plug & Charge EV negotiates its charging schedule.
+    if id_token["type"] == "eMAID" {
+        plug_and_charge::session_started(evse_index, &transaction_id)?;
+    }
+
+    Ok(())
+}
+
+/// Get the ongoing transaction of the EVSE which is not stopped yet.
+fn ongoing_transaction(evse_index: usize) -> Option<(String, JsonValue)> {
+    storage::get_transactions().into_iter()
+        .filter_map(|(transaction_id, transaction)| json::parse(&transaction).ok().map(|transaction| (transaction_id, transaction)))
+        .find(|(_, transaction)| transactions::connector(transaction).0 == evse_index && !transaction["idToken"].is_null() && transaction["stoppedReason"].is_null())
+}
+
+/// Check whether the id token info belongs to the group of the token which started the transaction.
+fn same_group(transaction: &JsonValue, id_token_info: &JsonValue) -> bool {
+    !transaction["groupIdToken"]["idToken"].is_null() && transaction["groupIdToken"]["idToken"] == id_token_info["groupIdToken"]["idToken"]
+}
+
+/// Get the id token info of the local list or the authorization cache.
+fn known_entry(id_token: &str) -> Result<Option<JsonValue>, EmulatorError> {
+    match local_list_entry(id_token)? {
+        Some(res) => Ok(Some(res)),
+        None => cache_entry(id_token),
+    }
+}
+
+fn is_enabled(variable_name: &str) -> bool {
+    components::get_variable("AuthCtrlr", variable_name).1.as_deref() == Some("true")
+}
+
+/// Check an id token against the authorization cache.
+///
+/// Expired entries are removed from the cache.
+/// Returns the cached id token info.
+fn cache_entry(id_token: &str) -> Result<Option<JsonValue>, EmulatorError> {
+    if components::get_variable("AuthCacheCtrlr", "Enabled").1.as_deref() != Some("true") {
+        return Ok(None);
+    }
+
+    let id_token_info = match storage::get_auth_cache_entry(id_token) {
+        Some(res) => json::parse(&res)?,
+        None => return Ok(None),
+    };
+
+    let expired = id_token_info["cacheExpiryDateTime"].as_str()
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .is_some_and(|date| date.with_timezone(&Utc) < clock::now());
+
+    if expired {
+        storage::delete_auth_cache_entry(id_token);
+
+        return Ok(None);
+    }
+
+    Ok(Some(id_token_info))
+}
+
+/// Check an id token against the local authorization list.
+///
+/// Returns the stored authorization status of the token or `None` if the local list is disabled
+/// or does not contain the token.
+pub fn local_list_status(id_token: &str) -> Result<Option<String>, EmulatorError> {
+    Ok(local_list_entry(id_token)?.map(|id_token_info| id_token_info["status"].to_string()))
+}
+
+/// Get the id token info stored in the local authorization list.
+fn local_list_entry(id_token: &str) -> Result<Option<JsonValue>, EmulatorError> {
+    if components::get_variable("LocalAuthListCtrlr", "Enabled").1.as_deref() != Some("true") {
+        return Ok(None);
+    }
+
+    let entry = storage::get_local_list_entry(id_token);
+
+    if entry.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(json::parse(&entry)?))
+}
+
+/// Apply a SendLocalList update to the stored local authorization list.
+///
+/// Returns the status of the update: `Accepted`, `Failed` or `VersionMismatch`.
+pub fn update_local_list(version_number: u64, update_type: &str, list: &json::JsonValue) -> &'static str {
+    match update_type {
+        "Full" => {
+            storage::clear_local_list();
+
+            for i in 0..list.len() {
+                let entry = &list[i];
+
+                if !entry["idTokenInfo"].is_null() {
+                    storage::set_local_list_entry(entry["idToken"]["idToken"].to_string(), entry["idTokenInfo"].dump());
+                }
+            }
+        },
+        "Differential" => {
+            if version_number <= storage::get_local_list_version() {
+                return "VersionMismatch";
+            }
+
+            for i in 0..list.len() {
+                let entry = &list[i];
+                let id_token: &str = &entry["idToken"]["idToken"].to_string();
+
+                // Entries without id token info are removed from the list.
+                if entry["idTokenInfo"].is_null() {
+                    storage::delete_local_list_entry(id_token);
+                } else {
+                    storage::set_local_list_entry(id_token.to_string(), entry["idTokenInfo"].dump());
+                }
+            }
+        },
+        _ => return "Failed",
+    }
+
+    storage::set_local_list_version(version_number);
+
+    "Accepted"
+}
@@ -0,0 +1,466 @@
+use std::fs;
+
+use json::JsonValue;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::PKey;
+use openssl::x509::{X509, X509NameBuilder, X509ReqBuilder};
+
+use crate::config;
+use crate::ids;
+use crate::requests;
+use crate::storage;
+
+// Certificate types which can be installed by CSMS.
+const INSTALL_CERTIFICATE_TYPES: [&str; 4] = ["V2GRootCertificate", "MORootCertificate", "CSMSRootCertificate", "ManufacturerRootCertificate"];
+
+/// Install a certificate from an InstallCertificate request.
+///
+/// Returns the status of the request: `Accepted`, `Rejected` or `Failed`.
+pub fn install(certificate_type: &str, certificate: &str) -> &'static str {
+    if !INSTALL_CERTIFICATE_TYPES.contains(&certificate_type) {
+        return "Rejected";
+    }
+
+    let hash_data = match hash_data(certificate, "SHA256") {
+        Some(res) => res,
+        None => return "Rejected",
+    };
+
+    let stored_certificate = object!{
+        "certificateType" => certificate_type,
+        "certificate" => certificate,
+        "certificateHashData" => hash_data.clone(),
+    };
+
+    storage::set_certificate(certificate_key(&hash_data), stored_certificate.dump());
+
+    "Accepted"
+}
+
+/// Delete a certificate matching hash data from a DeleteCertificate request.
+///
+/// Returns the status of the request: `Accepted` or `NotFound`.
+pub fn delete(certificate_hash_data: &JsonValue) -> &'static str {
+    for (key, certificate) in storage::get_certificates() {
+        if is_identified_by(&parse(&certificate), certificate_hash_data) {
+            storage::delete_certificate(&key);
+
+            return "Accepted";
+        }
+    }
+
+    "NotFound"
+}
+
+/// Get installed certificates of the given types (all types if the list is empty).
+pub fn installed(certificate_types: &JsonValue) -> Vec<JsonValue> {
+    storage::get_certificates().into_iter()
+        .map(|(_, certificate)| parse(&certificate))
+        .filter(|certificate| certificate_types.is_empty() || certificate_types.members().any(|certificate_type| *certificate_type == certificate["certificateType"]))
+        .collect()
+}
+
+/// Generate a new key pair and a certificate signing request for a SignCertificate request.
+///
+/// The private key is kept until CSMS sends the signed certificate.
+/// Returns the PEM encoded CSR.
+pub fn generate_csr(certificate_type: &str) -> Option<String> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).ok()?;
+    let key = PKey::from_ec_key(EcKey::generate(&group).ok()?).ok()?;
+
+    let station_id = config::var("STATION_ID").unwrap_or_default();
+    let organization_name = match config::var("VENDOR_NAME") {
+        Ok(var) => if var.is_empty() { "Vendor name".to_string() } else { var },
+        _ => "Vendor name".to_string(),
+    };
+
+    let mut name = X509NameBuilder::new().ok()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, &station_id).ok()?;
+    name.append_entry_by_nid(Nid::ORGANIZATIONNAME, &organization_name).ok()?;
+
+    let mut request = X509ReqBuilder::new().ok()?;
+    request.set_subject_name(&name.build()).ok()?;
+    request.set_pubkey(&key).ok()?;
+    request.sign(&key, MessageDigest::sha256()).ok()?;
+
+    let csr = String::from_utf8(request.build().to_pem().ok()?).ok()?;
+    let private_key = String::from_utf8(key.private_key_to_pem_pkcs8().ok()?).ok()?;
+
+    storage::set_pending_private_key(certificate_type.to_string(), Some(private_key));
+
+    Some(csr)
+}
+
+/// Store a certificate chain from a CertificateSigned request.
+///
+/// The chain is accepted only if its leaf certificate matches the key generated for the last CSR.
+/// Returns the status of the request: `Accepted` or `Rejected`.
+pub fn install_signed(certificate_chain: &str, certificate_type: &str) -> &'static str {
+    let private_key = match storage::get_pending_private_key(certificate_type) {
+        Some(res) => res,
+        None => return "Rejected",
+    };
+
+    let chain = match X509::stack_from_pem(certificate_chain.as_bytes()) {
+        Ok(res) => res,
+        Err(_) => return "Rejected",
+    };
+
+    let key_matches = match (chain.first().and_then(|leaf| leaf.public_key().ok()), PKey::private_key_from_pem(private_key.as_bytes()).ok()) {
+        (Some(public_key), Some(private_key)) => public_key.public_eq(&private_key),
+        _ => false,
+    };
+
+    if !key_matches {
+        return "Rejected";
+    }
+
+    let station_certificate = object!{
+        "certificateChain" => certificate_chain,
+        "privateKey" => private_key,
+    };
+
+    storage::set_station_certificate(certificate_type.to_string(), Some(station_certificate.dump()));
+    storage::set_pending_private_key(certificate_type.to_string(), None);
+
+    "Accepted"
+}
+
+/// Install the charging station certificate which is configured for security profile 3.
+///
+/// Certificate chain and private key are read from the PEM files `STATION_CERTIFICATE_FILE` and `STATION_PRIVATE_KEY_FILE`
+/// or from the PKCS#12 file `STATION_PKCS12_FILE` protected with `STATION_PKCS12_PASSWORD`.
+/// Certificate which CSMS issues later replaces the configured one.
+pub fn load_station_certificate() {
+    let (certificate_chain, private_key) = if let Some(path) = env_path("STATION_PKCS12_FILE") {
+        let content = match fs::read(&path) {
+            Ok(res) => res,
+            Err(e) => panic!("Couldn't read STATION_PKCS12_FILE ({})", e),
+        };
+
+        let parsed = match Pkcs12::from_der(&content).and_then(|archive| archive.parse2(&config::var("STATION_PKCS12_PASSWORD").unwrap_or_default())) {
+            Ok(res) => res,
+            Err(e) => panic!("Error during parsing: {:?}", e),
+        };
+
+        let (certificate, key) = match (parsed.cert, parsed.pkey) {
+            (Some(certificate), Some(key)) => (certificate, key),
+            _ => panic!("STATION_PKCS12_FILE must contain a certificate and a private key."),
+        };
+
+        let mut certificate_chain = pem(certificate.to_pem());
+
+        for ca_certificate in parsed.ca.into_iter().flatten() {
+            certificate_chain.push_str(&pem(ca_certificate.to_pem()));
+        }
+
+        (certificate_chain, pem(key.private_key_to_pem_pkcs8()))
+    } else if let Some(path) = env_path("STATION_CERTIFICATE_FILE") {
+        let certificate_chain = match fs::read_to_string(&path) {
+            Ok(res) => res,
+            Err(e) => panic!("Couldn't read STATION_CERTIFICATE_FILE ({})", e),
+        };
+
+        let private_key = match env_path("STATION_PRIVATE_KEY_FILE").map(fs::read_to_string) {
+            Some(Ok(res)) => res,
+            Some(Err(e)) => panic!("Couldn't read STATION_PRIVATE_KEY_FILE ({})", e),
+            None => panic!("STATION_PRIVATE_KEY_FILE must be set together with STATION_CERTIFICATE_FILE."),
+        };
+
+        (certificate_chain, private_key)
+    } else {
+        return;
+    };
+
+    let key_matches = match (X509::from_pem(certificate_chain.as_bytes()).and_then(|leaf| leaf.public_key()), PKey::private_key_from_pem(private_key.as_bytes())) {
+        (Ok(public_key), Ok(private_key)) => public_key.public_eq(&private_key),
+        _ => false,
+    };
+
+    if !key_matches {
+        panic!("Private key doesn't match the charging station certificate.");
+    }
+
+    let station_certificate = object!{
+        "certificateChain" => certificate_chain,
+        "privateKey" => private_key,
+    };
+
+    storage::set_station_certificate(String::from("ChargingStationCertificate"), Some(station_certificate.dump()));
+}
+
+/// Get the installed certificate chain and private key of the charging station.
+pub fn station_certificate(certificate_type: &str) -> Option<(String, String)> {
+    let station_certificate = parse(&storage::get_station_certificate(certificate_type)?);
+
+    Some((station_certificate["certificateChain"].to_string(), station_certificate["privateKey"].to_string()))
+}
+
+/// Check whether a new charging station certificate has to be requested.
+///
+/// Certificate is renewed when it is missing or expires within the given number of days.
+pub fn station_certificate_expires(days: u32) -> bool {
+    let (certificate_chain, _) = match station_certificate("ChargingStationCertificate") {
+        Some(res) => res,
+        None => return true,
+    };
+
+    let leaf = match X509::from_pem(certificate_chain.as_bytes()) {
+        Ok(res) => res,
+        Err(_) => return true,
+    };
+
+    match openssl::asn1::Asn1Time::days_from_now(days) {
+        Ok(deadline) => leaf.not_after() < deadline,
+        Err(_) => true,
+    }
+}
+
+/// Compute hash data which identifies a PEM encoded certificate with the hash algorithm: SHA256, SHA384 or SHA512.
+///
+/// Issuer key hash is computed from the key of the installed issuer certificate
+/// or from the key of the certificate itself if it is self-signed or the issuer is unknown.
+pub fn hash_data(certificate: &str, hash_algorithm: &str) -> Option<JsonValue> {
+    let digest = match hash_algorithm {
+        "SHA256" => MessageDigest::sha256(),
+        "SHA384" => MessageDigest::sha384(),
+        "SHA512" => MessageDigest::sha512(),
+        _ => return None,
+    };
+
+    let x509 = X509::from_pem(certificate.as_bytes()).ok()?;
+
+    let issuer_name_der = x509.issuer_name().to_der().ok()?;
+    let issuer_name_hash = hex(&hash(digest, &issuer_name_der).ok()?);
+
+    let issuer = storage::get_certificates().into_iter()
+        .filter_map(|(_, stored)| X509::from_pem(parse(&stored)["certificate"].to_string().as_bytes()).ok())
+        .find(|stored| stored.subject_name().to_der().ok().as_ref() == Some(&issuer_name_der))
+        .unwrap_or_else(|| x509.clone());
+
+    let public_key_der = issuer.public_key().ok()?.public_key_to_der().ok()?;
+    let issuer_key_hash = hex(&hash(digest, subject_public_key(&public_key_der)?).ok()?);
+
+    let serial_number = x509.serial_number().to_bn().ok()?.to_hex_str().ok()?.to_lowercase();
+
+    Some(object!{
+        "hashAlgorithm" => hash_algorithm,
+        "issuerNameHash" => issuer_name_hash,
+        "issuerKeyHash" => issuer_key_hash,
+        "serialNumber" => serial_number.trim_start_matches('0'),
+    })
+}
+
+/// Compute OCSP request data of a PEM encoded certificate.
+///
+/// Responder URL is read from the authority information access extension of the certificate,
+/// `OCSP_RESPONDER_URL` is used for certificates without it.
+pub fn ocsp_request_data(certificate: &str) -> Option<JsonValue> {
+    let mut request_data = hash_data(certificate, "SHA256")?;
+
+    let responder_url = X509::from_pem(certificate.as_bytes()).ok()?
+        .ocsp_responders().ok()
+        .and_then(|responders| responders.iter().next().map(|url| url.to_string()))
+        .unwrap_or_else(|| config::var("OCSP_RESPONDER_URL").unwrap_or_default());
+
+    request_data["responderURL"] = responder_url.into();
+
+    Some(request_data)
+}
+
+/// Queue GetCertificateStatus requests for installed certificates.
+pub fn request_statuses() {
+    for (_, certificate) in storage::get_certificates() {
+        let ocsp_request_data = match ocsp_request_data(&parse(&certificate)["certificate"].to_string()) {
+            Some(res) => res,
+            None => continue,
+        };
+
+        let msg_id: &str = &ids::uuid();
+        let msg = requests::get_certificate_status(msg_id, ocsp_request_data);
+
+        storage::set_message(msg_id.to_string(), msg.to_owned());
+
+        storage::queue_add(msg);
+    }
+}
+
+/// Handle GetCertificateStatus response: save the OCSP result of the certificate.
+pub fn status_received(ocsp_request_data: &JsonValue, response: &JsonValue) {
+    if response["status"] != "Accepted" {
+        println!("Couldn't get status of certificate {}: {}", ocsp_request_data["serialNumber"], response["status"]);
+
+        return;
+    }
+
+    for (key, certificate) in storage::get_certificates() {
+        let mut certificate = parse(&certificate);
+
+        if is_identified_by(&certificate, ocsp_request_data) {
+            certificate["ocspResult"] = response["ocspResult"].clone();
+
+            storage::set_certificate(key, certificate.dump());
+        }
+    }
+}
+
+/// Check whether hash data refer to the same certificate.
+pub fn matches(hash_data: &JsonValue, other: &JsonValue) -> bool {
+    let normalize = |value: &JsonValue| value.to_string().to_lowercase();
+
+    normalize(&hash_data["issuerNameHash"]) == normalize(&other["issuerNameHash"])
+        && normalize(&hash_data["issuerKeyHash"]) == normalize(&other["issuerKeyHash"])
+        && normalize(&hash_data["serialNumber"]).trim_start_matches('0') == normalize(&other["serialNumber"]).trim_start_matches('0')
+}
+
+/// Check whether hash data computed with any hash algorithm refer to the stored certificate.
+fn is_identified_by(stored_certificate: &JsonValue, hash_data: &JsonValue) -> bool {
+    let hash_algorithm = hash_data["hashAlgorithm"].as_str().unwrap_or("SHA256");
+
+    match self::hash_data(&stored_certificate["certificate"].to_string(), hash_algorithm) {
+        Some(certificate_hash_data) => matches(&certificate_hash_data, hash_data),
+        None => matches(&stored_certificate["certificateHashData"], hash_data),
+    }
+}
+
+/// Extract the public key bits from a DER encoded SubjectPublicKeyInfo structure.
+fn subject_public_key(der: &[u8]) -> Option<&[u8]> {
+    // SubjectPublicKeyInfo ::= SEQUENCE { algorithm AlgorithmIdentifier, subjectPublicKey BIT STRING }
+    let (_, content) = der_element(der)?;
+    let (algorithm_length, _) = der_element(content)?;
+    let (_, bit_string) = der_element(&content[algorithm_length..])?;
+
+    // The first byte of a bit string holds the number of unused bits.
+    bit_string.get(1..)
+}
+
+/// Parse a DER element header.
+///
+/// Returns the total length of the element and its content.
+fn der_element(der: &[u8]) -> Option<(usize, &[u8])> {
+    let first_length_byte = *der.get(1)? as usize;
+
+    let (header_length, content_length) = if first_length_byte < 0x80 {
+        (2, first_length_byte)
+    } else {
+        let length_bytes = first_length_byte & 0x7f;
+        let length = der.get(2..2 + length_bytes)?.iter().fold(0, |length, byte| (length << 8) | *byte as usize);
+
+        (2 + length_bytes, length)
+    };
+
+    let content = der.get(header_length..header_length + content_length)?;
+
+    Some((header_length + content_length, content))
+}
+
+fn certificate_key(hash_data: &JsonValue) -> String {
+    format!("{}:{}", hash_data["issuerNameHash"], hash_data["serialNumber"])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Get a path configured with an environment variable.
+fn env_path(name: &str) -> Option<String> {
+    match config::var(name) {
+        Ok(var) => if var.is_empty() { None } else { Some(var) },
+        _ => None,
+    }
+}
+
+/// Convert PEM encoded data to a string.
+fn pem(data: Result<Vec<u8>, ErrorStack>) -> String {
+    match data.map(String::from_utf8) {
+        Ok(Ok(res)) => res,
+        _ => panic!("Couldn't encode the charging station certificate."),
+    }
+}
+
+fn parse(value: &str) -> JsonValue {
+    match json::parse(value) {
+        Ok(result) => result,
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::{BigNum, BigNumContext};
+    use openssl::ec::PointConversionForm;
+    use openssl::x509::X509Builder;
+
+    fn self_signed_certificate() -> (String, EcKey<openssl::pkey::Private>) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = EcKey::generate(&group).unwrap();
+        let pkey = PKey::from_ec_key(key.clone()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "Test").unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&BigNum::from_u32(0x0775).unwrap().to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&pkey).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+
+        (String::from_utf8(builder.build().to_pem().unwrap()).unwrap(), key)
+    }
+
+    #[test]
+    fn der_element_reads_short_and_long_lengths() {
+        assert_eq!(der_element(&[0x04, 0x02, 0xaa, 0xbb]), Some((4, &[0xaa, 0xbb][..])));
+
+        let mut long = vec![0x04, 0x81, 0x80];
+        long.extend(vec![0x01; 0x80]);
+
+        assert_eq!(der_element(&long).map(|(length, content)| (length, content.len())), Some((0x83, 0x80)));
+        assert_eq!(der_element(&[0x04, 0x05, 0xaa]), None);
+    }
+
+    #[test]
+    fn subject_public_key_is_extracted_from_key_info() {
+        let (certificate, key) = self_signed_certificate();
+        let x509 = X509::from_pem(certificate.as_bytes()).unwrap();
+        let der = x509.public_key().unwrap().public_key_to_der().unwrap();
+
+        let mut context = BigNumContext::new().unwrap();
+        let point = key.public_key().to_bytes(key.group(), PointConversionForm::UNCOMPRESSED, &mut context).unwrap();
+
+        assert_eq!(subject_public_key(&der), Some(&point[..]));
+    }
+
+    #[test]
+    fn hash_data_follows_the_hash_algorithm() {
+        let (certificate, _) = self_signed_certificate();
+
+        let sha256 = hash_data(&certificate, "SHA256").unwrap();
+        let sha512 = hash_data(&certificate, "SHA512").unwrap();
+
+        assert_eq!(sha256["issuerNameHash"].to_string().len(), 64);
+        assert_eq!(hash_data(&certificate, "SHA384").unwrap()["issuerKeyHash"].to_string().len(), 96);
+        assert_eq!(sha512["hashAlgorithm"], "SHA512");
+        assert_eq!(sha512["serialNumber"], "775");
+        assert!(hash_data(&certificate, "MD5").is_none());
+
+        let stored_certificate = object!{
+            "certificate" => certificate.to_owned(),
+            "certificateHashData" => sha256,
+        };
+
+        assert!(is_identified_by(&stored_certificate, &sha512));
+    }
+}
@@ -0,0 +1,618 @@
+use chrono::prelude::*;
+use json::JsonValue;
+
+use crate::clock;
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::external_limits;
+use crate::load_management;
+use crate::state_machine;
+use crate::storage;
+use crate::temperature;
+use crate::topology;
+use crate::transactions;
+
+// Values used to convert limits in amperes into watts.
+pub const NOMINAL_VOLTAGE: f64 = 230.0;
+pub const DEFAULT_NUMBER_PHASES: f64 = 3.0;
+
+/// Validate and store a charging profile from a SetChargingProfile request.
+///
+/// Returns the status of the request: `Accepted` or `Rejected`.
+pub fn set(evse_id: usize, profile: &JsonValue) -> Result<&'static str, EmulatorError> {
+    let profile_id: u64 = match profile["id"].as_u64() {
+        Some(res) => res,
+        None => return Ok("Rejected"),
+    };
+
+    let stack_level: u64 = match profile["stackLevel"].as_u64() {
+        Some(res) => res,
+        None => return Ok("Rejected"),
+    };
+
+    if evse_id > storage::evse_count() {
+        return Ok("Rejected");
+    }
+
+    let purpose: &str = &profile["chargingProfilePurpose"].to_string();
+
+    match purpose {
+        "ChargingStationMaxProfile" => {
+            if evse_id != 0 {
+                return Ok("Rejected");
+            }
+        },
+        "TxDefaultProfile" => (),
+        "TxProfile" => {
+            if evse_id == 0 {
+                return Ok("Rejected");
+            }
+
+            // TxProfile can be set only for an ongoing transaction on the EVSE.
+            match get_transaction(&profile["transactionId"].to_string())? {
+                Some(transaction) => {
+                    if transaction["evseId"].as_usize() != Some(evse_id) {
+                        return Ok("Rejected");
+                    }
+                },
+                None => return Ok("Rejected"),
+            };
+        },
+        // ChargingStationExternalConstraints can not be set by CSMS.
+        _ => return Ok("Rejected"),
+    };
+
+    if schedules(profile).is_empty() {
+        return Ok("Rejected");
+    }
+
+    // Profile with the same purpose and stack level can not be set on the same EVSE.
+    for (id, stored_profile) in storage::get_charging_profiles() {
+        let stored_profile = parse(&stored_profile)?;
+
+        if id != profile_id
+            && stored_profile["evseId"].as_usize() == Some(evse_id)
+            && stored_profile["chargingProfilePurpose"] == purpose
+            && stored_profile["stackLevel"].as_u64() == Some(stack_level) {
+            return Ok("Rejected");
+        }
+    }
+
+    let mut stored_profile = profile.clone();
+    stored_profile["evseId"] = evse_id.into();
+
+    storage::set_charging_profile(profile_id, stored_profile.dump());
+
+    Ok("Accepted")
+}
+
+/// Check a charging profile from a RequestStartTransaction request before the transaction is started.
+///
+/// Only Tx profiles which are not bound to a transaction yet are allowed.
+pub fn is_valid_start_profile(profile: &JsonValue) -> bool {
+    profile["chargingProfilePurpose"] == "TxProfile"
+        && profile["transactionId"].is_null()
+        && profile["id"].as_u64().is_some()
+        && profile["stackLevel"].as_u64().is_some()
+        && !schedules(profile).is_empty()
+}
+
+/// Store a charging profile from a RequestStartTransaction request as a Tx profile of the started transaction.
+pub fn set_for_transaction(evse_id: usize, transaction_id: &str, profile: &JsonValue) -> Result<&'static str, EmulatorError> {
+    let mut profile = profile.clone();
+    profile["transactionId"] = transaction_id.into();
+
+    set(evse_id, &profile)
+}
+
+/// Remove charging profiles by id or by criteria from a ClearChargingProfile request.
+///
+/// Returns the status of the request: `Accepted` if at least one profile was removed or `Unknown`.
+pub fn clear(profile_id: Option<u64>, criteria: &JsonValue) -> Result<&'static str, EmulatorError> {
+    let mut status = "Unknown";
+
+    for (id, profile) in storage::get_charging_profiles() {
+        let profile = parse(&profile)?;
+
+        let matches = match profile_id {
+            Some(profile_id) => id == profile_id,
+            None => {
+                (criteria["evseId"].is_null() || criteria["evseId"].as_usize() == profile["evseId"].as_usize())
+                    && (criteria["chargingProfilePurpose"].is_null() || criteria["chargingProfilePurpose"] == profile["chargingProfilePurpose"])
+                    && (criteria["stackLevel"].is_null() || criteria["stackLevel"].as_u64() == profile["stackLevel"].as_u64())
+            },
+        };
+
+        // External constraints are not managed by CSMS.
+        if matches && profile["chargingProfilePurpose"] != "ChargingStationExternalConstraints" {
+            storage::delete_charging_profile(id);
+            status = "Accepted";
+        }
+    }
+
+    Ok(status)
+}
+
+/// Apply current limits to the power of EVSEs with ongoing transactions.
+pub fn apply_limits() {
+    enforce(true);
+}
+
+/// Enforce limits which change over time, e.g. with periods of charging schedules.
+pub fn enforce_limits() {
+    enforce(false);
+}
+
+/// Clamp the power of EVSEs with ongoing transactions to their current limits and the site capacity.
+///
+/// Limit which isn't set by CSMS is reported with NotifyChargingLimit when it starts to bound the power of the EVSE.
+fn enforce(verbose: bool) {
+    let mut offers: Vec<(usize, usize, f64)> = Vec::new();
+
+    for evse_index in 0..storage::evse_count() {
+        let (limit, source) = charging_limit(evse_index);
+        let enforced_limit = source.as_ref().map(|source| format!("{}:{}", source, limit));
+
+        if enforced_limit != storage::get_enforced_limit(evse_index) {
+            if let Some(source) = source.as_ref().filter(|source| *source != "CSO") {
+                external_limits::notify(evse_index, source, limit);
+            }
+
+            storage::set_enforced_limit(evse_index, enforced_limit);
+        }
+
+        let offered = (0..storage::connector_count(evse_index))
+            .find_map(|connector_index| transactions::offered_power(evse_index, connector_index).map(|power| (connector_index, power)));
+
+        if let Some((connector_index, power)) = offered {
+            offers.push((evse_index, connector_index, power));
+        }
+    }
+
+    for ((evse_index, connector_index, _), power) in offers.iter().zip(load_management::allocate(&offers)) {
+        let discharging = storage::get_connector(*evse_index, *connector_index).is_some_and(|connector| connector.power < 0.0);
+
+        // Charging is suspended by the EVSE while the power is limited to zero.
+        let notified = if state_machine::set_power(*evse_index, *connector_index, power) {
+            transactions::charging_state_changed(*evse_index, *connector_index)
+        } else if discharging != storage::get_connector(*evse_index, *connector_index).is_some_and(|connector| connector.power < 0.0) {
+            // Direction of the energy flow has changed.
+            transactions::charging_rate_changed(*evse_index, *connector_index)
+        } else {
+            Ok(())
+        };
+
+        // Limits of other EVSE are applied even if the transaction of this one is broken.
+        if let Err(e) = notified {
+            println!("Transaction of EVSE {} couldn't be updated ({})", evse_index + 1, e);
+        }
+
+        if verbose {
+            println!("Charging power of EVSE {} is {} W.", evse_index + 1, power);
+        }
+    }
+}
+
+/// Remove profiles which belong to a finished transaction.
+pub fn remove_transaction_profiles(transaction_id: &str) -> Result<(), EmulatorError> {
+    for (id, profile) in storage::get_charging_profiles() {
+        if parse(&profile)?["transactionId"] == transaction_id {
+            storage::delete_charging_profile(id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the power limit (W) which applies to the EVSE at the moment.
+///
+/// Tx profiles take precedence over Tx default profiles, profiles with higher stack level take precedence
+/// over profiles with lower stack level and the charging station max profile bounds the result.
+pub fn active_limit(evse_index: usize) -> Option<f64> {
+    limit_at(evse_index + 1, clock::now())
+}
+
+/// Get the power limit (W) which applies to the EVSE at the given moment.
+///
+/// EVSE id 0 refers to the whole charging station, so only station wide profiles are taken into account.
+pub fn limit_at(evse_id: usize, now: DateTime<Utc>) -> Option<f64> {
+    limit_since(evse_id, now, now)
+}
+
+/// Get the power limit (W) at the given moment of relative profiles without transaction which run from `origin`.
+fn limit_since(evse_id: usize, now: DateTime<Utc>, origin: DateTime<Utc>) -> Option<f64> {
+    let mut tx_limit: Option<(u64, f64)> = None;
+    let mut tx_default_limit: Option<(u64, f64)> = None;
+    let mut max_limit: Option<(u64, f64)> = None;
+    let mut external_limit: Option<f64> = None;
+
+    // Profile which can't be parsed doesn't limit the power.
+    for profile in storage::get_charging_profiles().into_iter().filter_map(|(_, profile)| parse(&profile).ok()) {
+        let profile_evse_id = profile["evseId"].as_usize().unwrap_or(0);
+
+        if profile_evse_id != 0 && profile_evse_id != evse_id {
+            continue;
+        }
+
+        // Tx profiles are bound to transactions which run on EVSEs.
+        if evse_id == 0 && profile["chargingProfilePurpose"] == "TxProfile" {
+            continue;
+        }
+
+        let limit = match current_limit(&profile, now, origin) {
+            Some(res) => res,
+            None => continue,
+        };
+
+        let stack_level = profile["stackLevel"].as_u64().unwrap_or(0);
+
+        let slot = match profile["chargingProfilePurpose"].as_str() {
+            Some("TxProfile") => &mut tx_limit,
+            Some("TxDefaultProfile") => &mut tx_default_limit,
+            Some("ChargingStationMaxProfile") => &mut max_limit,
+            Some("ChargingStationExternalConstraints") => {
+                external_limit = Some(external_limit.map_or(limit, |res| res.min(limit)));
+                continue;
+            },
+            _ => continue,
+        };
+
+        // EVSE specific profile overrides the station wide one with the same stack level.
+        let replace = match slot {
+            Some((level, _)) => stack_level > *level || (stack_level == *level && profile_evse_id != 0),
+            None => true,
+        };
+
+        if replace {
+            *slot = Some((stack_level, limit));
+        }
+    }
+
+    let mut limit = tx_limit.or(tx_default_limit).map(|(_, limit)| limit);
+
+    for bound in [max_limit.map(|(_, limit)| limit), external_limit].iter().flatten() {
+        limit = Some(limit.map_or(*bound, |res| res.min(*bound)));
+    }
+
+    limit
+}
+
+/// Get the power (W) which EVSE delivers while charging.
+pub fn charging_power(evse_index: usize) -> f64 {
+    charging_limit(evse_index).0
+}
+
+/// Get the power (W) to which the EVSE is limited together with the source of the limit.
+///
+/// Limits imposed by external systems bound the limit of charging profiles (`CSO`), overheated EVSE derates
+/// the power (`Other`). Source is `None` if only the capability of the EVSE limits the power. Negative limits
+/// request discharging of the EV if `V2G_ENABLED` is set.
+pub fn charging_limit(evse_index: usize) -> (f64, Option<String>) {
+    let max_power = topology::max_power(evse_index);
+
+    let mut limit = match (active_limit(evse_index), external_limits::limit(evse_index)) {
+        (Some(profile_limit), Some((external_limit, source))) if external_limit < profile_limit => (external_limit, Some(source)),
+        (Some(profile_limit), _) => (profile_limit, Some(String::from("CSO"))),
+        (None, Some((external_limit, source))) => (external_limit, Some(source)),
+        (None, None) => (max_power, None),
+    };
+
+    if limit.0 >= max_power {
+        limit = (max_power, None);
+    }
+
+    if let Some(derated_power) = temperature::derated_power(evse_index).filter(|derated_power| *derated_power < limit.0) {
+        limit = (derated_power, Some(String::from("Other")));
+    }
+
+    let min_limit = if is_v2g_enabled() { -max_power } else { 0.0 };
+
+    (limit.0.max(min_limit), limit.1)
+}
+
+/// Check if EVSE are allowed to discharge EVs into the grid.
+fn is_v2g_enabled() -> bool {
+    config::var("V2G_ENABLED").is_ok_and(|var| var == "true")
+}
+
+/// Compose all stored charging profiles into a single schedule for the EVSE.
+///
+/// Returns periods of the composite schedule as pairs of the period start (seconds from `start`) and the limit (W).
+/// Moments without any applicable profile are limited by the capacity of the EVSE.
+pub fn composite_schedule(evse_id: usize, start: DateTime<Utc>, duration: i64) -> Vec<(i64, f64)> {
+    let end = start + chrono::Duration::seconds(duration);
+    let capacity = if evse_id == 0 { (0..storage::evse_count()).map(topology::max_power).sum() } else { topology::max_power(evse_id - 1) };
+
+    // Collect moments at which any of the profiles may change its limit.
+    let mut moments = vec![start];
+
+    for profile in storage::get_charging_profiles().into_iter().filter_map(|(_, profile)| parse(&profile).ok()) {
+        moments.append(&mut breakpoints(&profile, start, end));
+    }
+
+    moments.retain(|moment| *moment >= start && *moment < end);
+    moments.sort();
+    moments.dedup();
+
+    let mut periods: Vec<(i64, f64)> = Vec::new();
+
+    for moment in moments {
+        // Relative profiles without transaction run from the start of the composite schedule.
+        let limit = match limit_since(evse_id, moment, start) {
+            Some(res) => res.clamp(0.0, capacity),
+            None => capacity,
+        };
+
+        // Merge periods with equal limits.
+        if periods.last().map(|(_, last_limit)| *last_limit) != Some(limit) {
+            periods.push(((moment - start).num_seconds(), limit));
+        }
+    }
+
+    periods
+}
+
+/// Get moments within the window at which the limit of the profile may change.
+fn breakpoints(profile: &JsonValue, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut moments = Vec::new();
+
+    moments.extend(parse_date(&profile["validFrom"]));
+    moments.extend(parse_date(&profile["validTo"]));
+
+    let mut start = match schedule_start(profile, from, from) {
+        Some(res) => res,
+        None => return moments,
+    };
+
+    loop {
+        moments.push(start);
+
+        for schedule in schedules(profile) {
+            for i in 0..schedule["chargingSchedulePeriod"].len() {
+                let start_period = schedule["chargingSchedulePeriod"][i]["startPeriod"].as_i64().unwrap_or(0);
+
+                moments.push(start + chrono::Duration::seconds(start_period));
+            }
+
+            if let Some(duration) = schedule["duration"].as_i64() {
+                moments.push(start + chrono::Duration::seconds(duration));
+            }
+        }
+
+        // Only recurring profiles have more than one run within the window.
+        let next_start = match profile["chargingProfileKind"].as_str() {
+            Some("Recurring") => {
+                let period = match profile["recurrencyKind"].as_str() {
+                    Some("Weekly") => 7 * 24 * 3600,
+                    _ => 24 * 3600,
+                };
+
+                start + chrono::Duration::seconds(period)
+            },
+            _ => break,
+        };
+
+        if next_start >= to {
+            break;
+        }
+
+        start = next_start;
+    }
+
+    moments
+}
+
+/// Convert limit in watts to the given charging rate unit.
+pub fn from_watts(limit: f64, charging_rate_unit: &str) -> f64 {
+    if charging_rate_unit == "A" {
+        limit / (NOMINAL_VOLTAGE * DEFAULT_NUMBER_PHASES)
+    } else {
+        limit
+    }
+}
+
+/// Get the limit (W) of the profile period which is active at the given moment.
+///
+/// Relative profile which isn't bound to a transaction runs from `origin`.
+pub fn current_limit(profile: &JsonValue, now: DateTime<Utc>, origin: DateTime<Utc>) -> Option<f64> {
+    if let Some(valid_from) = parse_date(&profile["validFrom"]) {
+        if now < valid_from {
+            return None;
+        }
+    }
+
+    if let Some(valid_to) = parse_date(&profile["validTo"]) {
+        if now >= valid_to {
+            return None;
+        }
+    }
+
+    let start = schedule_start(profile, now, origin)?;
+
+    for schedule in schedules(profile) {
+        let elapsed = (now - start).num_seconds();
+
+        if elapsed < 0 {
+            continue;
+        }
+
+        if let Some(duration) = schedule["duration"].as_i64() {
+            if elapsed >= duration {
+                continue;
+            }
+        }
+
+        let mut limit: Option<f64> = None;
+
+        // Periods are ordered by their start.
+        for i in 0..schedule["chargingSchedulePeriod"].len() {
+            let period = &schedule["chargingSchedulePeriod"][i];
+
+            if period["startPeriod"].as_i64().unwrap_or(0) <= elapsed {
+                limit = period["limit"].as_f64().map(|res| to_watts(res, &schedule["chargingRateUnit"], &period["numberPhases"]));
+            }
+        }
+
+        if limit.is_some() {
+            return limit;
+        }
+    }
+
+    None
+}
+
+/// Get the charging schedules of the profile.
+pub fn schedules(profile: &JsonValue) -> Vec<&JsonValue> {
+    let charging_schedule = &profile["chargingSchedule"];
+
+    if charging_schedule.is_array() {
+        charging_schedule.members().collect()
+    } else if charging_schedule.is_object() {
+        vec![charging_schedule]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Get the moment when the current run of the profile schedule started.
+///
+/// Relative profile which isn't bound to a transaction starts at `origin`.
+pub fn schedule_start(profile: &JsonValue, now: DateTime<Utc>, origin: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let start_schedule = schedules(profile).first().and_then(|schedule| parse_date(&schedule["startSchedule"]));
+
+    match profile["chargingProfileKind"].as_str() {
+        Some("Absolute") => Some(start_schedule.or_else(|| parse_date(&profile["validFrom"])).unwrap_or(now)),
+        Some("Recurring") => {
+            let period = match profile["recurrencyKind"].as_str() {
+                Some("Weekly") => 7 * 24 * 3600,
+                _ => 24 * 3600,
+            };
+            let start = start_schedule?;
+            let elapsed = (now - start).num_seconds();
+
+            if elapsed < 0 {
+                return Some(start);
+            }
+
+            Some(start + chrono::Duration::seconds(elapsed - elapsed % period))
+        },
+        Some("Relative") => {
+            // Relative schedules start together with the transaction.
+            let evse_id = profile["evseId"].as_usize().unwrap_or(0);
+
+            let transaction = match get_transaction(&profile["transactionId"].to_string()).ok().flatten() {
+                Some(res) => Some(res),
+                None => storage::get_transactions().into_iter()
+                    .filter_map(|(_, transaction)| parse(&transaction).ok())
+                    .find(|transaction| evse_id == 0 || transaction["evseId"].as_usize() == Some(evse_id)),
+            };
+
+            match transaction {
+                Some(transaction) => parse_date(&transaction["startTime"]),
+                None => Some(origin),
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Convert limit to watts.
+fn to_watts(limit: f64, charging_rate_unit: &JsonValue, number_phases: &JsonValue) -> f64 {
+    if charging_rate_unit == "A" {
+        limit * NOMINAL_VOLTAGE * number_phases.as_f64().unwrap_or(DEFAULT_NUMBER_PHASES)
+    } else {
+        limit
+    }
+}
+
+fn get_transaction(transaction_id: &str) -> Result<Option<JsonValue>, EmulatorError> {
+    let transaction = storage::get_transaction(transaction_id);
+
+    if transaction.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(parse(&transaction)?))
+}
+
+fn parse_date(value: &JsonValue) -> Option<DateTime<Utc>> {
+    match value.as_str() {
+        Some(res) => DateTime::parse_from_rfc3339(res).ok().map(|date| date.with_timezone(&Utc)),
+        None => None,
+    }
+}
+
+fn parse(value: &str) -> Result<JsonValue, EmulatorError> {
+    Ok(json::parse(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    // Profile is stored in the state of the test thread, so other tests don't see it.
+    #[test]
+    fn composite_schedule_follows_relative_profile_without_transaction() {
+        topology::load();
+
+        let profile = object!{
+            "id" => 764,
+            "stackLevel" => 0,
+            "evseId" => 1,
+            "chargingProfilePurpose" => "TxDefaultProfile",
+            "chargingProfileKind" => "Relative",
+            "chargingSchedule" => array![object!{
+                "chargingRateUnit" => "W",
+                "chargingSchedulePeriod" => array![
+                    object!{ "startPeriod" => 0, "limit" => 11000.0 },
+                    object!{ "startPeriod" => 1800, "limit" => 7000.0 }
+                ],
+            }],
+        };
+
+        storage::set_charging_profile(764, profile.dump());
+
+        let schedule = composite_schedule(1, date("2024-01-01T12:00:00Z"), 3600);
+
+        assert_eq!(schedule, vec![(0, 11000.0), (1800, 7000.0)]);
+    }
+
+    #[test]
+    fn current_limit_converts_amperes_and_respects_validity() {
+        let profile = object!{
+            "chargingProfileKind" => "Absolute",
+            "validTo" => "2024-01-01T13:00:00Z",
+            "chargingSchedule" => object!{
+                "startSchedule" => "2024-01-01T12:00:00Z",
+                "chargingRateUnit" => "A",
+                "chargingSchedulePeriod" => array![
+                    object!{ "startPeriod" => 0, "limit" => 16.0, "numberPhases" => 1 },
+                    object!{ "startPeriod" => 600, "limit" => 10.0 }
+                ],
+            },
+        };
+        let origin = date("2024-01-01T12:00:00Z");
+
+        assert_eq!(current_limit(&profile, date("2024-01-01T11:59:59Z"), origin), None);
+        assert_eq!(current_limit(&profile, date("2024-01-01T12:05:00Z"), origin), Some(16.0 * NOMINAL_VOLTAGE));
+        assert_eq!(current_limit(&profile, date("2024-01-01T12:10:00Z"), origin), Some(10.0 * NOMINAL_VOLTAGE * DEFAULT_NUMBER_PHASES));
+        assert_eq!(current_limit(&profile, date("2024-01-01T13:00:00Z"), origin), None);
+    }
+
+    #[test]
+    fn recurring_schedule_starts_with_the_current_run() {
+        let profile = object!{
+            "chargingProfileKind" => "Recurring",
+            "recurrencyKind" => "Daily",
+            "chargingSchedule" => object!{ "startSchedule" => "2024-01-01T08:00:00Z" },
+        };
+        let now = date("2024-01-03T07:00:00Z");
+
+        assert_eq!(schedule_start(&profile, now, now), Some(date("2024-01-02T08:00:00Z")));
+        assert_eq!(schedule_start(&profile, date("2023-12-31T00:00:00Z"), now), Some(date("2024-01-01T08:00:00Z")));
+    }
+}
@@ -1,16 +1,46 @@
-use std::env;
-
-use url;
-use ws::util::Token;
-use ws::{Handler, Sender, Handshake, Result, Message, Request, Error, ErrorKind, CloseCode};
-use uuid::Uuid;
+use std::thread;
+use std::time::Duration;
+
+use openssl::base64;
+use openssl::pkey::PKey;
+use openssl::ssl::{SslConnector, SslMethod, SslStream, SslVerifyMode};
+use openssl::x509::X509;
+use ws::util::{Token, Timeout, TcpStream};
+use ws::{Handler, Sender, Handshake, Result, Message, Request, Error, ErrorKind, CloseCode, Frame, OpCode};
 use chrono::prelude::*;
 use json::JsonValue;
 
+use crate::config;
 use crate::requests;
 use crate::responses;
 use crate::components;
 use crate::storage;
+use crate::authorization;
+use crate::reservations;
+use crate::charging_profiles;
+use crate::display_messages;
+use crate::data_transfer;
+use crate::firmware;
+use crate::publish_firmware;
+use crate::logs;
+use crate::customer_information;
+use crate::monitoring;
+use crate::events;
+use crate::certificates;
+use crate::plug_and_charge;
+use crate::security_events;
+use crate::meter;
+use crate::retries;
+use crate::clock;
+use crate::transactions;
+use crate::state_machine;
+use crate::state_machine::Event;
+use crate::connection;
+use crate::drivers;
+use crate::ids;
+use crate::schemas;
+use crate::errors::EmulatorError;
+use crate::journal;
 
 /// This macro allows to break from a code block outside of a loop.
 macro_rules! block {
@@ -19,237 +49,743 @@ macro_rules! block {
     };
 }
 
-static mut HEARTBEAT_INTERVAL: u64 = 0;
-
 // Timeout events.
 const HEARTBEAT: Token = Token(1);
 const QUEUE_FETCH: Token = Token(2);
+const RESERVATION_EXPIRY: Token = Token(3);
+const MONITORING_CHECK: Token = Token(4);
+const METER_SAMPLE: Token = Token(5);
+const BOOT_RETRY: Token = Token(6);
+const NETWORK_PRIORITY_CHECK: Token = Token(7);
+const PING: Token = Token(8);
+const NETWORK_PRIORITY_PROBED: Token = Token(9);
+// OCPP protocols which the station implements in order of preference.
+const SUPPORTED_PROTOCOLS: [&str; 2] = ["ocpp2.0.1", "ocpp2.0"];
 // OCPP constants.
 const CALL: u8 = 2;
 const CALLRESULT: u8 = 3;
 const CALLERROR: u8 = 4;
 // Message queue constants.
-const QUEUE_FETCH_INTERVAL: u64 = 50;
-const QUEUE_MESSAGE_EXPIRATION: u64 = 10;
+const DEFAULT_MESSAGE_TIMEOUT: u64 = 10;
+// Interval between checks of monitored variables.
+const MONITORING_CHECK_INTERVAL: u64 = 1000;
+// Interval between samples of the energy meter (ms).
+const METER_SAMPLE_INTERVAL: u64 = 1000;
+// Interval between attempts to return to a network profile with higher priority (ms).
+const NETWORK_PRIORITY_CHECK_INTERVAL: u64 = 60000;
+// Interval before BootNotification is sent again if CSMS doesn't return it (s).
+const DEFAULT_BOOT_RETRY_INTERVAL: u64 = 30;
+// Upper bound of the retry interval after rejected BootNotification requests (s).
+const MAX_BOOT_RETRY_INTERVAL: u64 = 3600;
+// Requests which CSMS may send while the registration is pending.
+const PENDING_ALLOWED_ACTIONS: [&str; 4] = ["GetVariables", "SetVariables", "GetBaseReport", "TriggerMessage"];
+// Requests which CSMS may send according to OCPP 2.0.1.
+const CSMS_ACTIONS: &[&str] = &[
+    "CancelReservation", "CertificateSigned", "ChangeAvailability", "ClearCache", "ClearChargingProfile",
+    "ClearDisplayMessage", "ClearVariableMonitoring", "CostUpdated", "CustomerInformation", "DataTransfer",
+    "DeleteCertificate", "GetBaseReport", "GetChargingProfiles", "GetCompositeSchedule", "GetDisplayMessages",
+    "GetInstalledCertificateIds", "GetLocalListVersion", "GetLog", "GetMonitoringReport", "GetReport",
+    "GetTransactionStatus", "GetVariables", "InstallCertificate", "PublishFirmware", "RequestStartTransaction",
+    "RequestStopTransaction", "ReserveNow", "Reset", "SendLocalList", "SetChargingProfile",
+    "SetDisplayMessage", "SetMonitoringBase", "SetMonitoringLevel", "SetNetworkProfile", "SetVariableMonitoring",
+    "SetVariables", "TriggerMessage", "UnlockConnector", "UnpublishFirmware", "UpdateFirmware",
+];
+// Number of days before expiration when the charging station certificate is renewed.
+const CERTIFICATE_RENEWAL_DAYS: u32 = 30;
+// Allowed length of the basic authentication password.
+const BASIC_AUTH_PASSWORD_MIN_LENGTH: usize = 16;
+const BASIC_AUTH_PASSWORD_MAX_LENGTH: usize = 40;
 
 // Websocket Handler struct.
 pub struct Client {
     pub out: Sender,
+    pub heartbeat_timeout: Option<Timeout>,
+    pub queue_timeout: Option<Timeout>,
+    pub ping_timeout: Option<Timeout>,
+    // Interval (ms) between Heartbeat requests, 0 until CSMS sets it.
+    pub heartbeat_interval: u64,
+    // Whether a WebSocket ping is sent and its pong is not received yet.
+    pub awaiting_pong: bool,
 }
 
-/// We implement the Handler trait for Client so that we can get more
-/// fine-grained control of the connection.
-impl Handler for Client {
+/// Keep the simulation running while the station is offline.
+///
+/// Energy meter is sampled and transactions go on for the given time (ms), their messages are queued until
+/// the station reconnects.
+pub fn run_offline(duration: u64) {
+    let mut elapsed: u64 = 0;
 
-    /// Add protocol to initial handshake request.
-    fn build_request(&mut self, url: &url::Url) -> Result<Request> {
-        let mut req = Request::from_url(url).unwrap();
-        req.add_protocol("ocpp2.0");
-        Ok(req)
+    while elapsed < duration && !storage::is_stop_requested() {
+        thread::sleep(Duration::from_millis(METER_SAMPLE_INTERVAL));
+
+        tick();
+
+        elapsed += METER_SAMPLE_INTERVAL;
     }
+}
 
-    /// Called when the WebSocket handshake is successful and the connection is open for sending
-    /// and receiving messages.
-    ///
-    /// Configures interval between fetches in the message queue.
-    /// Sends BootNotification message to the message queue.
-    fn on_open(&mut self, _: Handshake) -> Result<()> {
-        // Start queue worker.
-        self.out.timeout(QUEUE_FETCH_INTERVAL, QUEUE_FETCH)?;
-
-        // Get model from environment.
-        let model: String = match env::var("MODEL") {
-            Ok(var) => if var == "" { "Model".to_string() } else { var },
-            _ => "Model".to_string(),
-        };
+/// Sample the energy meter and let simulated transactions and drivers go on.
+///
+/// Errors are logged because nothing waits for the result of a timer, the next parts of the simulation go on.
+fn tick() {
+    let results = vec![meter::tick(), transactions::cancel_timed_out(), transactions::stop_at_targets(), drivers::tick()];
 
-        // Get vendor name from environment.
-        let vendor_name: String = match env::var("VENDOR_NAME") {
-            Ok(var) => if var == "" { "Vendor name".to_string() } else { var },
-            _ => "Vendor name".to_string(),
-        };
+    for e in results.into_iter().filter_map(|result| result.err()) {
+        println!("Couldn't simulate the station ({})", e);
+    }
+}
 
-        // Get serial number from environment.
-        let serial_number: Option<String> = match env::var("SERIAL_NUMBER") {
-            Ok(data) => Some(data),
-            _ => None,
+/// Queue a BootNotification request.
+///
+/// Charging station details are taken from the device model.
+pub fn queue_boot_notification(reason: &str) {
+    let identity = |component_name: &str, variable_name: &str| components::get_variable(component_name, variable_name).1.filter(|value| !value.is_empty());
+
+    let model: String = identity("ChargingStation", "Model").unwrap_or_default();
+    let vendor_name: String = identity("ChargingStation", "VendorName").unwrap_or_default();
+    let serial_number: Option<String> = identity("ChargingStation", "SerialNumber");
+    let firmware_version: Option<String> = identity("ChargingStation", "FirmwareVersion");
+
+    // Modem is reported if any of its identifiers is configured.
+    let iccid = identity("Modem", "ICCID");
+    let imsi = identity("Modem", "IMSI");
+
+    let modem: Option<JsonValue> = if iccid.is_some() || imsi.is_some() {
+        let mut modem = JsonValue::new_object();
+
+        if let Some(data) = iccid {
+            modem["iccid"] = data.into();
+        }
+
+        if let Some(data) = imsi {
+            modem["imsi"] = data.into();
+        }
+
+        Some(modem)
+    } else {
+        None
+    };
+
+    // Send BootNotification request.
+
+    storage::set_boot_reason(reason.to_string());
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::boot_notification(msg_id, reason, &model, &vendor_name, serial_number, firmware_version, modem);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+impl Client {
+    /// Send CALLERROR for a missing or invalid field of a CALL payload.
+    #[allow(clippy::result_large_err)]
+    fn send_field_error(&mut self, msg_id: &str, payload: &JsonValue, field: &str) -> Result<()> {
+        let response_msg: String = if payload[field].is_null() {
+            responses::call_error(msg_id, "OccurrenceConstraintViolation", &format!("Required field {} is missing.", field))
+        } else {
+            responses::call_error(msg_id, "TypeConstraintViolation", &format!("Field {} has an invalid value.", field))
         };
 
-        // Send BootNotification request.
+        self.send(response_msg)
+    }
+
+    /// Send queued requests after an event and schedule a wakeup for requests which become due later.
+    ///
+    /// Wakeup is scheduled only while a request waits for its response or for its retry, so the idle station
+    /// isn't woken up.
+    #[allow(clippy::result_large_err)]
+    fn drain_queue(&mut self) -> Result<()> {
+        // Requests which failed earlier are queued again when their retry interval has passed.
+        storage::queue_due_messages(clock::local_now().timestamp_millis());
+
+        self.send_next_message()?;
+
+        if let Some(timeout) = self.queue_timeout.take() {
+            self.out.cancel(timeout)?;
+        }
+
+        let mut wakeups: Vec<u64> = Vec::new();
 
-        let msg_id: &str = &Uuid::new_v4().to_string();
-        let msg = requests::boot_notification(msg_id, "PowerUp", &model, &vendor_name, serial_number);
+        // Unanswered request expires after the message timeout.
+        if let Some(timestamp) = storage::get_last_sent_message().timestamp {
+            wakeups.push(((timestamp + message_timeout() + 1) * 1000).saturating_sub(Utc::now().timestamp_millis() as u64));
+        }
 
-        storage::set_message(msg_id.to_string(), msg.to_owned());
+        if let Some(moment) = storage::next_delayed_message() {
+            wakeups.push(clock::real_duration((moment - clock::local_now().timestamp_millis()).max(0) as u64));
+        }
 
-        storage::queue_add(msg);
+        if let Some(delay) = wakeups.into_iter().min() {
+            self.out.timeout(delay.max(1), QUEUE_FETCH)?;
+        }
 
         Ok(())
     }
 
-    /// Called on incoming messages.
+    /// Send the next queued request unless a sent request is still waiting for its response.
     ///
-    /// Handles requests and responses from the Charging Station Management System.
-    fn on_message(&mut self, msg: Message) -> Result<()> {
-        let parsed_msg = match json::parse(msg.as_text()?) {
-            Ok(result) => result,
-            Err(e) => panic!("Error during parsing: {:?}", e),
+    /// Request which isn't answered within `OCPPCommCtrlr.MessageTimeout` is handed over to retries.
+    #[allow(clippy::result_large_err)]
+    fn send_next_message(&mut self) -> Result<()> {
+        let current_timestamp: u64 = Utc::now().timestamp() as u64;
+
+        let last_sent_msg = storage::get_last_sent_message();
+        // Check whether last sent message exists or not.
+        let last_sent_msg_exist: bool = last_sent_msg.id.is_some();
+        // Check whether last sent message has expired or not.
+        let last_sent_msg_expired: bool = match last_sent_msg.timestamp {
+            Some(timestamp) => timestamp + message_timeout() < current_timestamp,
+            None => true,
         };
 
-        let msg_type_id = match parsed_msg[0].as_u8() {
-            Some(res) => res,
-            None => panic!("Parsed message has no value."),
+        if last_sent_msg_exist && !last_sent_msg_expired {
+            return Ok(());
+        }
+
+        // Unanswered request is sent again later or dropped.
+        if let Some(id) = last_sent_msg.id {
+            storage::clear_last_sent_message(&id);
+
+            retries::handle_timeout(&id);
+        }
+
+        if storage::queue_size() == 0 {
+            return Ok(());
+        }
+
+        // Only BootNotification is sent until the station is accepted by CSMS.
+        let msg = if storage::get_registration_status() == "Accepted" {
+            storage::queue_pop()
+        } else {
+            storage::queue_remove_action("BootNotification").unwrap_or_default()
+        };
+
+        if msg.is_empty() {
+            return Ok(());
+        }
+
+        // Message which can't be parsed is dropped instead of blocking the queue.
+        let parsed_msg = match json::parse(&msg.to_owned()) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Queued message couldn't be parsed and is dropped ({})", EmulatorError::from(e));
+
+                return Ok(());
+            },
         };
 
         let msg_id: &str = &parsed_msg[1].to_string();
+        let msg_action: &str = &parsed_msg[2].to_string();
 
-        println!("Message ID: {}", msg_id);
+        if !schemas::check_sent(msg_action, "Request", &parsed_msg[3]) {
+            storage::release_message(msg_id);
+
+            return Ok(());
+        }
+
+        self.send(msg)?;
+
+        println!("{} ({}) was sent.", msg_action, msg_id);
+
+        storage::set_last_sent_message(msg_id.to_string(), current_timestamp);
+
+        Ok(())
+    }
+
+    /// Apply an OCPPCommCtrlr variable changed by CSMS to the timers of the connection.
+    #[allow(clippy::result_large_err)]
+    fn apply_comm_variable(&mut self, variable_name: &str) -> Result<()> {
+        match variable_name {
+            "HeartbeatInterval" => {
+                let interval = match comm_variable(variable_name).filter(|interval| *interval > 0) {
+                    Some(res) => res,
+                    None => return Ok(()),
+                };
+
+                if let Some(timeout) = self.heartbeat_timeout.take() {
+                    self.out.cancel(timeout)?;
+                }
+
+                self.heartbeat_interval = interval * 1000;
+
+                if storage::get_registration_status() == "Accepted" {
+                    self.out.timeout(clock::real_duration(self.heartbeat_interval), HEARTBEAT)?;
+                }
+            },
+            "WebSocketPingInterval" => {
+                if let Some(timeout) = self.ping_timeout.take() {
+                    self.out.cancel(timeout)?;
+                }
+
+                self.awaiting_pong = false;
+
+                if let Some(interval) = ping_interval() {
+                    self.out.timeout(interval, PING)?;
+                }
+            },
+            _ => (),
+        };
+
+        Ok(())
+    }
+
+    /// Send a message to CSMS and write it to the transcript.
+    #[allow(clippy::result_large_err)]
+    fn send(&mut self, mut msg: String) -> Result<()> {
+        if let Ok(parsed_msg) = json::parse(&msg) {
+            // Sent responses are validated against the schemas of their actions, requests are validated when they
+            // leave the queue. Invalid response which isn't sent is replaced with an error.
+            if parsed_msg[0] == CALLRESULT || parsed_msg[0] == CALLERROR {
+                if let Some(action) = storage::take_received_call(&parsed_msg[1].to_string()) {
+                    if parsed_msg[0] == CALLRESULT && !schemas::check_sent(&action, "Response", &parsed_msg[2]) {
+                        msg = responses::call_error(&parsed_msg[1].to_string(), "InternalError", &format!("{} response is invalid", action));
+                    }
+                }
+            }
+
+            // Responses are kept to answer duplicate requests.
+            if (parsed_msg[0] == CALLRESULT || parsed_msg[0] == CALLERROR) && parsed_msg[1] != "-1" {
+                storage::add_recent_response(parsed_msg[1].to_string(), msg.to_owned());
+            }
+        }
+
+        storage::transcript_add("Sent", &msg);
+        storage::set_last_send_time(clock::local_now().timestamp_millis());
+
+        self.out.send(msg)
+    }
+}
+
+/// Get the interval (ms) before BootNotification is sent again.
+///
+/// Interval from the response is used if it's given, otherwise `BOOT_RETRY_INTERVAL` (s) is used
+/// and doubled after each rejection when `backoff` is set.
+/// Random jitter of up to `BOOT_RETRY_JITTER` seconds is added to spread retries of many stations.
+fn boot_retry_interval(interval: Option<u64>, backoff: bool) -> u64 {
+    let default_interval: u64 = config::var("BOOT_RETRY_INTERVAL").ok().and_then(|var| var.parse().ok()).unwrap_or(DEFAULT_BOOT_RETRY_INTERVAL);
+
+    let interval: u64 = match interval.filter(|interval| *interval > 0) {
+        Some(res) => res,
+        None if backoff => default_interval.saturating_mul(1 << storage::get_boot_rejections().saturating_sub(1).min(16)).min(MAX_BOOT_RETRY_INTERVAL),
+        None => default_interval,
+    };
+
+    let jitter: u64 = config::var("BOOT_RETRY_JITTER").ok().and_then(|var| var.parse().ok()).unwrap_or(0);
+
+    let random: u64 = ids::random();
+
+    interval * 1000 + if jitter > 0 { random % (jitter * 1000) } else { 0 }
+}
+
+/// Get the interval (ms) between WebSocket pings from `OCPPCommCtrlr.WebSocketPingInterval`.
+///
+/// Returns `None` if pings are disabled.
+fn ping_interval() -> Option<u64> {
+    comm_variable("WebSocketPingInterval")
+        .filter(|interval| *interval > 0)
+        .map(|interval| interval * 1000)
+}
+
+/// Get the interval (ms) between Heartbeat requests from `OCPPCommCtrlr.HeartbeatInterval`.
+///
+/// Interval is kept in the device model, so it survives reconnects.
+pub fn heartbeat_interval() -> u64 {
+    comm_variable("HeartbeatInterval").unwrap_or(0) * 1000
+}
+
+/// Get the time (s) after which a request without response is considered lost from `OCPPCommCtrlr.MessageTimeout`.
+fn message_timeout() -> u64 {
+    comm_variable("MessageTimeout").unwrap_or(DEFAULT_MESSAGE_TIMEOUT)
+}
+
+/// Get the duration (s) of an outage after which status of every connector is reported from `OCPPCommCtrlr.OfflineThreshold`.
+fn offline_threshold() -> i64 {
+    comm_variable("OfflineThreshold").unwrap_or(0) as i64
+}
+
+fn comm_variable(variable_name: &str) -> Option<u64> {
+    components::get_variable("OCPPCommCtrlr", variable_name).1.and_then(|value| value.parse::<u64>().ok())
+}
+
+/// Get the OCPP protocols which are offered to CSMS in order of preference.
+///
+/// Protocols are configured with `OCPP_PROTOCOLS` or pinned with `OCPP_VERSION` (2.0 or 2.0.1),
+/// protocols which the station doesn't implement are ignored.
+fn offered_protocols() -> Vec<&'static str> {
+    // Version switch pins the protocol, e.g. for CSMS which implement only OCPP 2.0.1.
+    let configured = match config::var("OCPP_VERSION") {
+        Ok(var) if !var.is_empty() => format!("ocpp{}", var),
+        _ => config::var("OCPP_PROTOCOLS").unwrap_or_default(),
+    };
+
+    let protocols: Vec<&'static str> = configured.split(',')
+        .map(|protocol| protocol.trim())
+        .filter(|protocol| !protocol.is_empty())
+        .filter_map(|protocol| match SUPPORTED_PROTOCOLS.iter().find(|supported| **supported == protocol) {
+            Some(supported) => Some(*supported),
+            None => {
+                println!("OCPP protocol {} is not supported.", protocol);
+
+                None
+            },
+        })
+        .collect();
+
+    if protocols.is_empty() { SUPPORTED_PROTOCOLS.to_vec() } else { protocols }
+}
+
+/// Get the security profile which is used to connect to CSMS.
+pub fn security_profile() -> u8 {
+    match config::var("SECURITY_PROFILE") {
+        Ok(var) => var.parse().unwrap_or(1),
+        _ => 1,
+    }
+}
+
+/// Get the password which is used for basic authentication.
+///
+/// Password set by CSMS overrides `BASIC_AUTH_PASSWORD`.
+pub fn basic_auth_password() -> Option<String> {
+    if let Some(password) = storage::get_basic_auth_password() {
+        return Some(password);
+    }
+
+    match config::var("BASIC_AUTH_PASSWORD") {
+        Ok(var) => if var.is_empty() { None } else { Some(var) },
+        _ => None,
+    }
+}
+
+/// Queue a SignCertificate request with a newly generated CSR.
+pub fn queue_sign_certificate(certificate_type: &str) {
+    let csr = match certificates::generate_csr(certificate_type) {
+        Some(res) => res,
+        None => {
+            println!("Couldn't generate CSR for {}.", certificate_type);
+            return;
+        },
+    };
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::sign_certificate(msg_id, &csr, certificate_type);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+/// Queue a Heartbeat request.
+fn queue_heartbeat() {
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::heartbeat(msg_id);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+/// Change status of the connector and send StatusNotification with updated status.
+pub fn change_connector_status(evse_index: usize, connector_index: usize, status: &'static str) -> std::result::Result<(), EmulatorError> {
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::status_notification(msg_id, (evse_index + 1) as u8, (connector_index + 1) as u8, status)?;
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
 
+    storage::queue_add(msg);
+
+    storage::set_connector_status(evse_index, connector_index, status);
+
+    // Reservations of an EVSE which can't be used anymore are removed.
+    if status == "Faulted" || status == "Unavailable" {
+        match reservations::remove_for_evse(evse_index) {
+            Ok(removed) => for reservation_id in removed {
+                reservations::notify(reservation_id, "Removed");
+            },
+            Err(e) => println!("Reservations of EVSE {} couldn't be removed ({})", evse_index + 1, e),
+        }
+    }
+
+    // Notify CSMS about the changed availability state in the device model.
+    events::availability_changed(evse_index + 1, connector_index + 1, status)
+}
+
+impl Client {
+    /// Handle a request or a response of the Charging Station Management System.
+    fn handle_message(&mut self, msg: &Message, parsed_msg: &JsonValue, msg_id: &str, msg_type_id: u8) -> std::result::Result<(), EmulatorError> {
         match msg_type_id {
             CALL => block!({
+                if !parsed_msg[2].is_string() || !parsed_msg[3].is_object() {
+                    let response_msg: String = responses::call_error(msg_id, "ProtocolError", "CALL must contain an action and a payload object.");
+
+                    self.send(response_msg)?;
+
+                    break;
+                }
+
                 let action: &str = &parsed_msg[2].to_string();
                 let payload: &JsonValue = &parsed_msg[3];
 
                 println!("CALL Action: {}", action);
                 println!("CALL Payload: {}", payload);
 
+                // Request which was already handled is answered with the same response.
+                if let Some(response_msg) = storage::get_recent_response(msg_id) {
+                    println!("{} ({}) is a duplicate, previous response is sent again.", action, msg_id);
+
+                    self.send(response_msg)?;
+
+                    break;
+                }
+
+                // Rejected station doesn't respond to CSMS.
+                if storage::get_registration_status() == "Rejected" {
+                    println!("{} was ignored because the charging station is rejected.", action);
+
+                    break;
+                }
+
+                // Station which is not accepted by CSMS handles only the configuration requests.
+                if storage::get_registration_status() == "Pending" && !PENDING_ALLOWED_ACTIONS.contains(&action) {
+                    let response_msg: String = responses::call_error(msg_id, "SecurityError", "Registration of the charging station is pending.");
+
+                    self.send(response_msg)?;
+
+                    break;
+                }
+
+                // Request which violates the schema of the action isn't handled.
+                if let Err((error_code, description)) = schemas::validate(action, "Request", payload) {
+                    println!("{} is invalid: {}", action, description);
+
+                    self.send(responses::call_error(msg_id, error_code, &description))?;
+
+                    break;
+                }
+
+                storage::set_received_call(msg_id.to_string(), action.to_string());
+
                 match action {
                     "SetVariables" => {
                         // Send SetVariables response.
 
                         let set_variable_data_array = &payload["setVariableData"];
 
+                        if let Some(error_code) = components::check_message_limits(action, set_variable_data_array.len(), msg.as_text()?.len()) {
+                            self.send(responses::call_error(msg_id, error_code, "Request exceeds the message limits of the charging station."))?;
+
+                            break;
+                        }
+
                         let mut variables: JsonValue = JsonValue::new_array();
+                        // New basic authentication password which is used after reconnecting.
+                        let mut new_password: Option<String> = None;
+                        // Communication variables which are applied after the response.
+                        let mut comm_variables: Vec<String> = Vec::new();
 
                         for i in 0..set_variable_data_array.len() {
                             let set_variable_data = &set_variable_data_array[i];
-                            let component_name: &str = &set_variable_data["component"].to_string();
+                            let component_name: &str = &set_variable_data["component"]["name"].to_string();
                             let variable_name: &str = &set_variable_data["variable"]["name"].to_string();
 
+                            let attribute_type: &str = set_variable_data["attributeType"].as_str().unwrap_or("Actual");
+
                             let mut variable = object!{
-                                "component" => component_name,
-                                "variable" => object!{
-                                    "name" => variable_name,
-                                },
+                                "component" => set_variable_data["component"].clone(),
+                                "variable" => set_variable_data["variable"].clone(),
                             };
 
-                            match component_name {
-                                "AuthCtrlr" => {
-                                    match variable_name {
-                                        "AuthorizeRemoteStart" => variable["attributeStatus"] = "Rejected".into(),
-                                        _ => variable["attributeStatus"] = "UnknownVariable".into(),
+                            if !set_variable_data["attributeType"].is_null() {
+                                variable["attributeType"] = attribute_type.into();
+                            }
+
+                            match (component_name, variable_name) {
+                                ("SecurityCtrlr", "BasicAuthPassword") if attribute_type != "Actual" => variable["attributeStatus"] = "NotSupportedAttributeType".into(),
+                                ("SecurityCtrlr", "BasicAuthPassword") => {
+                                    let password = set_variable_data["attributeValue"].to_string();
+
+                                    if (BASIC_AUTH_PASSWORD_MIN_LENGTH..=BASIC_AUTH_PASSWORD_MAX_LENGTH).contains(&password.len()) {
+                                        variable["attributeStatus"] = "Accepted".into();
+
+                                        new_password = Some(password);
+                                    } else {
+                                        variable["attributeStatus"] = "Rejected".into();
+                                    }
+                                },
+                                _ => {
+                                    let attribute_value: &str = &set_variable_data["attributeValue"].to_string();
+
+                                    let attribute_status = components::set_component_variable(&set_variable_data["component"], &set_variable_data["variable"], attribute_type, attribute_value);
+
+                                    if component_name == "OCPPCommCtrlr" && attribute_status == "Accepted" {
+                                        comm_variables.push(variable_name.to_string());
                                     }
+
+                                    variable["attributeStatus"] = attribute_status.into();
                                 },
-                                _ => variable["attributeStatus"] = "UnknownComponent".into(),
                             };
 
-                            variables.push(variable).unwrap();
+                            variables.push(variable)?;
                         }
 
                         let response_msg: String = responses::set_variables(msg_id, variables);
 
-                        self.out.send(response_msg)?;
+                        self.send(response_msg)?;
+
+                        for variable_name in comm_variables {
+                            self.apply_comm_variable(&variable_name)?;
+                        }
+
+                        // Reconnect using the new password, the previous one is restored if CSMS doesn't accept it.
+                        if let Some(password) = new_password {
+                            println!("Reconnecting with the new basic authentication password.");
+
+                            storage::set_previous_basic_auth_password(basic_auth_password());
+                            storage::set_basic_auth_password(Some(password));
+                            storage::set_reconnect(true);
+
+                            security_events::notify("ReconfigurationOfSecurityParameters", Some("BasicAuthPassword"))?;
+
+                            self.out.close(CloseCode::Normal)?;
+                        }
                     },
                     "GetVariables" => {
                         // Send GetVariables response.
 
                         let get_variable_data_array = &payload["getVariableData"];
 
+                        if let Some(error_code) = components::check_message_limits(action, get_variable_data_array.len(), msg.as_text()?.len()) {
+                            self.send(responses::call_error(msg_id, error_code, "Request exceeds the message limits of the charging station."))?;
+
+                            break;
+                        }
+
                         let mut variables: JsonValue = JsonValue::new_array();
 
                         for i in 0..get_variable_data_array.len() {
                             let get_variable_data = &get_variable_data_array[i];
-                            let component_name: &str = &get_variable_data["component"].to_string();
-                            let variable_name: &str = &get_variable_data["variable"]["name"].to_string();
+                            let attribute_type: &str = get_variable_data["attributeType"].as_str().unwrap_or("Actual");
 
-                            let (attribute_status, attribute_value): (&str, Option<&str>) = components::get_variable(component_name, variable_name);
+                            let (attribute_status, attribute_value): (&str, Option<String>) = components::get_component_attribute(&get_variable_data["component"], &get_variable_data["variable"], attribute_type);
 
                             let mut variable = object!{
                                 "attributeStatus" => attribute_status,
-                                "component" => component_name,
-                                "variable" => object!{
-                                    "name" => variable_name,
-                                },
+                                "component" => get_variable_data["component"].clone(),
+                                "variable" => get_variable_data["variable"].clone(),
                             };
 
-                            match attribute_value {
-                                Some(data) => variable["attributeValue"] = data.into(),
-                                _ => (),
-                            };
+                            if !get_variable_data["attributeType"].is_null() {
+                                variable["attributeType"] = attribute_type.into();
+                            }
+
+                            if let Some(data) = attribute_value {
+                                variable["attributeValue"] = data.into();
+                            }
 
-                            variables.push(variable).unwrap();
+                            variables.push(variable)?;
                         }
 
                         let response_msg: String = responses::get_variables(msg_id, variables);
 
-                        self.out.send(response_msg)?;
+                        self.send(response_msg)?;
                     }
                     "RequestStartTransaction" => {
-                        let remote_start_id: u64 = match payload["remoteStartId"].as_number() {
-                            Some(res) => (res.as_fixed_point_i64(0).unwrap_or(0) as u64),
-                            None => panic!("Parsed message has no value."),
+                        // Negative id is rejected as an invalid value instead of wrapping around.
+                        let remote_start_id: u64 = match payload["remoteStartId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "remoteStartId")?;
+
+                                break;
+                            },
                         };
 
-                        // Generate transaction id.
-                        let transaction_id: &str = &Uuid::new_v4().to_string();
-
-                        // Check connector status.
-                        let evse_id: usize = match payload["evseId"].as_number() {
-                            Some(res) => res.as_fixed_point_i64(0).unwrap_or(0) as usize,
-                            _ => panic!("Parsed EVSE ID has no value."),
+                        // Station selects the first available EVSE if CSMS doesn't specify it.
+                        let evse_id: usize = match payload["evseId"].as_usize() {
+                            Some(res) => res,
+                            None if payload["evseId"].is_null() => {
+                                (0..storage::evse_count())
+                                    .find(|evse_index| transactions::free_connector(*evse_index).and_then(|connector_index| storage::get_connector(*evse_index, connector_index)).is_some_and(|connector| connector.status != "Reserved"))
+                                    .map_or(0, |evse_index| evse_index + 1)
+                            },
+                            None => {
+                                self.send_field_error(msg_id, payload, "evseId")?;
+
+                                break;
+                            },
                         };
 
-                        // FIXME Magic number (connector index).
-                        let connector = storage::get_connector(evse_id - 1, 0);
-
-                        let mut response_status = "Accepted";
-
-                        if connector.status != "Available" {
-                            response_status = "Rejected";
-                        }
-
-                        // Send RequestStartTransaction response.
+                        if evse_id == 0 || evse_id > storage::evse_count() {
+                            // Send RequestStartTransaction response.
 
-                        let request_start_transaction_msg = responses::request_start_transaction(msg_id, remote_start_id, response_status);
+                            let request_start_transaction_msg = responses::request_start_transaction(msg_id, remote_start_id, "Rejected", None);
 
-                        self.out.send(request_start_transaction_msg)?;
+                            self.send(request_start_transaction_msg)?;
 
-                        if response_status == "Rejected" {
                             break;
                         }
 
-                        // Set EVSE status to "Occupied" and send StatusNotification with updated status.
+                        let evse_index = evse_id - 1;
+                        // Connector of the EVSE which is used for the transaction.
+                        let connector_index = transactions::free_connector(evse_index);
 
-                        let connector_status = "Occupied";
-                        let status_notification_msg_id: &str = &Uuid::new_v4().to_string();
-                        let status_notification_msg = requests::status_notification(status_notification_msg_id, 1, 1, connector_status);
+                        let id_token: &str = &payload["idToken"]["idToken"].to_string();
 
-                        storage::set_message(status_notification_msg_id.to_string(), status_notification_msg.to_owned());
+                        let mut response_status = "Accepted";
+                        let mut reservation_id: Option<u64> = None;
 
-                        storage::queue_add(status_notification_msg);
+                        // Check id token against the local authorization list.
+                        if let Some(status) = authorization::local_list_status(id_token)? {
+                            if status != "Accepted" {
+                                response_status = "Rejected";
+                            }
+                        }
 
-                        storage::set_connector_status(0, 0, connector_status);
+                        // Check connector status.
+                        if response_status == "Accepted" {
+                            match connector_index {
+                                Some(connector_index) if storage::get_connector(evse_index, connector_index).is_some_and(|connector| connector.status == "Reserved") => {
+                                    // Reserved connector can be used only with the id token of the reservation.
+                                    reservation_id = reservations::consume(evse_index, id_token)?;
+
+                                    if reservation_id.is_none() {
+                                        response_status = "Rejected";
+                                    }
+                                },
+                                Some(_) => (),
+                                None => response_status = "Rejected",
+                            }
+                        }
 
-                        // Send "Started" TransactionEvent request to notify CSMS about the started transaction.
+                        // Charging profile of the request must be applicable to the new transaction.
+                        if !payload["chargingProfile"].is_null() && !charging_profiles::is_valid_start_profile(&payload["chargingProfile"]) {
+                            response_status = "Rejected";
+                        }
 
-                        let transaction_event_started_msg_id: &str = &Uuid::new_v4().to_string();
-                        let transaction_event_started_msg = requests::transaction_event(transaction_event_started_msg_id, transaction_id, "Started", "RemoteStart", None, Some(remote_start_id), None);
+                        // Send RequestStartTransaction response.
 
-                        storage::set_message(transaction_event_started_msg_id.to_string(), transaction_event_started_msg.to_owned());
+                        // Transaction which was started by plugging in the cable is reported to CSMS.
+                        let started_transaction_id = connector_index.and_then(|connector_index| transactions::find(evse_index, connector_index));
 
-                        storage::queue_add(transaction_event_started_msg);
+                        let request_start_transaction_msg = responses::request_start_transaction(msg_id, remote_start_id, response_status, started_transaction_id);
 
-                        // Save transaction.
-                        storage::set_transaction(transaction_id.to_string(), payload.dump());
+                        self.send(request_start_transaction_msg)?;
 
-                        // Send "Updated" TransactionEvent request to notify CSMS about the plugged in cable.
+                        let connector_index = match connector_index {
+                            Some(res) if response_status == "Accepted" => res,
+                            _ => break,
+                        };
 
-                        let transaction_event_updated_msg_id: &str = &Uuid::new_v4().to_string();
-                        let transaction_event_updated_msg = requests::transaction_event(transaction_event_updated_msg_id, transaction_id, "Updated", "CablePluggedIn", Some("Charging"), None, None);
+                        // Save transaction, it's started once it reaches its start point.
+                        let mut details = payload.clone();
+                        details.remove("evseId");
 
-                        storage::set_message(transaction_event_updated_msg_id.to_string(), transaction_event_updated_msg.to_owned());
+                        if let Some(reservation_id) = reservation_id {
+                            details["reservationId"] = reservation_id.into();
+                        }
 
-                        storage::queue_add(transaction_event_updated_msg);
+                        authorization::remote_start(evse_index, connector_index, details)?;
                     },
                     "RequestStopTransaction" => {
                         let transaction_id: &str = &payload["transactionId"].to_string();
@@ -265,94 +801,874 @@ impl Handler for Client {
 
                         let request_stop_transaction_msg = responses::request_stop_transaction(msg_id, response_status);
 
-                        self.out.send(request_stop_transaction_msg)?;
+                        self.send(request_stop_transaction_msg)?;
 
                         if response_status == "Rejected" {
                             break;
                         }
 
-                        // Send "Updated" TransactionEvent request to notify CSMS about remote stop command.
-
-                        let transaction_event_updated_msg_id: &str = &Uuid::new_v4().to_string();
-                        let transaction_event_updated_msg = requests::transaction_event(transaction_event_updated_msg_id, transaction_id, "Updated", "RemoteStop", None, None, None);
+                        // Stop energy transfer and end the transaction.
+                        transactions::stop(transaction_id, "RemoteStop", "Remote")?;
+                    },
+                    "SendLocalList" => {
+                        let version_number: u64 = match payload["versionNumber"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "versionNumber")?;
+
+                                break;
+                            },
+                        };
+                        let update_type: &str = &payload["updateType"].to_string();
 
-                        storage::set_message(transaction_event_updated_msg_id.to_string(), transaction_event_updated_msg.to_owned());
+                        let status = authorization::update_local_list(version_number, update_type, &payload["localAuthorizationList"]);
 
-                        storage::queue_add(transaction_event_updated_msg);
+                        // Send SendLocalList response.
 
-                        // Send "Ended" TransactionEvent request.
+                        let response_msg: String = responses::send_local_list(msg_id, status);
 
-                        let transaction_event_ended_msg_id: &str = &Uuid::new_v4().to_string();
-                        let transaction_event_ended_msg = requests::transaction_event(transaction_event_ended_msg_id, transaction_id, "Ended", "RemoteStop", None, None, Some("Remote"));
+                        self.send(response_msg)?;
+                    },
+                    "GetLocalListVersion" => {
+                        // Send GetLocalListVersion response.
 
-                        storage::set_message(transaction_event_ended_msg_id.to_string(), transaction_event_ended_msg.to_owned());
+                        let response_msg: String = responses::get_local_list_version(msg_id, storage::get_local_list_version());
 
-                        storage::queue_add(transaction_event_ended_msg);
+                        self.send(response_msg)?;
+                    },
+                    "ReserveNow" => {
+                        let reservation_id: u64 = match payload["id"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "id")?;
+
+                                break;
+                            },
+                        };
 
-                        // Delete transaction.
-                        storage::delete_transaction(transaction_id);
+                        let response_status = match reservations::reserve(reservation_id, payload) {
+                            Ok((evse_index, expires_in)) => {
+                                // Set status of EVSE connectors to "Reserved" and send StatusNotification with updated status.
+                                state_machine::handle_evse(evse_index, Event::Reserve);
 
-                        // Set EVSE status to "Available" and send StatusNotification with updated status.
+                                // Schedule reservation expiration.
+                                self.out.timeout(clock::real_duration(expires_in), RESERVATION_EXPIRY)?;
 
-                        let connector_status = "Available";
-                        let status_notification_msg_id: &str = &Uuid::new_v4().to_string();
-                        let status_notification_msg = requests::status_notification(status_notification_msg_id, 1, 1, connector_status);
+                                "Accepted"
+                            },
+                            Err(status) => status,
+                        };
 
-                        storage::set_message(status_notification_msg_id.to_string(), status_notification_msg.to_owned());
+                        // Send ReserveNow response.
 
-                        storage::queue_add(status_notification_msg);
+                        let response_msg: String = responses::reserve_now(msg_id, response_status);
 
-                        storage::set_connector_status(0, 0, connector_status);
+                        self.send(response_msg)?;
                     },
-                    _ => println!("No request handler for action: {}", action),
-                }
-            }),
-            CALLRESULT => block!({
-                let payload: &JsonValue = &parsed_msg[2];
+                    "ChangeAvailability" => {
+                        let event = match payload["operationalStatus"].as_str() {
+                            Some("Operative") => Event::Enable,
+                            Some("Inoperative") => Event::Disable,
+                            _ => {
+                                self.send_field_error(msg_id, payload, "operationalStatus")?;
+
+                                break;
+                            },
+                        };
 
-                let msg_from_map = storage::get_message(msg_id);
+                        // Change applies to the whole station, an EVSE or a single connector.
+                        let evse_id = payload["evse"]["id"].as_usize().unwrap_or(0);
+                        let connector_id = payload["evse"]["connectorId"].as_usize().unwrap_or(0);
 
-                if msg_from_map == "" {
-                    break;
-                }
+                        let connectors: Vec<(usize, usize)> = (0..storage::evse_count())
+                            .filter(|evse_index| evse_id == 0 || *evse_index + 1 == evse_id)
+                            .flat_map(|evse_index| (0..storage::connector_count(evse_index)).map(move |connector_index| (evse_index, connector_index)))
+                            .filter(|(_, connector_index)| connector_id == 0 || *connector_index + 1 == connector_id)
+                            .collect();
 
-                let parsed_msg_from_map = match json::parse(&msg_from_map.to_owned()) {
-                    Ok(result) => result,
-                    Err(e) => panic!("Error during parsing: {:?}", e),
-                };
+                        let response_status = if connectors.is_empty() || (evse_id == 0 && connector_id != 0) {
+                            "Rejected"
+                        } else {
+                            // Connectors with an ongoing transaction become unavailable once the EV is unplugged.
+                            let scheduled = event == Event::Disable && connectors.iter().any(|(evse_index, connector_index)| transactions::find(*evse_index, *connector_index).is_some());
 
-                let msg_from_map_action: &str = &parsed_msg_from_map[2].to_string();
-                // NOTE Unused.
-                // let msg_from_map_payload: &JsonValue = &parsed_msg_from_map[3];
+                            for (evse_index, connector_index) in connectors {
+                                state_machine::handle(evse_index, connector_index, event);
+                            }
 
-                match msg_from_map_action {
-                    "BootNotification" => {
-                        // Check status of the response.
-                        if payload["status"].to_string() == "Accepted" {
-                            println!("BootNotification was accepted.");
+                            if scheduled { "Scheduled" } else { "Accepted" }
+                        };
 
-                            // Set EVSE status to "Available" and send StatusNotification with updated status.
+                        // Send ChangeAvailability response.
 
-                            let connector_status = "Available";
-                            let status_notification_msg_id: &str = &Uuid::new_v4().to_string();
-                            let status_notification_msg = requests::status_notification(status_notification_msg_id, 1, 1, connector_status);
+                        let response_msg: String = responses::change_availability(msg_id, response_status);
 
-                            storage::set_message(status_notification_msg_id.to_string(), status_notification_msg.to_owned());
+                        self.send(response_msg)?;
+                    },
+                    "CancelReservation" => {
+                        let reservation_id: u64 = match payload["reservationId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "reservationId")?;
+
+                                break;
+                            },
+                        };
 
-                            storage::queue_add(status_notification_msg);
+                        let evse_index = reservations::cancel(reservation_id)?;
 
-                            storage::set_connector_status(0, 0, connector_status);
+                        let response_status = match evse_index {
+                            Some(_) => "Accepted",
+                            None => "Rejected",
+                        };
+
+                        // Send CancelReservation response.
+
+                        let response_msg: String = responses::cancel_reservation(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Set status of reserved EVSE connectors to "Available" and send StatusNotification with updated status.
+
+                        if let Some(evse_index) = evse_index {
+                            state_machine::handle_evse(evse_index, Event::ReservationEnded);
+                        }
+                    },
+                    "SetChargingProfile" => {
+                        let evse_id: usize = match payload["evseId"].as_usize() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "evseId")?;
+
+                                break;
+                            },
+                        };
+
+                        let response_status = charging_profiles::set(evse_id, &payload["chargingProfile"])?;
+
+                        // Send SetChargingProfile response.
+
+                        let response_msg: String = responses::set_charging_profile(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Apply new limits to the ongoing transactions.
+                        charging_profiles::apply_limits();
+
+                        // ISO 15118 EV renegotiates its charging schedule.
+                        if response_status == "Accepted" && payload["chargingProfile"]["chargingProfilePurpose"] == "TxProfile" {
+                            plug_and_charge::profile_received(evse_id)?;
+                        }
+                    },
+                    "ClearChargingProfile" => {
+                        let response_status = charging_profiles::clear(payload["chargingProfileId"].as_u64(), &payload["chargingProfileCriteria"])?;
+
+                        // Send ClearChargingProfile response.
+
+                        let response_msg: String = responses::clear_charging_profile(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Apply remaining limits to the ongoing transactions.
+                        charging_profiles::apply_limits();
+                    },
+                    "GetCompositeSchedule" => {
+                        let evse_id: usize = match payload["evseId"].as_usize() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "evseId")?;
+
+                                break;
+                            },
+                        };
+                        let duration: i64 = match payload["duration"].as_i64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "duration")?;
+
+                                break;
+                            },
+                        };
+                        let charging_rate_unit: &str = payload["chargingRateUnit"].as_str().unwrap_or("W");
+
+                        let mut response_status = "Accepted";
+                        let mut schedule: Option<JsonValue> = None;
+
+                        if evse_id > storage::evse_count() || duration <= 0 || (charging_rate_unit != "W" && charging_rate_unit != "A") {
+                            response_status = "Rejected";
+                        } else {
+                            let schedule_start = match clock::now().with_nanosecond(0) {
+                                Some(res) => res,
+                                None => return Err(EmulatorError::InvalidDate),
+                            };
 
-                            // Schedule a Heartbeat using the interval from BootNotification.
+                            let mut periods: JsonValue = JsonValue::new_array();
 
-                            unsafe {
-                                match payload["interval"].as_number() {
-                                    Some(res) => HEARTBEAT_INTERVAL = (res.as_fixed_point_i64(0).unwrap_or(0) as u64) * 1000,
-                                    None => panic!("Parsed message has no value."),
+                            for (start_period, limit) in charging_profiles::composite_schedule(evse_id, schedule_start, duration) {
+                                let period = object!{
+                                    "startPeriod" => start_period,
+                                    "limit" => charging_profiles::from_watts(limit, charging_rate_unit),
                                 };
 
-                                self.out.timeout(HEARTBEAT_INTERVAL, HEARTBEAT)?;
+                                periods.push(period)?;
+                            }
+
+                            schedule = Some(object!{
+                                "evseId" => evse_id,
+                                "duration" => duration,
+                                "scheduleStart" => schedule_start.to_rfc3339(),
+                                "chargingRateUnit" => charging_rate_unit,
+                                "chargingSchedulePeriod" => periods,
+                            });
+                        }
+
+                        // Send GetCompositeSchedule response.
+
+                        let response_msg: String = responses::get_composite_schedule(msg_id, response_status, schedule);
+
+                        self.send(response_msg)?;
+                    },
+                    "SetDisplayMessage" => {
+                        let response_status = display_messages::set(&payload["message"]);
+
+                        // Send SetDisplayMessage response.
+
+                        let response_msg: String = responses::set_display_message(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "GetDisplayMessages" => {
+                        let request_id: u64 = match payload["requestId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "requestId")?;
+
+                                break;
+                            },
+                        };
+
+                        let messages = display_messages::find(&payload["id"], &payload["priority"], &payload["state"])?;
+
+                        let response_status = if messages.is_empty() { "Unknown" } else { "Accepted" };
+
+                        // Send GetDisplayMessages response.
+
+                        let response_msg: String = responses::get_display_messages(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Send NotifyDisplayMessages request with the found messages.
+
+                        if !messages.is_empty() {
+                            let mut message_info: JsonValue = JsonValue::new_array();
+
+                            for message in messages {
+                                message_info.push(message)?;
+                            }
+
+                            let notify_display_messages_msg_id: &str = &ids::uuid();
+                            let notify_display_messages_msg = requests::notify_display_messages(notify_display_messages_msg_id, request_id, message_info, false);
+
+                            storage::set_message(notify_display_messages_msg_id.to_string(), notify_display_messages_msg.to_owned());
+
+                            storage::queue_add(notify_display_messages_msg);
+                        }
+                    },
+                    "ClearDisplayMessage" => {
+                        let message_id: u64 = match payload["id"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "id")?;
+
+                                break;
+                            },
+                        };
+
+                        let response_status = display_messages::clear(message_id)?;
+
+                        // Send ClearDisplayMessage response.
+
+                        let response_msg: String = responses::clear_display_message(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "DataTransfer" => {
+                        let vendor_id: &str = &payload["vendorId"].to_string();
+
+                        let (response_status, response_data) = data_transfer::handle(vendor_id, &payload["messageId"], &payload["data"])?;
+
+                        // Send DataTransfer response.
+
+                        let response_msg: String = responses::data_transfer(msg_id, response_status, response_data);
+
+                        self.send(response_msg)?;
+                    },
+                    "UpdateFirmware" => {
+                        let request_id: u64 = match payload["requestId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "requestId")?;
+
+                                break;
+                            },
+                        };
+
+                        let response_status = firmware::update(request_id, payload);
+
+                        // Send UpdateFirmware response.
+
+                        let response_msg: String = responses::update_firmware(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "PublishFirmware" => {
+                        let request_id: u64 = match payload["requestId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "requestId")?;
+
+                                break;
+                            },
+                        };
+
+                        let response_status = publish_firmware::publish(request_id, payload);
+
+                        // Send PublishFirmware response.
+
+                        let response_msg: String = responses::publish_firmware(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "UnpublishFirmware" => {
+                        let response_status = publish_firmware::unpublish(&payload["checksum"].to_string())?;
+
+                        // Send UnpublishFirmware response.
+
+                        let response_msg: String = responses::unpublish_firmware(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "GetLog" => {
+                        let request_id: u64 = match payload["requestId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "requestId")?;
+
+                                break;
+                            },
+                        };
+
+                        let (response_status, filename) = logs::get_log(request_id, payload);
+
+                        // Send GetLog response.
+
+                        let response_msg: String = responses::get_log(msg_id, response_status, filename);
+
+                        self.send(response_msg)?;
+                    },
+                    "CustomerInformation" => {
+                        let request_id: u64 = match payload["requestId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "requestId")?;
+
+                                break;
+                            },
+                        };
+
+                        let response_status = customer_information::handle(request_id, payload)?;
+
+                        // Send CustomerInformation response.
+
+                        let response_msg: String = responses::customer_information(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "CostUpdated" => {
+                        let transaction_id: &str = &payload["transactionId"].to_string();
+                        let total_cost: f64 = match payload["totalCost"].as_f64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "totalCost")?;
+
+                                break;
+                            },
+                        };
+
+                        // Save running cost of the transaction.
+                        let transaction = storage::get_transaction(transaction_id);
+
+                        if !transaction.is_empty() {
+                            let mut transaction = json::parse(&transaction)?;
+
+                            transaction["totalCost"] = total_cost.into();
+
+                            storage::set_transaction(transaction_id.to_string(), transaction.dump());
+
+                            println!("Running cost of transaction {}: {}", transaction_id, total_cost);
+                        }
+
+                        // Send CostUpdated response.
+
+                        let response_msg: String = responses::cost_updated(msg_id);
+
+                        self.send(response_msg)?;
+                    },
+                    "GetTransactionStatus" => {
+                        let transaction_id: Option<&str> = payload["transactionId"].as_str();
+
+                        let ongoing_indicator: Option<bool> = transaction_id.map(|id| !storage::get_transaction(id).is_empty());
+
+                        // Look for queued TransactionEvent requests of the transaction (or of any transaction).
+                        let messages_in_queue: bool = storage::queue_messages().iter().any(|queued_msg| {
+                            match json::parse(queued_msg) {
+                                Ok(parsed_queued_msg) => parsed_queued_msg[2] == "TransactionEvent"
+                                    && transaction_id.is_none_or(|id| requests::transaction_event_id(&parsed_queued_msg[3]) == id),
+                                Err(_) => false,
+                            }
+                        });
+
+                        // Send GetTransactionStatus response.
+
+                        let response_msg: String = responses::get_transaction_status(msg_id, ongoing_indicator, messages_in_queue);
+
+                        self.send(response_msg)?;
+                    },
+                    "SetVariableMonitoring" => {
+                        let set_monitoring_data_array = &payload["setMonitoringData"];
+
+                        let mut results: JsonValue = JsonValue::new_array();
+
+                        for i in 0..set_monitoring_data_array.len() {
+                            let set_monitoring_data = &set_monitoring_data_array[i];
+
+                            let (monitor_id, status) = monitoring::set(set_monitoring_data)?;
+
+                            let mut result = object!{
+                                "status" => status,
+                                "type" => set_monitoring_data["type"].clone(),
+                                "severity" => set_monitoring_data["severity"].clone(),
+                                "component" => set_monitoring_data["component"].clone(),
+                                "variable" => set_monitoring_data["variable"].clone(),
+                            };
+
+                            if let Some(data) = monitor_id {
+                                result["id"] = data.into();
+                            }
+
+                            results.push(result)?;
+                        }
+
+                        // Send SetVariableMonitoring response.
+
+                        let response_msg: String = responses::set_variable_monitoring(msg_id, results);
+
+                        self.send(response_msg)?;
+                    },
+                    "ClearVariableMonitoring" => {
+                        if !payload["id"].is_array() || payload["id"].members().any(|id| id.as_u64().is_none()) {
+                            self.send_field_error(msg_id, payload, "id")?;
+
+                            break;
+                        }
+
+                        let mut results: JsonValue = JsonValue::new_array();
+
+                        for id in payload["id"].members() {
+                            let monitor_id: u64 = id.as_u64().unwrap_or(0);
+
+                            let result = object!{
+                                "status" => monitoring::clear(monitor_id),
+                                "id" => monitor_id,
+                            };
+
+                            results.push(result)?;
+                        }
+
+                        // Send ClearVariableMonitoring response.
+
+                        let response_msg: String = responses::clear_variable_monitoring(msg_id, results);
+
+                        self.send(response_msg)?;
+                    },
+                    "SetMonitoringBase" => {
+                        let response_status = monitoring::set_base(&payload["monitoringBase"].to_string());
+
+                        // Send SetMonitoringBase response.
+
+                        let response_msg: String = responses::set_monitoring_base(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "SetMonitoringLevel" => {
+                        let response_status = match payload["severity"].as_u8() {
+                            Some(severity) if severity <= 9 => {
+                                storage::set_monitoring_level(severity);
+
+                                "Accepted"
+                            },
+                            _ => "Rejected",
+                        };
+
+                        // Send SetMonitoringLevel response.
+
+                        let response_msg: String = responses::set_monitoring_level(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "GetBaseReport" => {
+                        let request_id: u64 = match payload["requestId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "requestId")?;
+
+                                break;
+                            },
+                        };
+
+                        let report = components::base_report(&payload["reportBase"].to_string());
+
+                        let response_status = if report.is_some() { "Accepted" } else { "NotSupported" };
+
+                        // Send GetBaseReport response.
+
+                        let response_msg: String = responses::get_base_report(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Send NotifyReport requests with the reported variables, the report is split into parts which fit into the message limits.
+
+                        if let Some(report) = report {
+                            let generated_at = match clock::now().with_nanosecond(0) {
+                                Some(res) => res.to_rfc3339(),
+                                None => return Err(EmulatorError::InvalidDate),
+                            };
+
+                            let parts = components::report_parts(report);
+                            let part_count = parts.len();
+
+                            for (seq_no, report_data) in parts.into_iter().enumerate() {
+                                let notify_report_msg_id: &str = &ids::uuid();
+                                let notify_report_msg = requests::notify_report(notify_report_msg_id, request_id, &generated_at, seq_no as u64, report_data, seq_no + 1 < part_count);
+
+                                storage::set_message(notify_report_msg_id.to_string(), notify_report_msg.to_owned());
+
+                                storage::queue_add(notify_report_msg);
+                            }
+                        }
+                    },
+                    "GetMonitoringReport" => {
+                        let request_id: u64 = match payload["requestId"].as_u64() {
+                            Some(res) => res,
+                            None => {
+                                self.send_field_error(msg_id, payload, "requestId")?;
+
+                                break;
+                            },
+                        };
+
+                        let report = monitoring::report(&payload["monitoringCriteria"], &payload["componentVariable"])?;
+
+                        let response_status = if report.is_empty() { "EmptyResultSet" } else { "Accepted" };
+
+                        // Send GetMonitoringReport response.
+
+                        let response_msg: String = responses::get_monitoring_report(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Send NotifyMonitoringReport request with the found monitors.
+
+                        if !report.is_empty() {
+                            let generated_at = match clock::now().with_nanosecond(0) {
+                                Some(res) => res.to_rfc3339(),
+                                None => return Err(EmulatorError::InvalidDate),
+                            };
+
+                            let mut monitor: JsonValue = JsonValue::new_array();
+
+                            for item in report {
+                                monitor.push(item)?;
+                            }
+
+                            let notify_monitoring_report_msg_id: &str = &ids::uuid();
+                            let notify_monitoring_report_msg = requests::notify_monitoring_report(notify_monitoring_report_msg_id, request_id, &generated_at, 0, monitor, false);
+
+                            storage::set_message(notify_monitoring_report_msg_id.to_string(), notify_monitoring_report_msg.to_owned());
+
+                            storage::queue_add(notify_monitoring_report_msg);
+                        }
+                    },
+                    "InstallCertificate" => {
+                        let response_status = certificates::install(&payload["certificateType"].to_string(), &payload["certificate"].to_string());
+
+                        // Send InstallCertificate response.
+
+                        let response_msg: String = responses::install_certificate(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Check status of the new certificate.
+                        if response_status == "Accepted" {
+                            certificates::request_statuses();
+                        }
+                    },
+                    "DeleteCertificate" => {
+                        let response_status = certificates::delete(&payload["certificateHashData"]);
+
+                        // Send DeleteCertificate response.
+
+                        let response_msg: String = responses::delete_certificate(msg_id, response_status);
+
+                        self.send(response_msg)?;
+                    },
+                    "GetInstalledCertificateIds" => {
+                        let mut certificate_hash_data_chain: JsonValue = JsonValue::new_array();
+
+                        for certificate in certificates::installed(&payload["certificateType"]) {
+                            let item = object!{
+                                "certificateType" => certificate["certificateType"].clone(),
+                                "certificateHashData" => certificate["certificateHashData"].clone(),
+                            };
+
+                            certificate_hash_data_chain.push(item)?;
+                        }
+
+                        let response_status = if certificate_hash_data_chain.is_empty() { "NotFound" } else { "Accepted" };
+
+                        // Send GetInstalledCertificateIds response.
+
+                        let response_msg: String = responses::get_installed_certificate_ids(msg_id, response_status, certificate_hash_data_chain);
+
+                        self.send(response_msg)?;
+                    },
+                    "CertificateSigned" => {
+                        let certificate_type: &str = payload["certificateType"].as_str().unwrap_or("ChargingStationCertificate");
+
+                        let response_status = certificates::install_signed(&payload["certificateChain"].to_string(), certificate_type);
+
+                        // Send CertificateSigned response.
+
+                        let response_msg: String = responses::certificate_signed(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        // Reconnect using the new certificate.
+                        if response_status == "Accepted" && certificate_type == "ChargingStationCertificate" && security_profile() == 3 {
+                            println!("Reconnecting with the new charging station certificate.");
+
+                            storage::set_reconnect(true);
+
+                            self.out.close(CloseCode::Normal)?;
+                        }
+                    },
+                    "TriggerMessage" => {
+                        let evse_index: Option<usize> = payload["evse"]["id"].as_usize().filter(|id| *id > 0).map(|id| id - 1);
+                        let connector_index: Option<usize> = payload["evse"]["connectorId"].as_usize().filter(|id| *id > 0).map(|id| id - 1);
+                        let requested_message: &str = payload["requestedMessage"].as_str().unwrap_or_default();
+
+                        // Message is triggered for the given EVSE and connector or for all of them.
+                        let evse_indexes: Vec<usize> = match evse_index {
+                            Some(res) => vec![res],
+                            None => (0..storage::evse_count()).collect(),
+                        };
+                        let connectors: Vec<(usize, usize)> = evse_indexes.iter()
+                            .flat_map(|evse_index| (0..storage::connector_count(*evse_index)).map(move |connector_index| (*evse_index, connector_index)))
+                            .filter(|(_, index)| connector_index.is_none_or(|connector_index| connector_index == *index))
+                            .collect();
+
+                        let response_status: &str = match requested_message {
+                            _ if evse_index.is_some_and(|index| index >= storage::evse_count()) => "Rejected",
+                            // Station which is already accepted doesn't boot again.
+                            "BootNotification" if storage::get_registration_status() == "Accepted" => "Rejected",
+                            // Other messages would wait in the queue until the station is accepted.
+                            "BootNotification" => "Accepted",
+                            _ if storage::get_registration_status() != "Accepted" => "Rejected",
+                            "StatusNotification" if connectors.is_empty() => "Rejected",
+                            "Heartbeat" | "MeterValues" | "StatusNotification" => "Accepted",
+                            _ => "NotImplemented",
+                        };
+
+                        // Send TriggerMessage response.
+
+                        let response_msg: String = responses::trigger_message(msg_id, response_status);
+
+                        self.send(response_msg)?;
+
+                        if response_status != "Accepted" {
+                            break;
+                        }
+
+                        match requested_message {
+                            "BootNotification" => queue_boot_notification("Triggered"),
+                            "Heartbeat" => queue_heartbeat(),
+                            "MeterValues" => for evse_index in evse_indexes {
+                                meter::queue_triggered(evse_index)?;
+                            },
+                            "StatusNotification" => for (evse_index, connector_index) in connectors {
+                                if let Some(connector) = storage::get_connector(evse_index, connector_index) {
+                                    let status_notification_msg_id: &str = &ids::uuid();
+                                    let status_notification_msg = requests::status_notification(status_notification_msg_id, (evse_index + 1) as u8, (connector_index + 1) as u8, connector.status)?;
+
+                                    storage::set_message(status_notification_msg_id.to_string(), status_notification_msg.to_owned());
+
+                                    storage::queue_add(status_notification_msg);
+                                }
+                            },
+                            _ => (),
+                        }
+                    },
+                    _ => {
+                        println!("No request handler for action: {}", action);
+
+                        // Send CALLERROR response.
+
+                        let response_msg: String = if CSMS_ACTIONS.contains(&action) {
+                            responses::call_error(msg_id, "NotSupported", &format!("{} is not supported.", action))
+                        } else {
+                            responses::call_error(msg_id, "NotImplemented", &format!("{} is not known.", action))
+                        };
+
+                        self.send(response_msg)?;
+                    },
+                }
+            }),
+            CALLRESULT => block!({
+                let payload: &JsonValue = &parsed_msg[2];
+
+                let msg_from_map = storage::get_message(msg_id);
+
+                if msg_from_map.is_empty() {
+                    break;
+                }
+
+                let parsed_msg_from_map = json::parse(&msg_from_map.to_owned())?;
+
+                let msg_from_map_action: &str = &parsed_msg_from_map[2].to_string();
+
+                // Response which violates the schema of the action is logged and dropped, CALLERROR answers only CALL.
+                if let Err((error_code, description)) = schemas::validate(msg_from_map_action, "Response", payload) {
+                    println!("{} response is invalid and is dropped: {} ({})", msg_from_map_action, description, error_code);
+
+                    break;
+                }
+
+                match msg_from_map_action {
+                    "BootNotification" => {
+                        // Check status of the response.
+                        if payload["status"] == "Accepted" {
+                            println!("BootNotification was accepted.");
+
+                            storage::set_registration_status("Accepted");
+                            storage::set_boot_rejections(0);
+
+                            // Report the startup and synchronize the clock with CSMS.
+                            let security_event = match parsed_msg_from_map[3]["reason"].as_str() {
+                                Some("PowerUp") => "StartupOfTheDevice",
+                                Some("FirmwareUpdate") => "FirmwareUpdated",
+                                _ => "ResetOrReboot",
+                            };
+
+                            security_events::notify(security_event, None)?;
+
+                            clock::synchronize(&payload["currentTime"])?;
+
+                            // Send StatusNotification with the status of every connector.
+                            for evse_index in 0..storage::evse_count() {
+                                state_machine::handle_evse(evse_index, Event::Boot);
                             }
+
+                            // Schedule a Heartbeat using the interval from BootNotification, zero interval would flood CSMS.
+                            match payload["interval"].as_u64().filter(|interval| *interval > 0) {
+                                Some(res) => {
+                                    self.heartbeat_interval = res * 1000;
+
+                                    components::set_variable("OCPPCommCtrlr", "HeartbeatInterval", res.to_string());
+                                },
+                                None => println!("BootNotification response has no valid interval, previous heartbeat interval is kept."),
+                            };
+
+                            // Heartbeat is already scheduled if the station has rebooted.
+                            if let Some(timeout) = self.heartbeat_timeout.take() {
+                                self.out.cancel(timeout)?;
+                            }
+
+                            self.out.timeout(clock::real_duration(self.heartbeat_interval), HEARTBEAT)?;
+
+                            // Renew the charging station certificate which is used for the connection.
+                            if security_profile() == 3 && certificates::station_certificate_expires(CERTIFICATE_RENEWAL_DAYS) {
+                                queue_sign_certificate("ChargingStationCertificate");
+                            }
+
+                            // Check status of installed certificates.
+                            certificates::request_statuses();
+
+                            // Send configured vendor specific DataTransfer requests.
+
+                            for data_transfer in data_transfer::configured_messages()? {
+                                let data_transfer_msg_id: &str = &ids::uuid();
+                                let data_transfer_msg = requests::data_transfer(data_transfer_msg_id, &data_transfer["vendorId"].to_string(), data_transfer["messageId"].as_str(), Some(data_transfer["data"].clone()).filter(|data| !data.is_null()));
+
+                                storage::set_message(data_transfer_msg_id.to_string(), data_transfer_msg.to_owned());
+
+                                storage::queue_add(data_transfer_msg);
+                            }
+                        } else if payload["status"] == "Pending" {
+                            println!("BootNotification is pending.");
+
+                            storage::set_registration_status("Pending");
+
+                            // Send BootNotification again after the interval from the response.
+                            self.out.timeout(clock::real_duration(boot_retry_interval(payload["interval"].as_u64(), false)), BOOT_RETRY)?;
+                        } else if payload["status"] == "Rejected" {
+                            println!("BootNotification was rejected.");
+
+                            storage::set_registration_status("Rejected");
+                            storage::set_boot_rejections(storage::get_boot_rejections() + 1);
+
+                            // All traffic is suppressed until BootNotification is sent again.
+                            self.out.timeout(clock::real_duration(boot_retry_interval(payload["interval"].as_u64(), true)), BOOT_RETRY)?;
+                        }
+                    },
+                    "Heartbeat" => {
+                        clock::synchronize(&payload["currentTime"])?;
+                    },
+                    "DataTransfer" => {
+                        println!("DataTransfer status: {}", payload["status"]);
+
+                        if !payload["data"].is_null() {
+                            println!("DataTransfer data: {}", payload["data"]);
+                        }
+                    },
+                    "TransactionEvent" => {
+                        journal::acknowledge(msg_id);
+
+                        if !payload["idTokenInfo"].is_null() {
+                            transactions::id_token_info_received(&requests::transaction_event_id(&parsed_msg_from_map[3]), &payload["idTokenInfo"])?;
+                        }
+                    },
+                    "Get15118EVCertificate" => {
+                        plug_and_charge::certificate_received(&parsed_msg_from_map[3], payload)?;
+                    },
+                    "Authorize" => {
+                        authorization::authorized(&parsed_msg_from_map[3]["idToken"], &payload["idTokenInfo"])?;
+                    },
+                    "NotifyEVChargingNeeds" => {
+                        println!("NotifyEVChargingNeeds status: {}", payload["status"]);
+                    },
+                    "NotifyEVChargingSchedule" => {
+                        println!("NotifyEVChargingSchedule status: {}", payload["status"]);
+                    },
+                    "GetCertificateStatus" => {
+                        certificates::status_received(&parsed_msg_from_map[3]["ocspRequestData"], payload);
+                    },
+                    "SignCertificate" => {
+                        println!("SignCertificate status: {}", payload["status"]);
+
+                        if payload["status"] != "Accepted" {
+                            let certificate_type: &str = parsed_msg_from_map[3]["certificateType"].as_str().unwrap_or("ChargingStationCertificate");
+
+                            storage::set_pending_private_key(certificate_type.to_string(), None);
                         }
                     },
                     _=> println!("No response handler for action: {}", msg_from_map_action),
@@ -366,82 +1682,423 @@ impl Handler for Client {
                 println!("CALLERROR Error code: {}", error_code);
                 println!("CALLERROR Error Description: {}", error_description);
                 println!("CALLERROR Error details: {}", error_details);
+
+                // Send the failed request again or drop it.
+                retries::handle_call_error(msg_id, error_code);
+            },
+            _ => {
+                println!("Unknown message type ID");
+
+                self.send(responses::call_error(msg_id, "MessageTypeNotSupported", &format!("Message type id {} is not supported.", msg_type_id)))?;
+            },
+        }
+
+        // Late response of a request which has timed out makes its retry needless.
+        if msg_type_id == CALLRESULT {
+            storage::cancel_delayed_message(msg_id);
+        }
+
+        if msg_type_id == CALLRESULT || msg_type_id == CALLERROR {
+            // Answered request releases the queue, the next request is sent once the response is handled.
+            storage::clear_last_sent_message(msg_id);
+            storage::release_message(msg_id);
+        }
+
+        Ok(())
+    }
+}
+
+/// We implement the Handler trait for Client so that we can get more
+/// fine-grained control of the connection.
+impl Handler for Client {
+
+    /// Add protocols to initial handshake request.
+    ///
+    /// Station authenticates with its identity and basic authentication password unless security profile 3 is used.
+    fn build_request(&mut self, url: &url::Url) -> Result<Request> {
+        let mut req = Request::from_url(url)?;
+
+        // Connection through a proxy tunnel is addressed to the CSMS endpoint.
+        if let Some((host, port)) = storage::get_tunneled_endpoint() {
+            for (name, value) in req.headers_mut().iter_mut() {
+                if name == "Host" {
+                    *value = format!("{}:{}", host, port).into_bytes();
+                }
+            }
+        }
+
+        for protocol in offered_protocols() {
+            req.add_protocol(protocol);
+        }
+
+        if let Some(password) = basic_auth_password().filter(|_| security_profile() != 3) {
+            let credentials = format!("{}:{}", config::var("STATION_ID").unwrap_or_default(), password);
+
+            req.headers_mut().push((String::from("Authorization"), format!("Basic {}", base64::encode_block(credentials.as_bytes())).into_bytes()));
+        }
+
+        Ok(req)
+    }
+
+    /// Called when the WebSocket handshake is successful and the connection is open for sending
+    /// and receiving messages.
+    ///
+    /// Configures interval between fetches in the message queue, between checks of monitored variables and between meter samples.
+    /// Sends BootNotification message to the message queue unless the station has been accepted before.
+    fn on_open(&mut self, handshake: Handshake) -> Result<()> {
+        // CSMS has to select one of the offered protocols.
+        let protocol = match handshake.response.protocol() {
+            Ok(Some(res)) => offered_protocols().into_iter().find(|protocol| *protocol == res),
+            _ => None,
+        };
+
+        let protocol = match protocol {
+            Some(res) => res,
+            None => {
+                println!("CSMS hasn't selected any of the offered OCPP protocols.");
+
+                return self.out.close(CloseCode::Protocol);
             },
-            _ => println!("Unknown message type ID"),
+        };
+
+        println!("OCPP protocol: {}", protocol);
+
+        storage::set_protocol(protocol);
+        storage::set_online(true);
+        storage::set_connection_opened(true);
+
+        // Queue is drained once the connection is set up and whenever a message is queued.
+        let out = self.out.clone();
+
+        storage::set_queue_waker(Some(Box::new(move || {
+            let _ = out.timeout(1, QUEUE_FETCH);
+        })));
+
+        // Embedding application can stop the station while it's connected.
+        let out = self.out.clone();
+
+        storage::set_connection_closer(Some(Box::new(move || {
+            let _ = out.close(CloseCode::Away);
+        })));
+
+        if storage::is_stop_requested() {
+            return self.out.close(CloseCode::Away);
         }
 
+        self.out.timeout(1, QUEUE_FETCH)?;
+
+        // Start monitoring of variables.
+        self.out.timeout(MONITORING_CHECK_INTERVAL, MONITORING_CHECK)?;
+
+        // Start sampling of the energy meter.
+        self.out.timeout(METER_SAMPLE_INTERVAL, METER_SAMPLE)?;
+
+        // Start keepalive of the connection.
+        if let Some(interval) = ping_interval() {
+            self.out.timeout(interval, PING)?;
+        }
+
+        // Station connected with a fallback network profile tries to return to the preferred one.
+        if storage::get_network_slot() > 0 {
+            self.out.timeout(NETWORK_PRIORITY_CHECK_INTERVAL, NETWORK_PRIORITY_CHECK)?;
+        }
+
+        let offline_since = storage::take_offline_since();
+
+        // Connection was reestablished, only the heartbeat has to be restarted.
+        if storage::get_registration_status() == "Accepted" {
+            // Status of every connector is reported again after a long outage.
+            if offline_since.is_some_and(|since| clock::now().timestamp() - since > offline_threshold()) {
+                for evse_index in 0..storage::evse_count() {
+                    state_machine::handle_evse(evse_index, Event::Boot);
+                }
+            }
+
+            self.out.timeout(clock::real_duration(self.heartbeat_interval), HEARTBEAT)?;
+
+            return Ok(());
+        }
+
+        // Send BootNotification request.
+        queue_boot_notification("PowerUp");
+
         Ok(())
     }
 
+    /// Upgrade the connection to TLS.
+    ///
+    /// CSMS certificate is verified against `TLS_CA_FILE` if it's set, otherwise against the system CA certificates.
+    /// Verification is skipped with `TLS_SKIP_VERIFY=true`, `TLS_SERVER_NAME` overrides the server name (SNI).
+    /// Presents the charging station certificate to CSMS when security profile 3 is used.
+    fn upgrade_ssl_client(&mut self, stream: TcpStream, url: &url::Url) -> Result<SslStream<TcpStream>> {
+        let server_name: String = match config::var("TLS_SERVER_NAME") {
+            Ok(var) if !var.is_empty() => var,
+            _ => match storage::get_tunneled_endpoint().map(|(host, _)| host).or_else(|| url.domain().map(String::from)) {
+                Some(res) => res,
+                None => return Err(Error::new(ErrorKind::Protocol, format!("Unable to parse domain from {}. Needed for SSL.", url))),
+            },
+        };
+
+        let ssl_error = |e: openssl::error::ErrorStack| Error::new(ErrorKind::Internal, format!("Failed to upgrade client to SSL: {}", e));
+
+        let mut builder = SslConnector::builder(SslMethod::tls()).map_err(ssl_error)?;
+
+        if let Ok(path) = config::var("TLS_CA_FILE") {
+            if !path.is_empty() {
+                builder.set_ca_file(&path).map_err(ssl_error)?;
+            }
+        }
+
+        // Test labs often use self-signed certificates.
+        if config::var("TLS_SKIP_VERIFY").is_ok_and(|var| var == "true") {
+            println!("Verification of the CSMS certificate is skipped.");
+
+            builder.set_verify(SslVerifyMode::NONE);
+        }
+
+        if security_profile() == 3 {
+            if let Some((certificate_chain, private_key)) = certificates::station_certificate("ChargingStationCertificate") {
+                let chain = X509::stack_from_pem(certificate_chain.as_bytes()).map_err(ssl_error)?;
+                let key = PKey::private_key_from_pem(private_key.as_bytes()).map_err(ssl_error)?;
+
+                for (index, certificate) in chain.into_iter().enumerate() {
+                    if index == 0 {
+                        builder.set_certificate(&certificate).map_err(ssl_error)?;
+                    } else {
+                        builder.add_extra_chain_cert(certificate).map_err(ssl_error)?;
+                    }
+                }
+
+                builder.set_private_key(&key).map_err(ssl_error)?;
+            }
+        }
+
+        builder.build().connect(&server_name, stream).map_err(|e| {
+            if let Err(e) = security_events::notify("FailedToAuthenticateAtCsms", Some(&e.to_string())) {
+                println!("Failed authentication couldn't be reported ({})", e);
+            }
+
+            Error::from(e)
+        })
+    }
+
+    /// Called on incoming messages.
+    ///
+    /// Handles requests and responses from the Charging Station Management System.
+    fn on_message(&mut self, msg: Message) -> Result<()> {
+        storage::transcript_add("Received", msg.as_text()?);
+
+        // Message id "-1" is used in CALLERROR when the id can't be read from the message.
+
+        let parsed_msg = match json::parse(msg.as_text()?) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Error during parsing: {:?}", e);
+
+                return self.send(responses::call_error("-1", "FormatViolation", "Message is not a valid JSON."));
+            },
+        };
+
+        let msg_id: &str = match parsed_msg[1].as_str() {
+            Some(res) => res,
+            None => return self.send(responses::call_error("-1", "FormatViolation", "Message has no message id.")),
+        };
+
+        let msg_type_id = match parsed_msg[0].as_u8() {
+            Some(res) => res,
+            None => return self.send(responses::call_error(msg_id, "FormatViolation", "Message has no message type id.")),
+        };
+
+        println!("Message ID: {}", msg_id);
+
+        let result = match self.handle_message(&msg, &parsed_msg, msg_id, msg_type_id) {
+            Ok(()) => Ok(()),
+            Err(EmulatorError::Connection(e)) => Err(*e),
+            Err(e) => {
+                println!("Couldn't handle message {} ({})", msg_id, e);
+
+                // Request which isn't answered yet is rejected, the station keeps running.
+                if msg_type_id == CALL && storage::get_recent_response(msg_id).is_none() {
+                    self.send(responses::call_error(msg_id, e.error_code(), &e.to_string()))?;
+                }
+
+                Ok(())
+            },
+        };
+
+        result?;
+
+        self.drain_queue()
+    }
+
     /// Called any time this endpoint receives a close control frame.
     fn on_close(&mut self, code: CloseCode, reason: &str) {
        println!("WebSocket closing for ({:?}) {}", code, reason);
-       println!("Shutting down server after first connection closes.");
-       self.out.shutdown().unwrap();
+       storage::set_online(false);
+       storage::set_queue_waker(None);
+       storage::set_connection_closer(None);
+       println!("Station is offline until the connection is reestablished.");
+
+       if let Err(e) = self.out.shutdown() {
+           println!("WebSocket couldn't be shut down ({})", e);
+       }
    }
 
    /// Shutdown on any error.
    fn on_error(&mut self, err: Error) {
         println!("Shutting down server for error: {}", err);
-        self.out.shutdown().unwrap();
+        storage::set_online(false);
+
+        if let Err(e) = self.out.shutdown() {
+            println!("WebSocket couldn't be shut down ({})", e);
+        }
+    }
+
+    /// Called on incoming frames.
+    ///
+    /// Pong frames confirm that the connection is alive.
+    fn on_frame(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        if frame.opcode() == OpCode::Pong {
+            self.awaiting_pong = false;
+        }
+
+        if frame.has_rsv1() || frame.has_rsv2() || frame.has_rsv3() {
+            return Err(Error::new(ErrorKind::Protocol, "Encountered frame with reserved bits set."));
+        }
+
+        Ok(Some(frame))
+    }
+
+    /// Called when a timeout is set.
+    ///
+    /// Keeps the scheduled Heartbeat and ping so that they can be cancelled.
+    fn on_new_timeout(&mut self, event: Token, timeout: Timeout) -> Result<()> {
+        match event {
+            HEARTBEAT => self.heartbeat_timeout = Some(timeout),
+            QUEUE_FETCH => self.queue_timeout = Some(timeout),
+            PING => self.ping_timeout = Some(timeout),
+            _ => (),
+        };
+
+        Ok(())
     }
 
     /// Called when a timeout has been scheduled on the eventloop.
     ///
-    /// Sends Heartbeat message.
+    /// Sends Heartbeat message if no other message was sent within the heartbeat interval.
     /// Fetches and sends messages from the message queue.
     fn on_timeout(&mut self, event: Token) -> Result<()> {
-        match event {
+        let result = match event {
             HEARTBEAT => {
-                // Send Heartbeat message.
+                // Heartbeat is skipped if another message was sent within the interval.
+                let since_last_send: u64 = match storage::get_last_send_time() {
+                    Some(timestamp) => (clock::local_now().timestamp_millis() - timestamp).max(0) as u64,
+                    None => u64::MAX,
+                };
 
-                let msg_id: &str = &Uuid::new_v4().to_string();
-                let msg = requests::heartbeat(msg_id);
+                if since_last_send < self.heartbeat_interval {
+                    self.out.timeout(clock::real_duration(self.heartbeat_interval - since_last_send), HEARTBEAT)?;
 
-                storage::set_message(msg_id.to_string(), msg.to_owned());
+                    return Ok(());
+                }
 
-                storage::queue_add(msg);
+                queue_heartbeat();
 
                 // Schedule next message.
-                unsafe {
-                    self.out.timeout(HEARTBEAT_INTERVAL, HEARTBEAT)?;
-                }
+                self.out.timeout(clock::real_duration(self.heartbeat_interval), HEARTBEAT)?;
 
                 Ok(())
             },
             QUEUE_FETCH => {
-                let current_timestamp: u64 = Utc::now().timestamp() as u64;
-
-                let last_sent_msg = storage::get_last_sent_message();
-                // Check whether last sent message exists or not.
-                let last_sent_msg_exist: bool = last_sent_msg.id != None;
-                // Check whether last sent message has expired or not.
-                let last_sent_msg_expired: bool = match last_sent_msg.timestamp {
-                    Some(timestamp) => timestamp + QUEUE_MESSAGE_EXPIRATION < current_timestamp,
-                    None => true,
-                };
+                // Queue is drained after every event.
+                self.queue_timeout = None;
+
+                Ok(())
+            },
+            MONITORING_CHECK => {
+                if let Err(e) = monitoring::check() {
+                    println!("Monitors couldn't be checked ({})", e);
+                }
 
-                if storage::queue_size() > 0 && (!last_sent_msg_exist || last_sent_msg_expired) {
-                    let msg = storage::queue_pop();
+                self.out.timeout(MONITORING_CHECK_INTERVAL, MONITORING_CHECK)?;
 
-                    if msg != "" {
-                        let parsed_msg = match json::parse(&msg.to_owned()) {
-                            Ok(result) => result,
-                            Err(e) => panic!("Error during parsing: {:?}", e),
-                        };
+                Ok(())
+            },
+            BOOT_RETRY => {
+                queue_boot_notification(&storage::get_boot_reason());
 
-                        let msg_id: &str = &parsed_msg[1].to_string();
-                        let msg_action: &str = &parsed_msg[2].to_string();
+                Ok(())
+            },
+            METER_SAMPLE => {
+                // Authorized transactions don't wait for the cable forever and random drivers come and go.
+                tick();
 
-                        self.out.send(msg)?;
+                storage::sweep_messages();
 
-                        println!("{} ({}) was sent.", msg_action, msg_id);
+                self.out.timeout(METER_SAMPLE_INTERVAL, METER_SAMPLE)?;
 
-                        storage::set_last_sent_message(msg_id.to_string(), current_timestamp);
-                    }
+                Ok(())
+            },
+            PING => {
+                // Connection is broken if CSMS hasn't answered the previous ping, the station reconnects.
+                if self.awaiting_pong {
+                    println!("CSMS didn't answer WebSocket ping, connection is considered broken.");
+
+                    storage::set_online(false);
+                    self.out.shutdown()?;
+
+                    return Ok(());
                 }
 
-                self.out.timeout(QUEUE_FETCH_INTERVAL, QUEUE_FETCH)?;
+                self.awaiting_pong = true;
+                self.out.ping(Vec::new())?;
+
+                if let Some(interval) = ping_interval() {
+                    self.out.timeout(interval, PING)?;
+                }
+
+                Ok(())
+            },
+            NETWORK_PRIORITY_CHECK => {
+                let out = self.out.clone();
+
+                // Endpoints are probed in the background, the result is handled on the next event.
+                connection::probe_higher_priority(move || {
+                    let _ = out.timeout(1, NETWORK_PRIORITY_PROBED);
+                });
+
+                Ok(())
+            },
+            NETWORK_PRIORITY_PROBED => {
+                if connection::try_higher_priority() {
+                    storage::set_reconnect(true);
+
+                    self.out.close(CloseCode::Normal)?;
+                } else {
+                    self.out.timeout(NETWORK_PRIORITY_CHECK_INTERVAL, NETWORK_PRIORITY_CHECK)?;
+                }
+
+                Ok(())
+            },
+            RESERVATION_EXPIRY => {
+                let expired = match reservations::remove_expired() {
+                    Ok(res) => res,
+                    Err(e) => {
+                        println!("Expired reservations couldn't be removed ({})", e);
+
+                        Vec::new()
+                    },
+                };
+
+                for (reservation_id, evse_index) in expired {
+                    println!("Reservation {} has expired.", reservation_id);
+
+                    // Send ReservationStatusUpdate request to notify CSMS about the expired reservation.
+                    reservations::notify(reservation_id, "Expired");
+
+                    // Set status of reserved EVSE connectors to "Available" and send StatusNotification with updated status.
+                    state_machine::handle_evse(evse_index, Event::ReservationEnded);
+                }
 
                 Ok(())
             },
@@ -450,6 +2107,35 @@ impl Handler for Client {
                 ErrorKind::Internal,
                 "Invalid timeout token encountered!",
             )),
-        }
+        };
+
+        result?;
+
+        self.drain_queue()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boot_retry_interval_prefers_the_response_interval() {
+        assert_eq!(boot_retry_interval(Some(15), true), 15000);
+        assert_eq!(boot_retry_interval(Some(0), false), DEFAULT_BOOT_RETRY_INTERVAL * 1000);
+    }
+
+    // Every test runs on its own thread and so works with a separate state of the station.
+    #[test]
+    fn boot_retry_interval_doubles_after_each_rejection() {
+        storage::set_boot_rejections(1);
+        assert_eq!(boot_retry_interval(None, true), DEFAULT_BOOT_RETRY_INTERVAL * 1000);
+
+        storage::set_boot_rejections(3);
+        assert_eq!(boot_retry_interval(None, true), DEFAULT_BOOT_RETRY_INTERVAL * 4000);
+        assert_eq!(boot_retry_interval(None, false), DEFAULT_BOOT_RETRY_INTERVAL * 1000);
+
+        storage::set_boot_rejections(40);
+        assert_eq!(boot_retry_interval(None, true), MAX_BOOT_RETRY_INTERVAL * 1000);
     }
 }
@@ -0,0 +1,133 @@
+use chrono::prelude::*;
+use json::JsonValue;
+
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::ids;
+use crate::security_events;
+use crate::storage;
+
+// Change of the clock offset which is reported as setting the system time (ms).
+const SIGNIFICANT_OFFSET_CHANGE: i64 = 5000;
+// Start of the simulated time of deterministic mode unless `SIMULATION_START_TIME` is set.
+const DETERMINISTIC_START_TIME: &str = "2020-01-01T00:00:00Z";
+
+/// Get the current time of the charging station synchronized with CSMS.
+pub fn now() -> DateTime<Utc> {
+    local_now() + chrono::Duration::milliseconds(storage::get_clock_offset())
+}
+
+/// Get the current time without fractions of a second in RFC 3339 format, e.g. for timestamps of messages.
+pub fn timestamp() -> Result<String, EmulatorError> {
+    match now().with_nanosecond(0) {
+        Some(res) => Ok(res.to_rfc3339()),
+        None => Err(EmulatorError::InvalidDate),
+    }
+}
+
+/// Get the simulated local time which isn't affected by clock synchronization.
+///
+/// Simulated time runs `SIMULATION_SPEED` times faster than the real time since the first call and starts
+/// at `SIMULATION_START_TIME` if it's set. Manual clock enabled with `SIMULATION_CLOCK=manual` stands still
+/// unless it's advanced. Deterministic mode always uses the manual clock.
+pub fn local_now() -> DateTime<Utc> {
+    let real_now = Utc::now();
+    let speed = speed();
+    let manual = is_manual();
+
+    if !is_simulated() {
+        return real_now;
+    }
+
+    let start = match storage::get_simulation_start() {
+        Some(res) => res,
+        None => {
+            storage::set_simulation_start(real_now.timestamp_millis());
+
+            real_now.timestamp_millis()
+        },
+    };
+
+    let elapsed = if manual { 0 } else { ((real_now.timestamp_millis() - start) as f64 * speed) as i64 };
+    let origin = start_time().map_or(start, |start_time| start_time.timestamp_millis());
+
+    Utc.timestamp_millis(origin + elapsed + storage::get_clock_advance())
+}
+
+/// Advance the simulated clock by the given time (s).
+pub fn advance(seconds: i64) {
+    // Simulation starts now if the clock hasn't been read yet.
+    local_now();
+
+    storage::add_clock_advance(seconds * 1000);
+
+    println!("Clock was advanced to {}.", local_now().to_rfc3339());
+}
+
+/// Get the real duration (ms) of a simulated duration, e.g. to schedule a timer.
+pub fn real_duration(duration: u64) -> u64 {
+    ((duration as f64 / speed()) as u64).max(1)
+}
+
+/// Check if the local time differs from the real time.
+fn is_simulated() -> bool {
+    speed() != 1.0 || is_manual() || start_time().is_some() || storage::get_clock_advance() != 0
+}
+
+/// Check if the simulated clock stands still unless it's advanced.
+fn is_manual() -> bool {
+    ids::is_deterministic() || config::var("SIMULATION_CLOCK").is_ok_and(|var| var == "manual")
+}
+
+/// Get the configured start of the simulated time.
+fn start_time() -> Option<DateTime<Utc>> {
+    let start_time = match config::var("SIMULATION_START_TIME") {
+        Ok(var) => DateTime::parse_from_rfc3339(&var).ok(),
+        _ => None,
+    };
+
+    match start_time {
+        Some(res) => Some(res.with_timezone(&Utc)),
+        None if ids::is_deterministic() => DateTime::parse_from_rfc3339(DETERMINISTIC_START_TIME).ok().map(|start_time| start_time.with_timezone(&Utc)),
+        None => None,
+    }
+}
+
+/// Get the simulation speed multiplier, 1 by default.
+fn speed() -> f64 {
+    match config::var("SIMULATION_SPEED") {
+        Ok(var) => var.parse::<f64>().ok().filter(|speed| *speed > 0.0).unwrap_or(1.0),
+        _ => 1.0,
+    }
+}
+
+/// Synchronize the clock with `currentTime` from a BootNotification or Heartbeat response.
+///
+/// Simulated time starts from the time of CSMS which is taken only once, so it doesn't jump back with every
+/// response, and isn't synchronized at all if `SIMULATION_START_TIME` is set or in deterministic mode.
+/// Significant change of the offset is reported with SettingSystemTime security event.
+pub fn synchronize(current_time: &JsonValue) -> Result<(), EmulatorError> {
+    let current_time = match current_time.as_str().and_then(|date| DateTime::parse_from_rfc3339(date).ok()) {
+        Some(res) => res.with_timezone(&Utc),
+        None => return Ok(()),
+    };
+
+    if is_simulated() && (start_time().is_some() || storage::is_clock_synchronized()) {
+        return Ok(());
+    }
+
+    storage::set_clock_synchronized(true);
+
+    let offset = (current_time - local_now()).num_milliseconds();
+    let previous_offset = storage::get_clock_offset();
+
+    storage::set_clock_offset(offset);
+
+    if (offset - previous_offset).abs() >= SIGNIFICANT_OFFSET_CHANGE {
+        println!("Clock was set to {}.", current_time.to_rfc3339());
+
+        security_events::notify("SettingSystemTime", Some(&current_time.to_rfc3339()))?;
+    }
+
+    Ok(())
+}
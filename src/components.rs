@@ -1,11 +1,871 @@
-pub fn get_variable(component_name: &str, variable_name: &str) -> (&'static str, Option<&'static str>) {
+use std::fs;
+
+use json::JsonValue;
+
+use crate::client;
+use crate::config;
+use crate::connection;
+use crate::meter;
+use crate::monitoring;
+use crate::ocmf;
+use crate::security_events;
+use crate::storage;
+use crate::temperature;
+use crate::topology;
+
+// Measurands which are sampled by default.
+const DEFAULT_MEASURANDS: &str = "Energy.Active.Import.Register,Power.Active.Import,SoC,Voltage,Current.Import";
+// Measurands which are sampled by default at the beginning and at the end of transactions.
+const DEFAULT_TX_ENDED_MEASURANDS: &str = "Energy.Active.Import.Register";
+// Points at which transactions start and stop.
+const TX_POINTS: &str = "ParkingBayOccupancy,EVConnected,Authorized,DataSigned,PowerPathClosed,EnergyTransfer";
+
+// Variables of station wide components which are reported in base reports: component, variable, data type and mutability.
+const VARIABLES: [(&str, &str, &str, &str); 48] = [
+    ("AuthCtrlr", "AuthorizeRemoteStart", "boolean", "ReadWrite"),
+    ("AuthCtrlr", "LocalPreAuthorize", "boolean", "ReadWrite"),
+    ("AuthCtrlr", "LocalAuthorizeOffline", "boolean", "ReadWrite"),
+    ("AuthCtrlr", "OfflineTxForUnknownIdEnabled", "boolean", "ReadWrite"),
+    ("AlignedDataCtrlr", "Interval", "integer", "ReadWrite"),
+    ("AlignedDataCtrlr", "Measurands", "MemberList", "ReadWrite"),
+    ("AlignedDataCtrlr", "TxEndedInterval", "integer", "ReadWrite"),
+    ("AlignedDataCtrlr", "TxEndedMeasurands", "MemberList", "ReadWrite"),
+    ("AuthCacheCtrlr", "Enabled", "boolean", "ReadWrite"),
+    ("ChargingStation", "Power", "decimal", "ReadOnly"),
+    ("ChargingStation", "Model", "string", "ReadOnly"),
+    ("ChargingStation", "VendorName", "string", "ReadOnly"),
+    ("ChargingStation", "SerialNumber", "string", "ReadOnly"),
+    ("ChargingStation", "FirmwareVersion", "string", "ReadOnly"),
+    ("LocalAuthListCtrlr", "Enabled", "boolean", "ReadWrite"),
+    ("Modem", "ICCID", "string", "ReadOnly"),
+    ("Modem", "IMSI", "string", "ReadOnly"),
+    ("OCPPCommCtrlr", "HeartbeatInterval", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "MessageTimeout", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "MessageAttempts", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "MessageAttemptInterval", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "RetryBackOffWaitMinimum", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "RetryBackOffRepeatTimes", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "RetryBackOffRandomRange", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "NetworkConfigurationPriority", "SequenceList", "ReadOnly"),
+    ("OCPPCommCtrlr", "NetworkProfileConnectionAttempts", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "WebSocketPingInterval", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "OfflineThreshold", "integer", "ReadWrite"),
+    ("OCPPCommCtrlr", "QueueDepth", "integer", "ReadOnly"),
+    ("OCPPCommCtrlr", "QueueCapacity", "integer", "ReadOnly"),
+    ("SampledDataCtrlr", "TxUpdatedInterval", "integer", "ReadWrite"),
+    ("SampledDataCtrlr", "TxUpdatedMeasurands", "MemberList", "ReadWrite"),
+    ("SampledDataCtrlr", "TxStartedMeasurands", "MemberList", "ReadWrite"),
+    ("SampledDataCtrlr", "TxEndedMeasurands", "MemberList", "ReadWrite"),
+    ("SampledDataCtrlr", "TxEndedInterval", "integer", "ReadWrite"),
+    ("SampledDataCtrlr", "SignReadings", "boolean", "ReadWrite"),
+    ("SecurityCtrlr", "SecurityProfile", "integer", "ReadOnly"),
+    ("SecurityCtrlr", "OrganizationName", "string", "ReadWrite"),
+    ("SecurityCtrlr", "BasicAuthPassword", "passwordString", "WriteOnly"),
+    ("SecurityCtrlr", "CertificateEntries", "integer", "ReadOnly"),
+    ("SecurityCtrlr", "SecurityEvents", "MemberList", "ReadWrite"),
+    ("TariffCostCtrlr", "Currency", "string", "ReadWrite"),
+    ("TariffCostCtrlr", "TotalCost", "decimal", "ReadOnly"),
+    ("TxCtrlr", "StopTxOnInvalidId", "boolean", "ReadWrite"),
+    ("TxCtrlr", "MaxEnergyOnInvalidId", "integer", "ReadWrite"),
+    ("TxCtrlr", "TxStartPoint", "MemberList", "ReadWrite"),
+    ("TxCtrlr", "TxStopPoint", "MemberList", "ReadWrite"),
+    ("TxCtrlr", "EVConnectionTimeOut", "integer", "ReadWrite"),
+];
+// Requests which are limited by instances of DeviceDataCtrlr variables.
+const DEVICE_DATA_INSTANCES: [&str; 3] = ["GetReport", "GetVariables", "SetVariables"];
+// Bytes of a NotifyReport request besides its report data.
+const REPORT_MESSAGE_OVERHEAD: usize = 200;
+// Variables of EVSE and Connector components which are reported for each instance, availability comes first.
+const EVSE_VARIABLES: [(&str, &str); 4] = [("AvailabilityState", "OptionList"), ("Power", "decimal"), ("SupplyPhases", "integer"), ("Temperature", "decimal")];
+const CONNECTOR_VARIABLES: [(&str, &str); 5] = [
+    ("AvailabilityState", "OptionList"),
+    ("ConnectorType", "string"),
+    ("SupplyPhases", "integer"),
+    ("MaxCurrent", "decimal"),
+    ("MaxVoltage", "decimal"),
+];
+
+/// Read the device model definition of the charging station.
+///
+/// The file referenced by `DEVICE_MODEL_FILE` contains an array of component objects with `name`, optional
+/// `instance` and `evse` (`id` and optional `connectorId`) fields and a `variables` array. Each variable has
+/// a `name`, optional `instance`, `value`, `mutability`, `dataType`, `unit`, `minLimit`, `maxLimit` and
+/// `valuesList` fields, attributes other than `Actual` are listed in an optional `attributes` array of objects
+/// with `type`, `value` and `mutability` fields. Defined variables extend the built-in device model and
+/// override built-in variables with the same name, built-in values are used if the value is not defined.
+pub fn load() {
+    let path = match config::var("DEVICE_MODEL_FILE") {
+        Ok(var) => if var.is_empty() { return } else { var },
+        _ => return,
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(res) => res,
+        Err(e) => panic!("Couldn't read DEVICE_MODEL_FILE ({})", e),
+    };
+
+    let components = match json::parse(&content) {
+        Ok(result) => result,
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    };
+
+    let mut definitions: Vec<String> = Vec::new();
+
+    for config in components.members() {
+        let mut component = object!{ "name" => name(config, "component") };
+
+        if !config["instance"].is_null() {
+            component["instance"] = config["instance"].to_string().into();
+        }
+
+        if let Some(evse_id) = config["evse"]["id"].as_usize() {
+            component["evse"] = object!{ "id" => evse_id };
+
+            if let Some(connector_id) = config["evse"]["connectorId"].as_usize() {
+                component["evse"]["connectorId"] = connector_id.into();
+            }
+        }
+
+        for variable in config["variables"].members() {
+            definitions.push(definition(component.clone(), variable).dump());
+        }
+    }
+
+    println!("Device model variables: {}", definitions.len());
+
+    storage::set_device_model(definitions);
+}
+
+/// Build the definition of a variable in the form of report data of a GetBaseReport request.
+fn definition(component: JsonValue, config: &JsonValue) -> JsonValue {
+    let mut variable = object!{ "name" => name(config, "variable") };
+
+    if !config["instance"].is_null() {
+        variable["instance"] = config["instance"].to_string().into();
+    }
+
+    let mut actual_attribute = object!{
+        "type" => "Actual",
+        "mutability" => config["mutability"].as_str().unwrap_or("ReadWrite"),
+    };
+
+    if !config["value"].is_null() {
+        actual_attribute["value"] = config["value"].to_string().into();
+    }
+
+    let mut variable_attributes = array![actual_attribute];
+
+    for attribute in config["attributes"].members() {
+        let mut variable_attribute = object!{
+            "type" => attribute["type"].as_str().unwrap_or("Actual"),
+            "mutability" => attribute["mutability"].as_str().unwrap_or("ReadWrite"),
+        };
+
+        if !attribute["value"].is_null() {
+            variable_attribute["value"] = attribute["value"].to_string().into();
+        }
+
+        let _ = variable_attributes.push(variable_attribute);
+    }
+
+    let mut variable_characteristics = object!{
+        "dataType" => config["dataType"].as_str().unwrap_or("string"),
+        "supportsMonitoring" => false,
+    };
+
+    for field in ["unit", "minLimit", "maxLimit", "valuesList"].iter() {
+        if !config[*field].is_null() {
+            variable_characteristics[*field] = config[*field].clone();
+        }
+    }
+
+    object!{
+        "component" => component,
+        "variable" => variable,
+        "variableAttribute" => variable_attributes,
+        "variableCharacteristics" => variable_characteristics,
+    }
+}
+
+fn name(config: &JsonValue, kind: &str) -> String {
+    match config["name"].as_str() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => panic!("Every {} of the device model must have a name.", kind),
+    }
+}
+
+/// Get the name of a component or a variable which is given either as an object or as a plain name.
+pub fn component_name(component: &JsonValue) -> String {
+    if component.is_object() {
+        component["name"].to_string()
+    } else {
+        component.to_string()
+    }
+}
+
+/// Get a variable of a component which are given as objects of a GetVariables request.
+///
+/// Variables defined in the device model file take precedence over the built-in ones, values set by CSMS
+/// take precedence over the configured ones.
+pub fn get_component_variable(component: &JsonValue, variable: &JsonValue) -> (&'static str, Option<String>) {
+    let key = variable_key(component, variable);
+
+    if let Some(definition) = find_definition(&key) {
+        let value = storage::get_variable_value(&key)
+            .or_else(|| definition["variableAttribute"][0]["value"].as_str().map(String::from))
+            .or_else(|| built_in_variable(component, variable).1);
+
+        return ("Accepted", value);
+    }
+
+    match built_in_variable(component, variable) {
+        ("Accepted", value) => ("Accepted", storage::get_variable_value(&key).or(value)),
+        result => result,
+    }
+}
+
+/// Get an attribute of a variable of a component which are given as objects of a GetVariables request.
+///
+/// Returns `NotSupportedAttributeType` if the variable doesn't have an attribute of the type
+/// and `Rejected` if the attribute is write-only.
+pub fn get_component_attribute(component: &JsonValue, variable: &JsonValue, attribute_type: &str) -> (&'static str, Option<String>) {
+    let key = variable_key(component, variable);
+
+    let write_only = find_any_definition(&key)
+        .and_then(|definition| find_attribute(&definition, attribute_type))
+        .is_some_and(|variable_attribute| variable_attribute["mutability"] == "WriteOnly");
+
+    if write_only {
+        return ("Rejected", None);
+    }
+
+    if attribute_type == "Actual" {
+        return get_component_variable(component, variable);
+    }
+
+    match find_definition(&key) {
+        Some(definition) => match find_attribute(&definition, attribute_type) {
+            Some(variable_attribute) => {
+                let value = storage::get_variable_value(&attribute_key(&key, attribute_type))
+                    .or_else(|| variable_attribute["value"].as_str().map(String::from));
+
+                ("Accepted", value)
+            },
+            None => ("NotSupportedAttributeType", None),
+        },
+        // Built-in variables have actual values only.
+        None => match get_component_variable(component, variable) {
+            ("Accepted", _) => ("NotSupportedAttributeType", None),
+            result => result,
+        },
+    }
+}
+
+/// Store a value of a station wide variable which the station changes by itself.
+pub fn set_variable(component_name: &str, variable_name: &str, value: String) {
+    storage::set_variable_value(variable_key(&component_name.into(), &variable_name.into()), value);
+}
+
+/// Get a variable of a station wide component.
+pub fn get_variable(component_name: &str, variable_name: &str) -> (&'static str, Option<String>) {
+    get_component_variable(&component_name.into(), &variable_name.into())
+}
+
+/// Get a built-in variable of a component.
+///
+/// `EVSE` and `Connector` components are addressed by their `evse` field, built-in components have no instances.
+fn built_in_variable(component: &JsonValue, variable: &JsonValue) -> (&'static str, Option<String>) {
+    if !component["instance"].is_null() {
+        return ("UnknownComponent", None);
+    }
+
+    let variable_name: &str = &component_name(variable);
+
+    if !variable["instance"].is_null() {
+        return match (component_name(component).as_str(), variable["instance"].as_str()) {
+            ("DeviceDataCtrlr", Some(instance)) if DEVICE_DATA_INSTANCES.contains(&instance) => get_device_data_variable(variable_name),
+            _ => ("UnknownVariable", None),
+        };
+    }
+
+    let evse_index = component["evse"]["id"].as_usize()
+        .filter(|evse_id| *evse_id > 0 && *evse_id <= storage::evse_count())
+        .map(|evse_id| evse_id - 1);
+
+    match (component_name(component).as_str(), evse_index) {
+        ("EVSE", Some(evse_index)) => get_evse_variable(evse_index, variable_name),
+        ("Connector", Some(evse_index)) => {
+            match component["evse"]["connectorId"].as_usize() {
+                Some(connector_id) if connector_id > 0 && connector_id <= storage::connector_count(evse_index) => get_connector_variable(evse_index, connector_id - 1, variable_name),
+                _ => ("UnknownComponent", None),
+            }
+        },
+        ("EVSE", None) | ("Connector", None) => ("UnknownComponent", None),
+        (name, _) => configured_variable(name, variable_name),
+    }
+}
+
+fn get_device_data_variable(variable_name: &str) -> (&'static str, Option<String>) {
+    match variable_name {
+        "ItemsPerMessage" => ("Accepted", Some(env_or("ITEMS_PER_MESSAGE", "100"))),
+        "BytesPerMessage" => ("Accepted", Some(env_or("BYTES_PER_MESSAGE", "65000"))),
+        _ => ("UnknownVariable", None),
+    }
+}
+
+fn get_evse_variable(evse_index: usize, variable_name: &str) -> (&'static str, Option<String>) {
+    let connectors = storage::get_connectors(evse_index);
+
+    match variable_name {
+        // EVSE is available if any of its connectors is available, EVSE without connectors is unavailable.
+        "AvailabilityState" => {
+            let status = match storage::find_connector(evse_index, &["Available"]) {
+                Some(_) => "Available",
+                None => connectors.first().map_or("Unavailable", |connector| connector.status),
+            };
+
+            ("Accepted", Some(String::from(status)))
+        },
+        "Power" => ("Accepted", Some(meter::power(evse_index).to_string())),
+        "SupplyPhases" => ("Accepted", Some(connectors.iter().map(|connector| connector.phases).max().unwrap_or(0).to_string())),
+        // Vendor specific variable with the internal temperature (°C) of the EVSE.
+        "Temperature" => ("Accepted", Some(format!("{:.1}", temperature::get(evse_index)))),
+        _ => ("UnknownVariable", None),
+    }
+}
+
+fn get_connector_variable(evse_index: usize, connector_index: usize, variable_name: &str) -> (&'static str, Option<String>) {
+    let connector = match storage::get_connector(evse_index, connector_index) {
+        Some(res) => res,
+        None => return ("UnknownComponent", None),
+    };
+
+    match variable_name {
+        "AvailabilityState" => ("Accepted", Some(String::from(connector.status))),
+        "ConnectorType" => ("Accepted", Some(connector.connector_type)),
+        "SupplyPhases" => ("Accepted", Some(connector.phases.to_string())),
+        // Vendor specific variables with electrical limits of the connector.
+        "MaxCurrent" => ("Accepted", Some(connector.max_current.to_string())),
+        "MaxVoltage" => ("Accepted", Some(connector.max_voltage.to_string())),
+        _ => ("UnknownVariable", None),
+    }
+}
+
+/// Build report data of a GetBaseReport request.
+///
+/// Summary inventory contains availability of EVSE and connectors only.
+/// Returns `None` if the report base is not supported.
+pub fn base_report(report_base: &str) -> Option<Vec<JsonValue>> {
+    let summary = match report_base {
+        "ConfigurationInventory" | "FullInventory" => false,
+        "SummaryInventory" => true,
+        _ => return None,
+    };
+
+    let definitions = definitions();
+    let is_defined = |component: &JsonValue, variable_name: &str| {
+        let key = variable_key(component, &variable_name.into());
+
+        definitions.iter().any(|definition| variable_key(&definition["component"], &definition["variable"]) == key)
+    };
+
+    let mut report: Vec<JsonValue> = Vec::new();
+
+    if !summary {
+        report.extend(definitions.iter().cloned().map(report_item));
+
+        for definition in built_in_definitions() {
+            if !is_defined(&definition["component"], &definition["variable"]["name"].to_string()) {
+                report.push(report_item(definition));
+            }
+        }
+    }
+
+    let variable_count = |count: usize| if summary { 1 } else { count };
+
+    for evse_index in 0..storage::evse_count() {
+        let component = object!{
+            "name" => "EVSE",
+            "evse" => object!{ "id" => evse_index + 1 },
+        };
+
+        for (variable_name, data_type) in EVSE_VARIABLES.iter().take(variable_count(EVSE_VARIABLES.len())) {
+            if !is_defined(&component, variable_name) {
+                // Power of EVSE is bounded by its connectors.
+                let max_limit = if *variable_name == "Power" { Some(topology::max_power(evse_index)) } else { None };
+
+                report.push(report_item(built_in_definition(component.clone(), variable_name, data_type, "ReadOnly", max_limit)));
+            }
+        }
+
+        for connector_index in 0..storage::connector_count(evse_index) {
+            let component = object!{
+                "name" => "Connector",
+                "evse" => object!{ "id" => evse_index + 1, "connectorId" => connector_index + 1 },
+            };
+
+            for (variable_name, data_type) in CONNECTOR_VARIABLES.iter().take(variable_count(CONNECTOR_VARIABLES.len())) {
+                if !is_defined(&component, variable_name) {
+                    report.push(report_item(built_in_definition(component.clone(), variable_name, data_type, "ReadOnly", None)));
+                }
+            }
+        }
+    }
+
+    Some(report)
+}
+
+/// Fill in the current values of attributes of a variable definition.
+fn report_item(mut definition: JsonValue) -> JsonValue {
+    let component = definition["component"].clone();
+    let variable = definition["variable"].clone();
+
+    for variable_attribute in definition["variableAttribute"].members_mut() {
+        if let (_, Some(value)) = get_component_attribute(&component, &variable, &variable_attribute["type"].to_string()) {
+            variable_attribute["value"] = value.into();
+        }
+    }
+
+    definition
+}
+
+fn built_in_definition(component: JsonValue, variable_name: &str, data_type: &str, mutability: &str, max_limit: Option<f64>) -> JsonValue {
+    let mut variable_characteristics = object!{
+        "dataType" => data_type,
+        "supportsMonitoring" => false,
+    };
+
+    if let Some(data) = max_limit {
+        variable_characteristics["maxLimit"] = data.into();
+    }
+
+    let variable_attribute = object!{
+        "type" => "Actual",
+        "mutability" => mutability,
+    };
+
+    object!{
+        "component" => component,
+        "variable" => object!{ "name" => variable_name },
+        "variableAttribute" => array![variable_attribute],
+        "variableCharacteristics" => variable_characteristics,
+    }
+}
+
+fn built_in_definitions() -> Vec<JsonValue> {
+    let device_data_definitions = DEVICE_DATA_INSTANCES.iter().flat_map(|instance| {
+        ["ItemsPerMessage", "BytesPerMessage"].iter().map(move |variable_name| {
+            let mut definition = built_in_definition(object!{ "name" => "DeviceDataCtrlr" }, variable_name, "integer", "ReadOnly", None);
+
+            definition["variable"]["instance"] = (*instance).into();
+
+            definition
+        })
+    });
+
+    VARIABLES.iter()
+        .map(|(component_name, variable_name, data_type, mutability)| {
+            let mut definition = built_in_definition(object!{ "name" => *component_name }, variable_name, data_type, mutability, None);
+
+            // Lists accept only values which the station supports.
+            if variable_name.ends_with("Measurands") {
+                definition["variableCharacteristics"]["valuesList"] = meter::MEASURANDS.join(",").into();
+            } else if *variable_name == "TxStartPoint" || *variable_name == "TxStopPoint" {
+                definition["variableCharacteristics"]["valuesList"] = TX_POINTS.into();
+            } else if *variable_name == "SecurityEvents" {
+                definition["variableCharacteristics"]["valuesList"] = security_events::EVENT_TYPES.join(",").into();
+            }
+
+            definition
+        })
+        .chain(device_data_definitions)
+        .collect()
+}
+
+/// Check the size of a request against `DeviceDataCtrlr.ItemsPerMessage` and `DeviceDataCtrlr.BytesPerMessage`.
+///
+/// Returns the error code of CALLERROR if the request has too many items or bytes.
+pub fn check_message_limits(action: &str, items: usize, bytes: usize) -> Option<&'static str> {
+    if message_limit("BytesPerMessage", action).is_some_and(|limit| bytes > limit) {
+        return Some("FormatViolation");
+    }
+
+    if message_limit("ItemsPerMessage", action).is_some_and(|limit| items > limit) {
+        return Some("TooManyElements");
+    }
+
+    None
+}
+
+/// Split report data into parts which fit into NotifyReport requests.
+///
+/// Each part is limited by `DeviceDataCtrlr.ItemsPerMessage` and `DeviceDataCtrlr.BytesPerMessage` of GetReport.
+pub fn report_parts(report: Vec<JsonValue>) -> Vec<JsonValue> {
+    let items = message_limit("ItemsPerMessage", "GetReport").unwrap_or(usize::MAX).max(1);
+    let bytes = message_limit("BytesPerMessage", "GetReport").unwrap_or(usize::MAX);
+
+    let mut parts: Vec<JsonValue> = Vec::new();
+    let mut part: JsonValue = JsonValue::new_array();
+    let mut part_bytes: usize = REPORT_MESSAGE_OVERHEAD;
+
+    for item in report {
+        let item_bytes = item.dump().len() + 1;
+
+        if !part.is_empty() && (part.len() >= items || part_bytes + item_bytes > bytes) {
+            parts.push(part);
+
+            part = JsonValue::new_array();
+            part_bytes = REPORT_MESSAGE_OVERHEAD;
+        }
+
+        part_bytes += item_bytes;
+        part.push(item).unwrap();
+    }
+
+    parts.push(part);
+
+    parts
+}
+
+fn message_limit(variable_name: &str, action: &str) -> Option<usize> {
+    get_component_variable(&"DeviceDataCtrlr".into(), &object!{ "name" => variable_name, "instance" => action }).1
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|limit| *limit > 0)
+}
+
+/// Get the variable definitions which are loaded from the device model file.
+fn definitions() -> Vec<JsonValue> {
+    storage::get_device_model().iter()
+        .filter_map(|definition| json::parse(definition).ok())
+        .collect()
+}
+
+/// Find the definition of a variable, defined variables take precedence over the built-in ones.
+fn find_definition(key: &str) -> Option<JsonValue> {
+    definitions().into_iter().find(|definition| variable_key(&definition["component"], &definition["variable"]) == key)
+}
+
+/// Find the definition of a variable among the defined and the built-in variables.
+fn find_any_definition(key: &str) -> Option<JsonValue> {
+    find_definition(key).or_else(|| built_in_definitions().into_iter().find(|definition| variable_key(&definition["component"], &definition["variable"]) == key))
+}
+
+fn find_attribute(definition: &JsonValue, attribute_type: &str) -> Option<JsonValue> {
+    definition["variableAttribute"].members().find(|variable_attribute| variable_attribute["type"] == attribute_type).cloned()
+}
+
+/// Set an attribute of a variable of a component which are given as objects of a SetVariables request.
+///
+/// The value is stored in the device model if the attribute is writable and the value matches the variable characteristics.
+/// Returns the attribute status of the SetVariables response.
+pub fn set_component_variable(component: &JsonValue, variable: &JsonValue, attribute_type: &str, value: &str) -> &'static str {
+    let key = variable_key(component, variable);
+
+    let definition = match find_any_definition(&key) {
+        Some(res) => res,
+        // Variables of EVSE and connectors describe the hardware and can't be changed.
+        None => return match get_component_variable(component, variable).0 {
+            "Accepted" => "Rejected",
+            status => status,
+        },
+    };
+
+    let variable_attribute = match find_attribute(&definition, attribute_type) {
+        Some(res) => res,
+        None => return "NotSupportedAttributeType",
+    };
+
+    if variable_attribute["mutability"] == "ReadOnly" || !is_valid(&definition["variableCharacteristics"], value) {
+        return "Rejected";
+    }
+
+    // Numeric actual value must be within the limits which are set with MinSet and MaxSet attributes.
+    if let (Ok(number), "Actual") = (value.parse::<f64>(), attribute_type) {
+        let limit = |attribute_type: &str| get_component_attribute(component, variable, attribute_type).1.and_then(|limit| limit.parse::<f64>().ok());
+
+        if limit("MinSet").is_some_and(|min| number < min) || limit("MaxSet").is_some_and(|max| number > max) {
+            return "Rejected";
+        }
+    }
+
+    let previous_value = get_component_attribute(component, variable, attribute_type).1;
+
+    storage::set_variable_value(attribute_key(&key, attribute_type), value.to_string());
+
+    // Changed actual value is reported by monitors of the variable without waiting for the next check.
+    if attribute_type == "Actual" && previous_value.as_deref() != Some(value) {
+        if let Err(e) = monitoring::check_variable(component, variable) {
+            println!("Monitors of {} couldn't be checked ({})", key, e);
+        }
+    }
+
+    "Accepted"
+}
+
+fn configured_variable(component_name: &str, variable_name: &str) -> (&'static str, Option<String>) {
     match component_name {
         "AuthCtrlr" => {
             match variable_name {
-                "AuthorizeRemoteStart" => ("Accepted", Some("false")),
+                "AuthorizeRemoteStart" => ("Accepted", Some(env_or("AUTHORIZE_REMOTE_START", "false"))),
+                "LocalPreAuthorize" => ("Accepted", Some(env_or("LOCAL_PRE_AUTHORIZE", "true"))),
+                "LocalAuthorizeOffline" => ("Accepted", Some(env_or("LOCAL_AUTHORIZE_OFFLINE", "true"))),
+                "OfflineTxForUnknownIdEnabled" => ("Accepted", Some(env_or("OFFLINE_TX_FOR_UNKNOWN_ID_ENABLED", "false"))),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "AlignedDataCtrlr" => {
+            match variable_name {
+                "Interval" => ("Accepted", Some(env_or("ALIGNED_DATA_INTERVAL", "900"))),
+                "Measurands" => ("Accepted", Some(env_or("ALIGNED_DATA_MEASURANDS", DEFAULT_MEASURANDS))),
+                "TxEndedInterval" => ("Accepted", Some(env_or("ALIGNED_DATA_TX_ENDED_INTERVAL", "0"))),
+                "TxEndedMeasurands" => ("Accepted", Some(env_or("ALIGNED_DATA_TX_ENDED_MEASURANDS", DEFAULT_TX_ENDED_MEASURANDS))),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "AuthCacheCtrlr" => {
+            match variable_name {
+                "Enabled" => ("Accepted", Some(String::from("true"))),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "ChargingStation" => {
+            match variable_name {
+                "Power" => ("Accepted", Some(power().to_string())),
+                "Model" => ("Accepted", Some(env_or("MODEL", "Model"))),
+                "VendorName" => ("Accepted", Some(env_or("VENDOR_NAME", "Vendor name"))),
+                "SerialNumber" => ("Accepted", Some(config::var("SERIAL_NUMBER").unwrap_or_default())),
+                "FirmwareVersion" => ("Accepted", Some(config::var("FIRMWARE_VERSION").unwrap_or_default())),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "Modem" => {
+            match variable_name {
+                "ICCID" => ("Accepted", Some(config::var("MODEM_ICCID").unwrap_or_default())),
+                "IMSI" => ("Accepted", Some(config::var("MODEM_IMSI").unwrap_or_default())),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "LocalAuthListCtrlr" => {
+            match variable_name {
+                "Enabled" => ("Accepted", Some(String::from("true"))),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "OCPPCommCtrlr" => {
+            match variable_name {
+                // Interval is stored once CSMS sets it.
+                "HeartbeatInterval" => ("Accepted", Some(String::from("0"))),
+                "MessageTimeout" => ("Accepted", Some(env_or("MESSAGE_TIMEOUT", "10"))),
+                "MessageAttempts" => ("Accepted", Some(env_or("MESSAGE_ATTEMPTS", "3"))),
+                "MessageAttemptInterval" => ("Accepted", Some(env_or("MESSAGE_ATTEMPT_INTERVAL", "10"))),
+                "RetryBackOffWaitMinimum" => ("Accepted", Some(env_or("RETRY_BACK_OFF_WAIT_MINIMUM", "10"))),
+                "RetryBackOffRepeatTimes" => ("Accepted", Some(env_or("RETRY_BACK_OFF_REPEAT_TIMES", "5"))),
+                "RetryBackOffRandomRange" => ("Accepted", Some(env_or("RETRY_BACK_OFF_RANDOM_RANGE", "10"))),
+                "NetworkConfigurationPriority" => ("Accepted", Some(connection::configuration_priority())),
+                "NetworkProfileConnectionAttempts" => ("Accepted", Some(env_or("NETWORK_PROFILE_CONNECTION_ATTEMPTS", "3"))),
+                "WebSocketPingInterval" => ("Accepted", Some(env_or("WEB_SOCKET_PING_INTERVAL", "30"))),
+                "OfflineThreshold" => ("Accepted", Some(env_or("OFFLINE_THRESHOLD", "60"))),
+                // Number of messages waiting to be sent.
+                "QueueDepth" => ("Accepted", Some(storage::queue_size().to_string())),
+                "QueueCapacity" => ("Accepted", Some(storage::queue_capacity().to_string())),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "SampledDataCtrlr" => {
+            match variable_name {
+                "TxUpdatedInterval" => ("Accepted", Some(env_or("TX_UPDATED_INTERVAL", "60"))),
+                "TxUpdatedMeasurands" => ("Accepted", Some(env_or("TX_UPDATED_MEASURANDS", DEFAULT_MEASURANDS))),
+                "TxStartedMeasurands" => ("Accepted", Some(env_or("TX_STARTED_MEASURANDS", DEFAULT_TX_ENDED_MEASURANDS))),
+                "TxEndedMeasurands" => ("Accepted", Some(env_or("TX_ENDED_MEASURANDS", DEFAULT_TX_ENDED_MEASURANDS))),
+                "TxEndedInterval" => ("Accepted", Some(env_or("TX_ENDED_INTERVAL", "0"))),
+                // Readings are signed by default if the signing key is configured.
+                "SignReadings" => ("Accepted", Some(env_or("SIGN_READINGS", &ocmf::is_configured().to_string()))),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "SecurityCtrlr" => {
+            match variable_name {
+                "SecurityProfile" => ("Accepted", Some(client::security_profile().to_string())),
+                "OrganizationName" => ("Accepted", Some(config::var("VENDOR_NAME").unwrap_or_default())),
+                "BasicAuthPassword" => ("Accepted", client::basic_auth_password()),
+                "CertificateEntries" => ("Accepted", Some(storage::get_certificates().len().to_string())),
+                // Vendor specific writable variable with security event types which are reported.
+                "SecurityEvents" => ("Accepted", Some(security_events::enabled_events())),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "TxCtrlr" => {
+            match variable_name {
+                "StopTxOnInvalidId" => ("Accepted", Some(env_or("STOP_TX_ON_INVALID_ID", "true"))),
+                "MaxEnergyOnInvalidId" => ("Accepted", Some(env_or("MAX_ENERGY_ON_INVALID_ID", "0"))),
+                "TxStartPoint" => ("Accepted", Some(env_or("TX_START_POINT", "Authorized"))),
+                "TxStopPoint" => ("Accepted", Some(env_or("TX_STOP_POINT", "EVConnected,Authorized"))),
+                "EVConnectionTimeOut" => ("Accepted", Some(env_or("EV_CONNECTION_TIMEOUT", "60"))),
+                _ => ("UnknownVariable", None),
+            }
+        },
+        "TariffCostCtrlr" => {
+            match variable_name {
+                "Currency" => ("Accepted", Some(String::from("EUR"))),
+                "TotalCost" => ("Accepted", Some(total_cost())),
                 _ => ("UnknownVariable", None),
             }
         },
         _ => ("UnknownComponent", None),
     }
 }
+
+/// Build the key of a variable which identifies it in the device model.
+pub fn variable_key(component: &JsonValue, variable: &JsonValue) -> String {
+    let mut key = component_name(component);
+
+    if !component["instance"].is_null() {
+        key.push_str(&format!("({})", component["instance"]));
+    }
+
+    if let Some(evse_id) = component["evse"]["id"].as_usize() {
+        key.push_str(&format!("@{}", evse_id));
+
+        if let Some(connector_id) = component["evse"]["connectorId"].as_usize() {
+            key.push_str(&format!("/{}", connector_id));
+        }
+    }
+
+    key.push_str(&format!(".{}", component_name(variable)));
+
+    if !variable["instance"].is_null() {
+        key.push_str(&format!("({})", variable["instance"]));
+    }
+
+    key
+}
+
+/// Build the key of a variable attribute, actual values are stored with the key of the variable.
+fn attribute_key(key: &str, attribute_type: &str) -> String {
+    if attribute_type == "Actual" {
+        key.to_string()
+    } else {
+        format!("{}/{}", key, attribute_type)
+    }
+}
+
+/// Check whether the value matches the characteristics of a variable.
+///
+/// Numbers must be within the limits, the length of strings must not exceed the maximum limit and
+/// members of lists must be in the list of allowed values.
+fn is_valid(variable_characteristics: &JsonValue, value: &str) -> bool {
+    let values_list: Option<Vec<&str>> = variable_characteristics["valuesList"].as_str()
+        .map(|values| values.split(',').map(|value| value.trim()).collect());
+    let min_limit = variable_characteristics["minLimit"].as_f64().unwrap_or(f64::MIN);
+    let max_limit = variable_characteristics["maxLimit"].as_f64().unwrap_or(f64::MAX);
+
+    let number = match variable_characteristics["dataType"].as_str().unwrap_or("string") {
+        "integer" => value.parse::<i64>().ok().map(|number| number as f64),
+        "decimal" => value.parse::<f64>().ok(),
+        "boolean" => return value == "true" || value == "false",
+        "OptionList" => return values_list.is_none_or(|values| values.contains(&value)),
+        // Lists may be empty.
+        "MemberList" | "SequenceList" => return value.trim().is_empty() || values_list.is_none_or(|values| value.split(',').all(|member| values.contains(&member.trim()))),
+        _ => Some(value.chars().count() as f64),
+    };
+
+    match number {
+        Some(number) => number >= min_limit && number <= max_limit,
+        None => false,
+    }
+}
+
+/// Get a value configured with an environment variable or the default value if it's not set.
+fn env_or(name: &str, default: &str) -> String {
+    match config::var(name) {
+        Ok(var) => if var.is_empty() { default.to_string() } else { var },
+        _ => default.to_string(),
+    }
+}
+
+/// Get running cost of the ongoing session, costs of stopped transactions which wait for their last event aren't running.
+fn total_cost() -> String {
+    storage::get_transactions().into_iter()
+        .filter_map(|(_, transaction)| json::parse(&transaction).ok())
+        .filter(|transaction| !transaction["idToken"].is_null() && transaction["stoppedReason"].is_null())
+        .find_map(|transaction| transaction["totalCost"].as_f64())
+        .map_or_else(|| String::from("0"), |cost| cost.to_string())
+}
+
+/// Get power (W) which the charging station delivers at the moment.
+fn power() -> f64 {
+    (0..storage::evse_count()).map(meter::power).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_valid_checks_data_types_and_limits() {
+        let integer = object!{ "dataType" => "integer", "minLimit" => 0, "maxLimit" => 10 };
+
+        assert!(is_valid(&integer, "10"));
+        assert!(!is_valid(&integer, "11"));
+        assert!(!is_valid(&integer, "1.5"));
+        assert!(is_valid(&object!{ "dataType" => "decimal" }, "1.5"));
+        assert!(!is_valid(&object!{ "dataType" => "boolean" }, "yes"));
+
+        // Length of strings is limited by the maximum limit.
+        assert!(is_valid(&object!{ "dataType" => "string", "maxLimit" => 3 }, "abc"));
+        assert!(!is_valid(&object!{ "dataType" => "string", "maxLimit" => 3 }, "abcd"));
+    }
+
+    #[test]
+    fn is_valid_checks_list_members() {
+        let option_list = object!{ "dataType" => "OptionList", "valuesList" => "A,B" };
+        let member_list = object!{ "dataType" => "MemberList", "valuesList" => "A, B,C" };
+
+        assert!(is_valid(&option_list, "B"));
+        assert!(!is_valid(&option_list, "A,B"));
+        assert!(is_valid(&member_list, "A,C"));
+        assert!(is_valid(&member_list, ""));
+        assert!(!is_valid(&member_list, "A,D"));
+        assert!(is_valid(&object!{ "dataType" => "MemberList" }, "anything"));
+    }
+
+    fn item(name: &str) -> JsonValue {
+        object!{
+            "component" => object!{ "name" => "OCPPCommCtrlr" },
+            "variable" => object!{ "name" => name },
+        }
+    }
+
+    // Limits are set in the state of the test thread, so they don't leak into other tests.
+    #[test]
+    fn report_parts_follow_message_limits() {
+        let limit_key = |variable_name: &str| variable_key(&"DeviceDataCtrlr".into(), &object!{ "name" => variable_name, "instance" => "GetReport" });
+
+        storage::set_variable_value(limit_key("ItemsPerMessage"), String::from("2"));
+
+        let parts = report_parts(vec![item("A"), item("B"), item("C")]);
+
+        assert_eq!(parts.iter().map(|part| part.len()).collect::<Vec<usize>>(), vec![2, 1]);
+        assert_eq!(parts[1][0], item("C"));
+
+        // Every part holds at least one item even if it exceeds the byte limit.
+        let item_bytes = item("A").dump().len() + 1;
+
+        storage::set_variable_value(limit_key("ItemsPerMessage"), String::from("100"));
+        storage::set_variable_value(limit_key("BytesPerMessage"), (REPORT_MESSAGE_OVERHEAD + item_bytes).to_string());
+
+        assert_eq!(report_parts(vec![item("A"), item("B")]).len(), 2);
+
+        storage::set_variable_value(limit_key("BytesPerMessage"), String::from("1"));
+
+        assert_eq!(report_parts(vec![item("A"), item("B")]).len(), 2);
+        assert_eq!(report_parts(Vec::new()).len(), 1);
+    }
+
+    #[test]
+    fn identity_of_station_is_read_only() {
+        for variable_name in ["Model", "VendorName", "SerialNumber", "FirmwareVersion"].iter() {
+            assert_eq!(set_component_variable(&object!{ "name" => "ChargingStation" }, &object!{ "name" => *variable_name }, "Actual", "Changed"), "Rejected");
+        }
+    }
+}
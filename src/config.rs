@@ -0,0 +1,14 @@
+use std::env;
+
+use crate::storage;
+
+/// Get a configuration variable of the station which runs on the current thread.
+///
+/// Variables which the station is built with take precedence over the environment of the process, so stations of a
+/// fleet share the environment but differ in their own variables.
+pub fn var(key: &str) -> Result<String, env::VarError> {
+    match storage::get_config_variable(key) {
+        Some(res) => Ok(res),
+        None => env::var(key),
+    }
+}
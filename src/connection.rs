@@ -0,0 +1,240 @@
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use ws::connect;
+
+use crate::client;
+use crate::components;
+use crate::config;
+use crate::ids;
+use crate::proxy;
+use crate::storage;
+
+// Timeout of the check whether a CSMS endpoint is reachable.
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connect to CSMS and reconnect whenever the connection is lost.
+///
+/// Reconnect attempt waits `OCPPCommCtrlr.RetryBackOffWaitMinimum` seconds which are doubled after each failed
+/// attempt up to `OCPPCommCtrlr.RetryBackOffRepeatTimes` times, and a random delay of up to
+/// `OCPPCommCtrlr.RetryBackOffRandomRange` seconds. Message queue and device model state are kept across attempts.
+/// Station fails over to the next network profile after `OCPPCommCtrlr.NetworkProfileConnectionAttempts` failed attempts.
+/// The call returns when the station is stopped.
+pub fn run(station_id: &str) {
+    let endpoints = endpoints();
+    let mut attempt: u32 = 0;
+    let mut profile_attempts: u64 = 0;
+
+    // Messages queued by the client and offline simulation can't wait for space in the queue.
+    storage::set_client_thread();
+
+    while !storage::is_stop_requested() {
+        let slot = storage::get_network_slot();
+        let connection_string = connection_url(&endpoints[slot], station_id);
+
+        println!("Connecting to {} (network profile {}).", connection_string, slot + 1);
+
+        let connection_string = through_proxy(connection_string);
+
+        if let Err(e) = connect(connection_string, |out| { client::Client { out, heartbeat_timeout: None, queue_timeout: None, ping_timeout: None, awaiting_pong: false, heartbeat_interval: client::heartbeat_interval() } }) {
+            println!("Couldn't connect to CSMS ({})", e);
+        }
+
+        storage::set_online(false);
+
+        if storage::is_stop_requested() {
+            break;
+        }
+
+        // Backoff starts over after the connection was established.
+        if storage::take_connection_opened() {
+            attempt = 0;
+            profile_attempts = 0;
+
+            storage::take_previous_basic_auth_password();
+        } else {
+            profile_attempts += 1;
+
+            // CSMS hasn't accepted the new basic authentication password.
+            if let Some(password) = storage::take_previous_basic_auth_password() {
+                println!("Previous basic authentication password is restored.");
+
+                storage::set_basic_auth_password(Some(password));
+            }
+        }
+
+        if storage::take_reconnect() {
+            println!("Reconnecting to CSMS.");
+
+            continue;
+        }
+
+        if endpoints.len() > 1 && profile_attempts >= variable("NetworkProfileConnectionAttempts").max(1) {
+            let next = (slot + 1) % endpoints.len();
+
+            println!("Failing over from network profile {} to {}.", slot + 1, next + 1);
+
+            storage::set_network_slot(next);
+
+            attempt = 0;
+            profile_attempts = 0;
+        }
+
+        let interval = backoff_interval(attempt);
+
+        println!("Reconnecting to CSMS in {} ms.", interval);
+
+        // Simulated transactions go on while the station is offline.
+        client::run_offline(interval);
+
+        attempt = attempt.saturating_add(1);
+    }
+}
+
+/// Check on a background thread whether CSMS of a network profile with higher priority than the active one is
+/// reachable, so the connection isn't stalled while endpoints are probed.
+///
+/// `probed` is called when the check is finished.
+pub fn probe_higher_priority<F: FnOnce() + Send + 'static>(probed: F) {
+    let endpoints: Vec<String> = endpoints().into_iter().take(storage::get_network_slot()).collect();
+
+    storage::spawn(move || {
+        let slot = endpoints.iter().position(|endpoint| reachable(endpoint));
+
+        storage::set_reachable_network_slot(slot);
+
+        probed();
+    });
+}
+
+/// Switch to the reachable network profile with higher priority which was found by `probe_higher_priority`.
+///
+/// Returns `true` if the station has to reconnect using the new profile.
+pub fn try_higher_priority() -> bool {
+    match storage::take_reachable_network_slot() {
+        Some(slot) if slot < storage::get_network_slot() => {
+            println!("Returning to network profile {}.", slot + 1);
+
+            storage::set_network_slot(slot);
+
+            true
+        },
+        _ => false,
+    }
+}
+
+/// Get the network configuration slots in priority order, the active slot comes first.
+pub fn configuration_priority() -> String {
+    let active = storage::get_network_slot();
+
+    let mut slots: Vec<String> = vec![(active + 1).to_string()];
+    slots.extend((0..endpoints().len()).filter(|slot| *slot != active).map(|slot| (slot + 1).to_string()));
+
+    slots.join(",")
+}
+
+/// Get CSMS endpoints of the network profiles in priority order.
+///
+/// `CSMS_URL` contains one URL or a comma separated list of URLs.
+fn endpoints() -> Vec<String> {
+    let endpoints: Vec<String> = config::var("CSMS_URL").unwrap_or_default()
+        .split(',')
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+        .collect();
+
+    if endpoints.is_empty() {
+        panic!("CSMS_URL must contain at least one URL.");
+    }
+
+    endpoints
+}
+
+/// Build the connection URL `<base>/<stationId>` of the endpoint.
+///
+/// Station identity is URL-encoded as a path segment.
+fn connection_url(endpoint: &str, station_id: &str) -> String {
+    let mut url = match url::Url::parse(endpoint) {
+        Ok(res) => res,
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    };
+
+    match url.path_segments_mut() {
+        Ok(mut segments) => {
+            segments.pop_if_empty().push(station_id);
+        },
+        Err(_) => panic!("CSMS_URL {} can't have a path.", endpoint),
+    }
+
+    url.to_string()
+}
+
+/// Get the URL which the WebSocket client connects to.
+///
+/// Connection through a proxy goes to a local tunnel, the endpoint host is kept for the handshake and TLS.
+fn through_proxy(connection_string: String) -> String {
+    let mut url = match url::Url::parse(&connection_string) {
+        Ok(res) => res,
+        Err(_) => return connection_string,
+    };
+
+    let (host, port) = match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => (host.to_string(), port),
+        _ => return connection_string,
+    };
+
+    let local_address = match proxy::tunnel(&host, port) {
+        Some(res) => res,
+        None => {
+            storage::set_tunneled_endpoint(None);
+
+            return connection_string;
+        },
+    };
+
+    println!("Connecting through the proxy tunnel {}.", local_address);
+
+    storage::set_tunneled_endpoint(Some((host, port)));
+
+    if url.set_ip_host(local_address.ip()).is_err() || url.set_port(Some(local_address.port())).is_err() {
+        return connection_string;
+    }
+
+    url.to_string()
+}
+
+/// Check whether a TCP connection to the host of the endpoint can be opened.
+fn reachable(endpoint: &str) -> bool {
+    let url = match url::Url::parse(endpoint) {
+        Ok(res) => res,
+        Err(_) => return false,
+    };
+
+    let addresses = match (url.host_str().unwrap_or_default(), url.port_or_known_default().unwrap_or(80)).to_socket_addrs() {
+        Ok(res) => res,
+        Err(_) => return false,
+    };
+
+    addresses.into_iter().any(|address| TcpStream::connect_timeout(&address, REACHABILITY_TIMEOUT).is_ok())
+}
+
+/// Get the interval (ms) before the reconnect attempt.
+fn backoff_interval(attempt: u32) -> u64 {
+    let wait_minimum = variable("RetryBackOffWaitMinimum");
+    let repeat_times = variable("RetryBackOffRepeatTimes");
+    let random_range = variable("RetryBackOffRandomRange");
+
+    let interval = wait_minimum.saturating_mul(1 << attempt.min(repeat_times as u32).min(16));
+
+    let random: u64 = ids::random();
+
+    interval * 1000 + if random_range > 0 { random % (random_range * 1000) } else { 0 }
+}
+
+/// Get the value of an OCPPCommCtrlr variable.
+fn variable(variable_name: &str) -> u64 {
+    match components::get_variable("OCPPCommCtrlr", variable_name).1 {
+        Some(value) => value.parse().unwrap_or(0),
+        None => 0,
+    }
+}
@@ -0,0 +1,99 @@
+use json::JsonValue;
+
+use crate::clock;
+use crate::errors::EmulatorError;
+use crate::ids;
+use crate::requests;
+use crate::storage;
+
+// Maximum length of data in a single NotifyCustomerInformation request.
+const MAX_DATA_LENGTH: usize = 512;
+
+/// Handle a CustomerInformation request.
+///
+/// Looks up stored data of the customer, queues the report and clears the data if requested.
+/// Returns the status of the request: `Accepted`, `Rejected` or `Invalid`.
+pub fn handle(request_id: u64, payload: &JsonValue) -> Result<&'static str, EmulatorError> {
+    let report: bool = payload["report"].as_bool().unwrap_or(false);
+    let clear: bool = payload["clear"].as_bool().unwrap_or(false);
+
+    let references = [&payload["idToken"], &payload["customerCertificate"], &payload["customerIdentifier"]];
+
+    // Exactly one reference to the customer is required.
+    if references.iter().filter(|reference| !reference.is_null()).count() != 1 || (!report && !clear) {
+        return Ok("Invalid");
+    }
+
+    let id_token: Option<&str> = payload["idToken"]["idToken"].as_str()
+        .or_else(|| payload["customerIdentifier"].as_str());
+
+    let mut data: Vec<String> = Vec::new();
+
+    if let Some(id_token) = id_token {
+        data.append(&mut find(id_token)?);
+
+        if clear {
+            storage::delete_local_list_entry(id_token);
+        }
+    }
+
+    if report {
+        if data.is_empty() {
+            data.push(String::from("No customer information found."));
+        }
+
+        queue_report(request_id, &data.join("\n"))?;
+    }
+
+    Ok("Accepted")
+}
+
+/// Find stored data which refers to the id token.
+fn find(id_token: &str) -> Result<Vec<String>, EmulatorError> {
+    let mut data: Vec<String> = Vec::new();
+
+    let local_list_entry = storage::get_local_list_entry(id_token);
+
+    if !local_list_entry.is_empty() {
+        data.push(format!("Local authorization list: {}", local_list_entry));
+    }
+
+    for (transaction_id, transaction) in storage::get_transactions() {
+        if parse(&transaction)?["idToken"]["idToken"] == id_token {
+            data.push(format!("Transaction {}: {}", transaction_id, transaction));
+        }
+    }
+
+    for (reservation_id, reservation) in storage::get_reservations() {
+        if parse(&reservation)?["idToken"]["idToken"] == id_token {
+            data.push(format!("Reservation {}: {}", reservation_id, reservation));
+        }
+    }
+
+    Ok(data)
+}
+
+/// Queue the report split into NotifyCustomerInformation requests.
+fn queue_report(request_id: u64, report: &str) -> Result<(), EmulatorError> {
+    let chars: Vec<char> = report.chars().collect();
+    let chunks: Vec<String> = chars.chunks(MAX_DATA_LENGTH).map(|chunk| chunk.iter().collect()).collect();
+
+    let generated_at = clock::timestamp()?;
+
+    for (seq_no, chunk) in chunks.iter().enumerate() {
+        let tbc = seq_no + 1 < chunks.len();
+
+        let msg_id: &str = &ids::uuid();
+        let msg = requests::notify_customer_information(msg_id, chunk, tbc, seq_no as u64, &generated_at, request_id);
+
+        storage::set_message(msg_id.to_string(), msg.to_owned());
+
+        storage::queue_add(msg);
+    }
+
+    Ok(())
+}
+
+fn parse(value: &str) -> Result<JsonValue, EmulatorError> {
+    Ok(json::parse(value)?)
+}
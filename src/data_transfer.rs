@@ -0,0 +1,185 @@
+use std::fs;
+
+use json::JsonValue;
+
+use crate::authorization;
+use crate::charging_profiles;
+use crate::clock;
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::ev;
+use crate::external_limits;
+use crate::faults;
+use crate::id_tokens;
+use crate::plug_and_charge;
+use crate::security_events;
+use crate::snapshot;
+use crate::storage;
+use crate::transactions;
+
+// Vendor identifier of the emulator's own data transfer extensions.
+pub const VENDOR_ID: &str = "StationEmulator";
+
+/// Data transfer handler. Takes message id and data and returns response status and data.
+type Handler = fn(&JsonValue, &JsonValue) -> Result<(&'static str, Option<JsonValue>), EmulatorError>;
+
+/// Handlers of incoming data transfers by vendor id.
+const HANDLERS: [(&str, Handler); 1] = [
+    (VENDOR_ID, emulator_handler),
+];
+
+/// Dispatch an incoming DataTransfer request to the handler of its vendor.
+///
+/// Returns the status of the request and optional response data.
+pub fn handle(vendor_id: &str, message_id: &JsonValue, data: &JsonValue) -> Result<(&'static str, Option<JsonValue>), EmulatorError> {
+    match HANDLERS.iter().find(|(id, _)| *id == vendor_id) {
+        Some((_, handler)) => handler(message_id, data),
+        None => Ok(("UnknownVendorId", None)),
+    }
+}
+
+/// Handle data transfers of the emulator vendor.
+///
+/// `Echo` message returns received data back to CSMS.
+/// `SetConnectorFault` and `ClearConnectorFault` messages simulate hardware faults of a connector, an EVSE (`connectorId` 0)
+/// or the whole station (`evseId` 0), their data contains `evseId`, `connectorId`, optional `error` (`GroundFault`,
+/// `OverCurrentFailure` or `HighTemperature`) or `techCode` and optional `abortTransaction` flag.
+/// `PlugIn` and `PlugOut` messages simulate the cable of an EV being plugged in or out,
+/// their data contains `evseId` and `connectorId`.
+/// `SuspendEV` and `ResumeEV` messages simulate the connected EV pausing and resuming charging on its own,
+/// their data contains `evseId` and `connectorId`.
+/// `AdvanceClock` message advances the simulated clock by `seconds`.
+/// `SecurityEvent` message simulates a security event, its data contains `type` and optional `techInfo`.
+/// `SetExternalLimit` and `ClearExternalLimit` messages simulate limits of an external system,
+/// their data contains `evseId` (0 for the whole station), optional `source` (default `EMS`) and `limit` (W).
+/// `PresentIdToken` message presents an id token at an EVSE to start or stop a transaction, its data contains
+/// `evseId` and either `idToken` given as an object or as a plain RFID token or `driver` name from `ID_TOKENS_FILE`.
+/// `PlugAndCharge` message plugs in a simulated ISO 15118 EV, its data contains `evseId` and `eMAID`.
+/// `ExportSnapshot` message returns the state of the emulator and saves it to `SNAPSHOT_FILE` if it's configured.
+fn emulator_handler(message_id: &JsonValue, data: &JsonValue) -> Result<(&'static str, Option<JsonValue>), EmulatorError> {
+    // Data may be sent as a stringified object.
+    let data = match data.as_str() {
+        Some(res) => json::parse(res).unwrap_or_else(|_| data.clone()),
+        None => data.clone(),
+    };
+
+    let response = match message_id.as_str() {
+        Some("Echo") => ("Accepted", Some(data)),
+        Some("SetConnectorFault") | Some("ClearConnectorFault") => {
+            let (evse_id, connector_id) = match (data["evseId"].as_usize(), data["connectorId"].as_usize()) {
+                (Some(evse_id), Some(connector_id)) => (evse_id, connector_id),
+                _ => return Ok(("Rejected", None)),
+            };
+
+            if message_id == "SetConnectorFault" {
+                (faults::set(evse_id, connector_id, data["error"].as_str(), data["techCode"].as_str(), data["abortTransaction"] == true)?, None)
+            } else {
+                (faults::clear(evse_id, connector_id)?, None)
+            }
+        },
+        Some("PlugIn") | Some("PlugOut") => {
+            let (evse_index, connector_index) = match (data["evseId"].as_usize(), data["connectorId"].as_usize()) {
+                (Some(evse_id), Some(connector_id)) if evse_id > 0 && connector_id > 0 && connector_id <= storage::connector_count(evse_id - 1) => (evse_id - 1, connector_id - 1),
+                _ => return Ok(("Rejected", None)),
+            };
+
+            let accepted = if message_id == "PlugIn" {
+                transactions::plug_in(evse_index, connector_index)?
+            } else {
+                transactions::plug_out(evse_index, connector_index)?
+            };
+
+            (if accepted { "Accepted" } else { "Rejected" }, None)
+        },
+        Some("SuspendEV") | Some("ResumeEV") => {
+            let (evse_index, connector_index) = match (data["evseId"].as_usize(), data["connectorId"].as_usize()) {
+                (Some(evse_id), Some(connector_id)) if evse_id > 0 && connector_id > 0 && connector_id <= storage::connector_count(evse_id - 1) => (evse_id - 1, connector_id - 1),
+                _ => return Ok(("Rejected", None)),
+            };
+
+            if !ev::pause(evse_index, connector_index, message_id == "SuspendEV") {
+                return Ok(("Rejected", None));
+            }
+
+            // Charging state follows the demand of the EV right away.
+            charging_profiles::apply_limits();
+
+            ("Accepted", None)
+        },
+        Some("AdvanceClock") => {
+            match data["seconds"].as_i64() {
+                Some(seconds) if seconds > 0 => {
+                    clock::advance(seconds);
+
+                    ("Accepted", None)
+                },
+                _ => ("Rejected", None),
+            }
+        },
+        Some("SecurityEvent") => {
+            let event_type = match data["type"].as_str() {
+                Some(res) => res,
+                None => return Ok(("Rejected", None)),
+            };
+
+            security_events::notify(event_type, data["techInfo"].as_str())?;
+
+            ("Accepted", None)
+        },
+        Some("SetExternalLimit") => {
+            let limit = match data["limit"].as_f64() {
+                Some(res) => res,
+                None => return Ok(("Rejected", None)),
+            };
+
+            (external_limits::set(data["evseId"].as_usize().unwrap_or(0), data["source"].as_str().unwrap_or("EMS"), limit), None)
+        },
+        Some("ClearExternalLimit") => {
+            (external_limits::clear(data["evseId"].as_usize().unwrap_or(0), data["source"].as_str().unwrap_or("EMS")), None)
+        },
+        Some("PresentIdToken") => {
+            let evse_id = data["evseId"].as_usize().unwrap_or(0);
+
+            // Token is given directly or belongs to a configured driver.
+            let id_token = match data["driver"].as_str() {
+                Some(name) => id_tokens::driver(name),
+                None => id_tokens::parse(&data["idToken"]),
+            };
+
+            match id_token {
+                Some(id_token) => (authorization::present(evse_id, &id_token)?, None),
+                None => ("Rejected", None),
+            }
+        },
+        Some("PlugAndCharge") => {
+            let evse_id = data["evseId"].as_usize().unwrap_or(0);
+
+            (plug_and_charge::plug_in(evse_id, &data["eMAID"].to_string())?, None)
+        },
+        Some("ExportSnapshot") => {
+            let snapshot = snapshot::export();
+
+            snapshot::save(&snapshot);
+
+            ("Accepted", Some(snapshot))
+        },
+        _ => ("UnknownMessageId", None),
+    };
+
+    Ok(response)
+}
+
+/// Read vendor specific data transfers which are sent to CSMS after boot.
+///
+/// The file referenced by `DATA_TRANSFER_FILE` contains an array of objects with `vendorId`,
+/// optional `messageId` and optional `data` fields.
+pub fn configured_messages() -> Result<Vec<JsonValue>, EmulatorError> {
+    let path = match config::var("DATA_TRANSFER_FILE") {
+        Ok(var) => if var.is_empty() { return Ok(Vec::new()) } else { var },
+        _ => return Ok(Vec::new()),
+    };
+
+    let content = fs::read_to_string(&path)?;
+
+    Ok(json::parse(&content)?.members().cloned().collect())
+}
@@ -0,0 +1,111 @@
+use chrono::prelude::*;
+use json::JsonValue;
+
+use crate::clock;
+use crate::errors::EmulatorError;
+use crate::storage;
+
+// Supported message formats.
+const MESSAGE_FORMATS: [&str; 4] = ["ASCII", "HTML", "URI", "UTF8"];
+
+/// Validate and store a message from a SetDisplayMessage request.
+///
+/// Returns the status of the request.
+pub fn set(message: &JsonValue) -> &'static str {
+    let message_id: u64 = match message["id"].as_u64() {
+        Some(res) => res,
+        None => return "Rejected",
+    };
+
+    match message["priority"].as_str() {
+        Some("AlwaysFront") | Some("InFront") | Some("NormalCycle") => (),
+        _ => return "NotSupportedPriority",
+    };
+
+    match message["state"].as_str() {
+        None | Some("Charging") | Some("Faulted") | Some("Idle") | Some("Unavailable") => (),
+        _ => return "NotSupportedState",
+    };
+
+    match message["message"]["format"].as_str() {
+        Some(format) if MESSAGE_FORMATS.contains(&format) => (),
+        _ => return "NotSupportedMessageFormat",
+    };
+
+    if let Some(transaction_id) = message["transactionId"].as_str() {
+        if storage::get_transaction(transaction_id).is_empty() {
+            return "UnknownTransaction";
+        }
+    }
+
+    // Message with the same id replaces the existing one.
+    storage::set_display_message(message_id, message.dump());
+
+    "Accepted"
+}
+
+/// Get stored messages which match criteria from a GetDisplayMessages request.
+pub fn find(ids: &JsonValue, priority: &JsonValue, state: &JsonValue) -> Result<Vec<JsonValue>, EmulatorError> {
+    prune()?;
+
+    let mut messages: Vec<JsonValue> = storage::get_display_messages().into_iter()
+        .map(|(_, message)| parse(&message))
+        .collect::<Result<Vec<JsonValue>, EmulatorError>>()?
+        .into_iter()
+        .filter(|message| ids.is_empty() || ids.members().any(|id| id.as_u64() == message["id"].as_u64()))
+        .filter(|message| priority.is_null() || message["priority"] == *priority)
+        .filter(|message| state.is_null() || message["state"] == *state)
+        .collect();
+
+    messages.sort_by_key(|message| message["id"].as_u64());
+
+    Ok(messages)
+}
+
+/// Remove a message by its id.
+///
+/// Returns the status of the request: `Accepted` or `Unknown`.
+pub fn clear(message_id: u64) -> Result<&'static str, EmulatorError> {
+    prune()?;
+
+    if storage::get_display_message(message_id).is_empty() {
+        return Ok("Unknown");
+    }
+
+    storage::delete_display_message(message_id);
+
+    Ok("Accepted")
+}
+
+/// Remove messages which were set for a finished transaction.
+pub fn remove_transaction_messages(transaction_id: &str) -> Result<(), EmulatorError> {
+    for (id, message) in storage::get_display_messages() {
+        if parse(&message)?["transactionId"] == transaction_id {
+            storage::delete_display_message(id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove messages which are not going to be displayed anymore.
+pub fn prune() -> Result<(), EmulatorError> {
+    let now = clock::now();
+
+    for (id, message) in storage::get_display_messages() {
+        let end_date_time = parse(&message)?["endDateTime"].as_str()
+            .and_then(|res| DateTime::parse_from_rfc3339(res).ok());
+
+        if let Some(end_date_time) = end_date_time {
+            if end_date_time.with_timezone(&Utc) <= now {
+                storage::delete_display_message(id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse(value: &str) -> Result<JsonValue, EmulatorError> {
+    Ok(json::parse(value)?)
+}
@@ -0,0 +1,146 @@
+use json::JsonValue;
+
+use crate::authorization;
+use crate::clock;
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::id_tokens;
+use crate::ids;
+use crate::storage;
+use crate::transactions;
+
+// Default mean duration (s) of sessions of random drivers.
+const DEFAULT_SESSION_DURATION: f64 = 3600.0;
+
+/// Simulate drivers arriving at the station randomly and leaving after their sessions.
+///
+/// Drivers arrive at available EVSE with the rate of `DRIVER_ARRIVAL_RATE` per hour, plug in their EVs and present
+/// id tokens from `ID_TOKENS_FILE`. Sessions last `DRIVER_SESSION_DURATION` seconds on average distributed according
+/// to `DRIVER_SESSION_DISTRIBUTION`. A driver presents an invalid token with `DRIVER_INVALID_TOKEN_PROBABILITY` (%)
+/// and unplugs the EV without stopping the transaction with `DRIVER_UNPLUG_PROBABILITY` (%).
+pub fn tick() -> Result<(), EmulatorError> {
+    let rate = env_f64("DRIVER_ARRIVAL_RATE", 0.0);
+
+    if rate <= 0.0 {
+        return Ok(());
+    }
+
+    let now = clock::local_now().timestamp_millis();
+
+    leave(now)?;
+
+    if let Some(previous) = storage::get_meter_timestamp("drivers") {
+        let hours = (now - previous) as f64 / 3_600_000.0;
+
+        // Arrivals form a Poisson process.
+        if random() < 1.0 - (-rate * hours).exp() {
+            arrive(now)?;
+        }
+    }
+
+    storage::set_meter_timestamp("drivers".to_string(), now);
+
+    Ok(())
+}
+
+/// Let a driver plug in at a random available EVSE and present an id token.
+fn arrive(now: i64) -> Result<(), EmulatorError> {
+    let sessions = storage::get_driver_sessions();
+
+    let available: Vec<(usize, usize)> = (0..storage::evse_count())
+        .filter(|evse_index| sessions.iter().all(|(index, _)| index != evse_index))
+        .filter_map(|evse_index| storage::find_connector(evse_index, &["Available"]).map(|connector_index| (evse_index, connector_index)))
+        .collect();
+
+    if available.is_empty() {
+        return Ok(());
+    }
+
+    let (evse_index, connector_index) = available[(random() * available.len() as f64) as usize % available.len()];
+    let drivers = id_tokens::drivers();
+
+    // Drivers without configured tokens present random RFID cards.
+    let id_token = if random() * 100.0 < env_f64("DRIVER_INVALID_TOKEN_PROBABILITY", 0.0) {
+        random_token("INVALID")
+    } else if drivers.is_empty() {
+        random_token("DRIVER")
+    } else {
+        drivers[(random() * drivers.len() as f64) as usize % drivers.len()].clone()
+    };
+
+    let session = object!{
+        "connectorId" => connector_index + 1,
+        "idToken" => id_token.clone(),
+        "departure" => now + (session_duration() * 1000.0) as i64,
+        "unplug" => random() * 100.0 < env_f64("DRIVER_UNPLUG_PROBABILITY", 0.0),
+    };
+
+    println!("Driver arrives at EVSE {} with id token {}.", evse_index + 1, id_token["idToken"]);
+
+    storage::set_driver_session(evse_index, Some(session.dump()));
+
+    transactions::plug_in(evse_index, connector_index)?;
+    authorization::present(evse_index + 1, &id_token)?;
+
+    Ok(())
+}
+
+/// Let drivers whose sessions are over stop their transactions and unplug their EVs.
+fn leave(now: i64) -> Result<(), EmulatorError> {
+    for (evse_index, session) in storage::get_driver_sessions() {
+        let session = json::parse(&session)?;
+
+        if session["departure"].as_i64().is_some_and(|departure| departure > now) {
+            continue;
+        }
+
+        let connector_index = session["connectorId"].as_usize().unwrap_or(1).max(1) - 1;
+
+        println!("Driver leaves EVSE {}.", evse_index + 1);
+
+        // Transaction may have been stopped already, e.g. by CSMS.
+        if session["unplug"] != true && transactions::find(evse_index, connector_index).is_some() {
+            authorization::present(evse_index + 1, &session["idToken"])?;
+        }
+
+        transactions::plug_out(evse_index, connector_index)?;
+
+        storage::set_driver_session(evse_index, None);
+    }
+
+    Ok(())
+}
+
+/// Get a random session duration (s).
+///
+/// Durations are `exponential` (default), `uniform` up to twice the mean duration or `fixed`.
+fn session_duration() -> f64 {
+    let mean = env_f64("DRIVER_SESSION_DURATION", DEFAULT_SESSION_DURATION);
+
+    match config::var("DRIVER_SESSION_DISTRIBUTION").as_deref() {
+        Ok("fixed") => mean,
+        Ok("uniform") => random() * 2.0 * mean,
+        _ => -mean * (1.0 - random()).ln(),
+    }
+}
+
+fn random_token(prefix: &str) -> JsonValue {
+    object!{
+        "idToken" => format!("{}{}", prefix, format!("{:08X}", ids::random() as u32)),
+        "type" => "ISO14443",
+    }
+}
+
+/// Get a random number in the range [0, 1).
+fn random() -> f64 {
+    let random: u64 = ids::random();
+
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn env_f64(name: &str, default: f64) -> f64 {
+    match config::var(name) {
+        Ok(var) => var.parse::<f64>().unwrap_or(default),
+        _ => default,
+    }
+}
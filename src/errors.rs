@@ -0,0 +1,73 @@
+use std::error;
+use std::fmt;
+
+/// Error which occurs while a message of CSMS or an event of the simulation is handled.
+///
+/// Errors are reported to CSMS instead of stopping the emulator, only connection errors close the connection.
+#[derive(Debug)]
+pub enum EmulatorError {
+    // Message or stored data is not a valid JSON.
+    Parse(json::Error),
+    // Current date can't be represented.
+    InvalidDate,
+    // Message or stored data has a date which is not RFC 3339.
+    ParseDate(chrono::ParseError),
+    // Connector which isn't in the topology of the station. EVSE index and connector index.
+    UnknownConnector(usize, usize),
+    // File which the emulator is configured with couldn't be read.
+    Io(std::io::Error),
+    // Configuration variable which the station can't run without isn't set.
+    MissingVariable(&'static str),
+    // Message couldn't be sent or received.
+    Connection(Box<ws::Error>),
+}
+
+impl EmulatorError {
+    /// Get the error code of CALLERROR which reports the error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            EmulatorError::Connection(_) => "GenericError",
+            _ => "InternalError",
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::Parse(e) => write!(f, "Error during parsing: {}", e),
+            EmulatorError::InvalidDate => write!(f, "Current date is empty."),
+            EmulatorError::ParseDate(e) => write!(f, "Error during parsing of a date: {}", e),
+            EmulatorError::UnknownConnector(evse_index, connector_index) => write!(f, "Connector {} of EVSE {} doesn't exist.", connector_index + 1, evse_index + 1),
+            EmulatorError::Io(e) => write!(f, "Couldn't read a file: {}", e),
+            EmulatorError::MissingVariable(name) => write!(f, "Variable {} isn't set.", name),
+            EmulatorError::Connection(e) => write!(f, "Connection error: {}", e),
+        }
+    }
+}
+
+impl error::Error for EmulatorError {}
+
+impl From<json::Error> for EmulatorError {
+    fn from(e: json::Error) -> Self {
+        EmulatorError::Parse(e)
+    }
+}
+
+impl From<chrono::ParseError> for EmulatorError {
+    fn from(e: chrono::ParseError) -> Self {
+        EmulatorError::ParseDate(e)
+    }
+}
+
+impl From<std::io::Error> for EmulatorError {
+    fn from(e: std::io::Error) -> Self {
+        EmulatorError::Io(e)
+    }
+}
+
+impl From<ws::Error> for EmulatorError {
+    fn from(e: ws::Error) -> Self {
+        EmulatorError::Connection(Box::new(e))
+    }
+}
@@ -0,0 +1,103 @@
+use crate::config;
+use crate::storage;
+
+// Default parameters of the simulated EV.
+const DEFAULT_BATTERY_CAPACITY: f64 = 60000.0;
+const DEFAULT_INITIAL_SOC: f64 = 20.0;
+const DEFAULT_MAX_POWER: f64 = 50000.0;
+// State of charge (%) below which the EV doesn't discharge its battery.
+const DEFAULT_MIN_DISCHARGE_SOC: f64 = 20.0;
+// State of charge (%) from which the charging power tapers down to the minimum at 100 %.
+const TAPER_SOC: f64 = 80.0;
+const MIN_TAPER_POWER: f64 = 1000.0;
+
+/// Connect a simulated EV to the connector.
+///
+/// Battery capacity (Wh), initial state of charge (%) and maximum charging power (W) are read from
+/// `EV_BATTERY_CAPACITY`, `EV_INITIAL_SOC` and `EV_MAX_POWER`.
+pub fn plug_in(evse_index: usize, connector_index: usize) {
+    let capacity = env_f64("EV_BATTERY_CAPACITY", DEFAULT_BATTERY_CAPACITY);
+    let initial_soc = env_f64("EV_INITIAL_SOC", DEFAULT_INITIAL_SOC).clamp(0.0, 100.0);
+
+    let ev = storage::Ev {
+        capacity,
+        energy: capacity * initial_soc / 100.0,
+        max_power: env_f64("EV_MAX_POWER", DEFAULT_MAX_POWER),
+        paused: false,
+    };
+
+    storage::set_connector_ev(evse_index, connector_index, Some(ev));
+}
+
+/// Store energy (Wh) delivered to the EV which is connected to the connector, negative energy discharges the EV.
+pub fn charge(evse_index: usize, connector_index: usize, energy: f64) {
+    if let Some(mut ev) = storage::get_connector(evse_index, connector_index).and_then(|connector| connector.ev) {
+        ev.energy = (ev.energy + energy).clamp(0.0, ev.capacity);
+
+        storage::set_connector_ev(evse_index, connector_index, Some(ev));
+    }
+}
+
+/// Pause or resume charging on request of the EV which is connected to the connector.
+///
+/// Returns `false` if no simulated EV is connected.
+pub fn pause(evse_index: usize, connector_index: usize, paused: bool) -> bool {
+    match storage::get_connector(evse_index, connector_index).and_then(|connector| connector.ev) {
+        Some(mut ev) => {
+            ev.paused = paused;
+
+            storage::set_connector_ev(evse_index, connector_index, Some(ev));
+
+            true
+        },
+        None => false,
+    }
+}
+
+/// Get the state of charge (%) of the EV which is connected to the EVSE.
+pub fn soc(evse_index: usize) -> Option<f64> {
+    storage::get_connectors(evse_index).into_iter()
+        .find_map(|connector| connector.ev)
+        .map(|ev| ev.energy / ev.capacity * 100.0)
+}
+
+/// Get the power (W) which the EV connected to the connector accepts.
+///
+/// Power tapers down when the battery is almost full and is zero for the full battery or the paused EV.
+/// Returns `None` if no simulated EV is connected.
+pub fn demand(evse_index: usize, connector_index: usize) -> Option<f64> {
+    let ev = storage::get_connector(evse_index, connector_index)?.ev?;
+    let soc = ev.energy / ev.capacity * 100.0;
+
+    if soc >= 100.0 || ev.paused {
+        return Some(0.0);
+    }
+
+    if soc <= TAPER_SOC {
+        return Some(ev.max_power);
+    }
+
+    Some((ev.max_power * (100.0 - soc) / (100.0 - TAPER_SOC)).max(MIN_TAPER_POWER.min(ev.max_power)))
+}
+
+/// Get the power (W) which the EV connected to the connector is able to discharge.
+///
+/// EV doesn't discharge below `EV_MIN_DISCHARGE_SOC` (%) or while it has paused charging.
+/// Returns `None` if no simulated EV is connected.
+pub fn supply(evse_index: usize, connector_index: usize) -> Option<f64> {
+    let ev = storage::get_connector(evse_index, connector_index)?.ev?;
+    let soc = ev.energy / ev.capacity * 100.0;
+
+    if ev.paused || soc <= env_f64("EV_MIN_DISCHARGE_SOC", DEFAULT_MIN_DISCHARGE_SOC) {
+        return Some(0.0);
+    }
+
+    Some(ev.max_power)
+}
+
+fn env_f64(name: &str, default: f64) -> f64 {
+    match config::var(name) {
+        Ok(var) => var.parse::<f64>().unwrap_or(default),
+        _ => default,
+    }
+}
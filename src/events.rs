@@ -0,0 +1,118 @@
+use json::JsonValue;
+
+use crate::clock;
+use crate::errors::EmulatorError;
+use crate::ids;
+use crate::requests;
+use crate::storage;
+
+/// Queue a NotifyEvent request with a single event.
+///
+/// Event id and timestamp are assigned to the event. Returns the id of the event.
+pub fn notify(mut event_data: JsonValue) -> Result<u64, EmulatorError> {
+    let now = clock::timestamp()?;
+
+    let event_id = storage::next_event_id();
+
+    event_data["eventId"] = event_id.into();
+    event_data["timestamp"] = now.to_owned().into();
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::notify_event(msg_id, &now, 0, array![event_data], false);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+
+    Ok(event_id)
+}
+
+/// Notify CSMS about the changed availability state of the connector.
+pub fn availability_changed(evse_id: usize, connector_id: usize, status: &str) -> Result<(), EmulatorError> {
+    let event_data = object!{
+        "trigger" => "Delta",
+        "actualValue" => status,
+        "component" => connector_component(evse_id, connector_id),
+        "eventNotificationType" => "HardWiredNotification",
+        "variable" => object!{
+            "name" => "AvailabilityState",
+        },
+    };
+
+    notify(event_data)?;
+
+    Ok(())
+}
+
+/// Raise a hardware problem of the component.
+///
+/// The problem is reported only once until it is cleared.
+pub fn raise(component: JsonValue, variable_name: &str, tech_code: &str) -> Result<(), EmulatorError> {
+    let key = event_key(&component, variable_name);
+
+    if storage::get_active_event(&key).is_some() {
+        return Ok(());
+    }
+
+    let event_data = object!{
+        "trigger" => "Alerting",
+        "actualValue" => "true",
+        "techCode" => tech_code,
+        "component" => component,
+        "eventNotificationType" => "HardWiredNotification",
+        "variable" => object!{
+            "name" => variable_name,
+        },
+    };
+
+    let event_id = notify(event_data)?;
+
+    storage::set_active_event(key, Some(event_id));
+
+    Ok(())
+}
+
+/// Clear a previously raised hardware problem of the component.
+///
+/// The cleared event refers to the raised one as its cause.
+pub fn clear(component: JsonValue, variable_name: &str) -> Result<(), EmulatorError> {
+    let key = event_key(&component, variable_name);
+
+    let cause = match storage::get_active_event(&key) {
+        Some(res) => res,
+        None => return Ok(()),
+    };
+
+    let event_data = object!{
+        "trigger" => "Alerting",
+        "actualValue" => "false",
+        "cause" => cause,
+        "cleared" => true,
+        "component" => component,
+        "eventNotificationType" => "HardWiredNotification",
+        "variable" => object!{
+            "name" => variable_name,
+        },
+    };
+
+    notify(event_data)?;
+
+    storage::set_active_event(key, None);
+
+    Ok(())
+}
+
+/// Get the device model component of the connector.
+pub fn connector_component(evse_id: usize, connector_id: usize) -> JsonValue {
+    object!{
+        "name" => "Connector",
+        "evse" => object!{
+            "id" => evse_id,
+            "connectorId" => connector_id,
+        },
+    }
+}
+
+fn event_key(component: &JsonValue, variable_name: &str) -> String {
+    format!("{}.{}", component.dump(), variable_name)
+}
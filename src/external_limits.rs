@@ -0,0 +1,96 @@
+use json::JsonValue;
+
+use crate::charging_profiles;
+use crate::ids;
+use crate::requests;
+use crate::storage;
+
+/// Apply a limit imposed by an external system (e.g. EMS).
+///
+/// EVSE id 0 limits each EVSE of the charging station, negative limit requests discharging of EVs.
+/// The limit is reported with NotifyChargingLimit once it bounds the power of an EVSE.
+/// Returns `Accepted` if the limit is applied or `Rejected` if the EVSE doesn't exist.
+pub fn set(evse_id: usize, source: &str, limit: f64) -> &'static str {
+    if evse_id > storage::evse_count() || source == "CSO" {
+        return "Rejected";
+    }
+
+    let external_limit = object!{
+        "evseId" => evse_id,
+        "chargingLimitSource" => source,
+        "limit" => limit,
+    };
+
+    storage::set_external_limit(key(evse_id, source), Some(external_limit.dump()));
+
+    charging_profiles::apply_limits();
+
+    "Accepted"
+}
+
+/// Remove an external limit and send ClearedChargingLimit.
+///
+/// Returns `Accepted` if the limit is removed or `Rejected` if there is no such limit.
+pub fn clear(evse_id: usize, source: &str) -> &'static str {
+    if !storage::get_external_limits().iter().any(|(stored_key, _)| *stored_key == key(evse_id, source)) {
+        return "Rejected";
+    }
+
+    storage::set_external_limit(key(evse_id, source), None);
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::cleared_charging_limit(msg_id, source, evse_id_field(evse_id));
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+
+    charging_profiles::apply_limits();
+
+    "Accepted"
+}
+
+/// Get the lowest external limit (W) which applies to the EVSE together with its source.
+pub fn limit(evse_index: usize) -> Option<(f64, String)> {
+    storage::get_external_limits().into_iter()
+        .filter_map(|(_, external_limit)| json::parse(&external_limit).ok())
+        .filter(|external_limit| external_limit["evseId"] == 0 || external_limit["evseId"] == evse_index + 1)
+        .filter_map(|external_limit| external_limit["limit"].as_f64().map(|limit| (limit, external_limit["chargingLimitSource"].to_string())))
+        .fold(None, |lowest: Option<(f64, String)>, (limit, source)| match lowest {
+            Some(lowest) if lowest.0 <= limit => Some(lowest),
+            _ => Some((limit, source)),
+        })
+}
+
+/// Report the limit which bounds the power of the EVSE with NotifyChargingLimit.
+pub fn notify(evse_index: usize, source: &str, limit: f64) {
+    let charging_limit = object!{
+        "chargingLimitSource" => source,
+        "isGridCritical" => false,
+    };
+
+    let charging_schedule = array![object!{
+        "id" => 1,
+        "chargingRateUnit" => "W",
+        "chargingSchedulePeriod" => array![object!{
+            "startPeriod" => 0,
+            "limit" => limit,
+        }],
+    }];
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::notify_charging_limit(msg_id, charging_limit, Some((evse_index + 1).into()), charging_schedule);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+fn key(evse_id: usize, source: &str) -> String {
+    format!("{}:{}", evse_id, source)
+}
+
+/// Limits of the whole charging station are sent without EVSE id.
+fn evse_id_field(evse_id: usize) -> Option<JsonValue> {
+    if evse_id == 0 { None } else { Some(evse_id.into()) }
+}
@@ -0,0 +1,96 @@
+use json::JsonValue;
+
+use crate::charging_profiles;
+use crate::errors::EmulatorError;
+use crate::events;
+use crate::state_machine;
+use crate::state_machine::Event;
+use crate::storage;
+use crate::transactions;
+
+// Errors which are simulated by fault injection.
+const ERRORS: [&str; 3] = ["GroundFault", "OverCurrentFailure", "HighTemperature"];
+// Tech code of a fault without a specific error.
+const DEFAULT_TECH_CODE: &str = "Fault";
+
+/// Put connectors into `Faulted` state with the given error or a custom tech code.
+///
+/// Connector id 0 faults all connectors of the EVSE and EVSE id 0 faults the whole station, the problem is reported
+/// with NotifyEvent for the faulted component. Transactions of faulted connectors are stopped with reason `Other`
+/// if `abort_transaction` is set, otherwise charging is suspended by the EVSE.
+/// Returns the status of the request: `Accepted` or `Rejected`.
+pub fn set(evse_id: usize, connector_id: usize, error: Option<&str>, tech_code: Option<&str>, abort_transaction: bool) -> Result<&'static str, EmulatorError> {
+    let tech_code = match error {
+        Some(error) if ERRORS.contains(&error) => error,
+        Some(_) => return Ok("Rejected"),
+        None => tech_code.unwrap_or(DEFAULT_TECH_CODE),
+    };
+
+    let (component, connectors) = match affected(evse_id, connector_id) {
+        Some(res) => res,
+        None => return Ok("Rejected"),
+    };
+
+    for (evse_index, connector_index) in connectors {
+        let charging = state_machine::charging_state(evse_index, connector_index) == "Charging";
+
+        state_machine::handle(evse_index, connector_index, Event::Fault);
+
+        match transactions::find(evse_index, connector_index) {
+            Some(transaction_id) if abort_transaction => transactions::stop(&transaction_id, "AbnormalCondition", "Other")?,
+            // Charging is suspended by the faulted EVSE.
+            _ if charging => transactions::charging_state_changed(evse_index, connector_index)?,
+            _ => (),
+        }
+    }
+
+    events::raise(component, "Problem", tech_code)?;
+
+    Ok("Accepted")
+}
+
+/// Clear a fault which was injected with `set` for the same EVSE and connector ids.
+///
+/// Returns the status of the request: `Accepted` or `Rejected`.
+pub fn clear(evse_id: usize, connector_id: usize) -> Result<&'static str, EmulatorError> {
+    let (component, connectors) = match affected(evse_id, connector_id) {
+        Some(res) => res,
+        None => return Ok("Rejected"),
+    };
+
+    for (evse_index, connector_index) in connectors {
+        state_machine::handle(evse_index, connector_index, Event::ClearFault);
+    }
+
+    events::clear(component, "Problem")?;
+
+    // Interrupted charging is resumed.
+    charging_profiles::apply_limits();
+
+    Ok("Accepted")
+}
+
+/// Get the component which reports the fault together with indexes of its connectors.
+///
+/// Returns `None` if the EVSE or the connector doesn't exist.
+fn affected(evse_id: usize, connector_id: usize) -> Option<(JsonValue, Vec<(usize, usize)>)> {
+    if evse_id == 0 {
+        let connectors = (0..storage::evse_count())
+            .flat_map(|evse_index| (0..storage::connector_count(evse_index)).map(move |connector_index| (evse_index, connector_index)))
+            .collect();
+
+        return Some((object!{ "name" => "ChargingStation" }, connectors));
+    }
+
+    if evse_id > storage::evse_count() || connector_id > storage::connector_count(evse_id - 1) {
+        return None;
+    }
+
+    if connector_id == 0 {
+        let connectors = (0..storage::connector_count(evse_id - 1)).map(|connector_index| (evse_id - 1, connector_index)).collect();
+
+        return Some((object!{ "name" => "EVSE", "evse" => object!{ "id" => evse_id } }, connectors));
+    }
+
+    Some((events::connector_component(evse_id, connector_id), vec![(evse_id - 1, connector_id - 1)]))
+}
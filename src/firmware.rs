@@ -0,0 +1,238 @@
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use json::JsonValue;
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+
+use crate::client;
+use crate::clock;
+use crate::ids;
+use crate::requests;
+use crate::security_events;
+use crate::storage;
+
+// Default interval between download attempts (s).
+const DEFAULT_RETRY_INTERVAL: u64 = 30;
+// Interval between checks of the update state (ms).
+const POLL_INTERVAL: u64 = 1000;
+
+/// Start a firmware update from an UpdateFirmware request.
+///
+/// Download and installation run in the background and report their progress
+/// through FirmwareStatusNotification requests.
+pub fn update(request_id: u64, payload: &JsonValue) -> &'static str {
+    let location: String = match payload["firmware"]["location"].as_str() {
+        Some(res) => res.to_string(),
+        None => return "Rejected",
+    };
+
+    let retrieve_date_time = match parse_date(&payload["firmware"]["retrieveDateTime"]) {
+        Some(res) => res,
+        None => return "Rejected",
+    };
+
+    let install_date_time = parse_date(&payload["firmware"]["installDateTime"]);
+
+    // Signed firmware is verified with the public key of its signing certificate.
+    let signature: Option<(X509, Vec<u8>)> = match (payload["firmware"]["signingCertificate"].as_str(), payload["firmware"]["signature"].as_str()) {
+        (Some(certificate), Some(signature)) => match (X509::from_pem(certificate.as_bytes()), base64::decode_block(signature)) {
+            (Ok(certificate), Ok(signature)) => Some((certificate, signature)),
+            _ => {
+                if let Err(e) = security_events::notify("InvalidFirmwareSigningCertificate", None) {
+                    println!("Invalid firmware signing certificate couldn't be reported ({})", e);
+                }
+
+                return "InvalidCertificate";
+            },
+        },
+        _ => None,
+    };
+    let retries: u64 = payload["retries"].as_u64().unwrap_or(0);
+    let retry_interval: u64 = payload["retryInterval"].as_u64().unwrap_or(DEFAULT_RETRY_INTERVAL);
+
+    // New request cancels the ongoing update.
+    let status = match storage::get_firmware_request() {
+        Some(_) => "AcceptedCanceled",
+        None => "Accepted",
+    };
+
+    storage::set_firmware_request(Some(request_id));
+
+    storage::spawn(move || run(request_id, &location, retrieve_date_time, install_date_time, retries, retry_interval, signature));
+
+    status
+}
+
+/// Download and install firmware.
+fn run(request_id: u64, location: &str, retrieve_date_time: DateTime<Utc>, install_date_time: Option<DateTime<Utc>>, retries: u64, retry_interval: u64, signature: Option<(X509, Vec<u8>)>) {
+    if retrieve_date_time > clock::now() {
+        notify(request_id, "DownloadScheduled");
+
+        if !wait_until(request_id, retrieve_date_time) {
+            return;
+        }
+    }
+
+    // Download firmware.
+
+    let mut firmware: Option<Vec<u8>> = None;
+
+    for attempt in 0..=retries {
+        if !is_active(request_id) {
+            return;
+        }
+
+        if attempt > 0 && !wait_until(request_id, clock::now() + chrono::Duration::seconds(retry_interval as i64)) {
+            return;
+        }
+
+        notify(request_id, "Downloading");
+
+        match download(location) {
+            Ok(data) => {
+                firmware = Some(data);
+                break;
+            },
+            Err(e) => println!("Firmware download failed: {}", e),
+        };
+    }
+
+    let firmware = match firmware {
+        Some(data) => data,
+        None => {
+            notify(request_id, "DownloadFailed");
+            storage::set_firmware_request(None);
+            return;
+        },
+    };
+
+    let path = env::temp_dir().join(format!("firmware-{}", request_id));
+
+    if let Err(e) = fs::write(&path, &firmware) {
+        println!("Couldn't save firmware ({})", e);
+        notify(request_id, "DownloadFailed");
+        storage::set_firmware_request(None);
+        return;
+    }
+
+    println!("Firmware was saved to {:?}.", path);
+
+    notify(request_id, "Downloaded");
+
+    if let Some((certificate, signature)) = signature {
+        if !verify(&firmware, &certificate, &signature) {
+            notify(request_id, "InvalidSignature");
+            if let Err(e) = security_events::notify("InvalidFirmwareSignature", None) {
+                println!("Invalid firmware signature couldn't be reported ({})", e);
+            }
+            storage::set_firmware_request(None);
+            return;
+        }
+
+        notify(request_id, "SignatureVerified");
+    }
+
+    // Install firmware.
+
+    if let Some(install_date_time) = install_date_time {
+        if install_date_time > clock::now() {
+            notify(request_id, "InstallScheduled");
+
+            if !wait_until(request_id, install_date_time) {
+                return;
+            }
+        }
+    }
+
+    // Installation waits for ongoing transactions to finish.
+    while !storage::get_transactions().is_empty() {
+        if !is_active(request_id) {
+            return;
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL));
+    }
+
+    notify(request_id, "Installing");
+
+    if firmware.is_empty() {
+        notify(request_id, "InstallationFailed");
+        storage::set_firmware_request(None);
+        return;
+    }
+
+    notify(request_id, "Installed");
+    storage::set_firmware_request(None);
+
+    // Reboot with the new firmware.
+    client::queue_boot_notification("FirmwareUpdate");
+}
+
+/// Verify the SHA256 signature of firmware.
+fn verify(firmware: &[u8], certificate: &X509, signature: &[u8]) -> bool {
+    let public_key = match certificate.public_key() {
+        Ok(res) => res,
+        Err(_) => return false,
+    };
+
+    let verified = match Verifier::new(MessageDigest::sha256(), &public_key) {
+        Ok(mut verifier) => verifier.update(firmware).is_ok() && verifier.verify(signature).unwrap_or(false),
+        Err(_) => false,
+    };
+
+    verified
+}
+
+/// Download a file.
+pub fn download(location: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(location).call().map_err(|e| e.to_string())?;
+
+    let mut data: Vec<u8> = Vec::new();
+
+    response.into_reader().read_to_end(&mut data).map_err(|e| e.to_string())?;
+
+    Ok(data)
+}
+
+/// Queue a FirmwareStatusNotification request.
+fn notify(request_id: u64, status: &str) {
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::firmware_status_notification(msg_id, status, request_id);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+/// Wait until the given moment.
+///
+/// Returns `false` if the update was cancelled in the meantime.
+fn wait_until(request_id: u64, moment: DateTime<Utc>) -> bool {
+    while clock::now() < moment {
+        if !is_active(request_id) {
+            return false;
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL));
+    }
+
+    is_active(request_id)
+}
+
+fn is_active(request_id: u64) -> bool {
+    storage::get_firmware_request() == Some(request_id)
+}
+
+fn parse_date(value: &JsonValue) -> Option<DateTime<Utc>> {
+    match value.as_str() {
+        Some(res) => DateTime::parse_from_rfc3339(res).ok().map(|date| date.with_timezone(&Utc)),
+        None => None,
+    }
+}
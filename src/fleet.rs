@@ -0,0 +1,81 @@
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+use crate::errors::EmulatorError;
+use crate::{Station, StationHandle};
+
+// Default interval between starts of stations (ms).
+const DEFAULT_START_INTERVAL: u64 = 100;
+// Placeholder of the station number in values of environment variables.
+const STATION_NUMBER: &str = "{n}";
+// Variables which are made distinct for every station even without the placeholder.
+const IDENTITY_VARIABLES: [&str; 2] = ["STATION_ID", "SERIAL_NUMBER"];
+
+/// Fleet of stations which run on threads of the current process to load test CSMS.
+///
+/// Every station has its own state and shares the configuration of the environment in which `{n}` is replaced
+/// with the number of the station, e.g. `TOPOLOGY_FILE=topology-{n}.json`. `STATION_ID` and `SERIAL_NUMBER` get
+/// the number as a suffix if they don't contain it.
+#[derive(Debug)]
+pub struct Fleet {
+    size: usize,
+}
+
+impl Fleet {
+    /// Create a fleet of `STATION_COUNT` stations.
+    ///
+    /// Returns `None` unless more than one station is configured.
+    pub fn from_env() -> Option<Fleet> {
+        match env::var("STATION_COUNT").ok().and_then(|var| var.parse::<usize>().ok()) {
+            Some(size) if size > 1 => Some(Fleet { size }),
+            _ => None,
+        }
+    }
+
+    /// Start every station of the fleet `STATION_START_INTERVAL` (ms) after the previous one.
+    ///
+    /// The call returns when all stations have stopped.
+    pub fn run(&self) {
+        let start_interval = env::var("STATION_START_INTERVAL").ok().and_then(|var| var.parse::<u64>().ok()).unwrap_or(DEFAULT_START_INTERVAL);
+
+        let mut stations: Vec<(String, StationHandle)> = Vec::new();
+
+        for number in 1..=self.size {
+            if number > 1 {
+                thread::sleep(Duration::from_millis(start_interval));
+            }
+
+            match station(number) {
+                Ok(station) => stations.push((station.station_id().to_string(), station.start())),
+                Err(e) => println!("Station {} of the fleet couldn't be started ({})", number, e),
+            }
+        }
+
+        println!("Fleet of {} stations is started.", stations.len());
+
+        for (station_id, handle) in stations {
+            handle.join();
+
+            println!("Station {} has stopped.", station_id);
+        }
+    }
+}
+
+/// Build the station with the given number from the environment.
+fn station(number: usize) -> Result<Station, EmulatorError> {
+    let mut builder = Station::builder();
+
+    // Variables which aren't valid unicode can't contain the placeholder.
+    let variables = env::vars_os().filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+
+    for (name, value) in variables {
+        if value.contains(STATION_NUMBER) {
+            builder = builder.env(&name, &value.replace(STATION_NUMBER, &number.to_string()));
+        } else if IDENTITY_VARIABLES.contains(&name.as_str()) && !value.is_empty() {
+            builder = builder.env(&name, &format!("{}-{}", value, number));
+        }
+    }
+
+    builder.build()
+}
@@ -0,0 +1,81 @@
+use std::fs;
+
+use json::JsonValue;
+
+use crate::config;
+
+// Types of id tokens defined by OCPP.
+const ID_TOKEN_TYPES: [&str; 8] = ["Central", "eMAID", "ISO14443", "ISO15693", "KeyCode", "Local", "MacAddress", "NoAuthorization"];
+// Type of plain tokens which are read from RFID cards.
+const DEFAULT_ID_TOKEN_TYPE: &str = "ISO14443";
+
+/// Build a typed id token from a plain token or an id token object.
+///
+/// Returns `None` if the token, its type or its additional info is invalid.
+pub fn parse(value: &JsonValue) -> Option<JsonValue> {
+    if let Some(token) = value.as_str() {
+        return Some(object!{ "idToken" => token, "type" => DEFAULT_ID_TOKEN_TYPE });
+    }
+
+    let token = value["idToken"].as_str()?;
+    let id_token_type = value["type"].as_str().unwrap_or(DEFAULT_ID_TOKEN_TYPE);
+
+    if !ID_TOKEN_TYPES.contains(&id_token_type) {
+        return None;
+    }
+
+    let mut id_token = object!{
+        "idToken" => token,
+        "type" => id_token_type,
+    };
+
+    if !value["additionalInfo"].is_null() {
+        let mut additional_info: JsonValue = JsonValue::new_array();
+
+        for info in value["additionalInfo"].members() {
+            let _ = additional_info.push(object!{
+                "additionalIdToken" => info["additionalIdToken"].as_str()?,
+                "type" => info["type"].as_str()?,
+            });
+        }
+
+        id_token["additionalInfo"] = additional_info;
+    }
+
+    Some(id_token)
+}
+
+/// Get the id token of a simulated driver.
+///
+/// The file referenced by `ID_TOKENS_FILE` contains an array of objects with `driver` name and `idToken` fields.
+pub fn driver(name: &str) -> Option<JsonValue> {
+    let drivers = read_drivers()?;
+    let entry = drivers.members().find(|entry| entry["driver"] == name)?;
+
+    parse(&entry["idToken"])
+}
+
+/// Get id tokens of all simulated drivers from `ID_TOKENS_FILE`.
+pub fn drivers() -> Vec<JsonValue> {
+    match read_drivers() {
+        Some(drivers) => drivers.members().filter_map(|entry| parse(&entry["idToken"])).collect(),
+        None => Vec::new(),
+    }
+}
+
+fn read_drivers() -> Option<JsonValue> {
+    let path = match config::var("ID_TOKENS_FILE") {
+        Ok(var) => if var.is_empty() { return None } else { var },
+        _ => return None,
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(res) => res,
+        Err(e) => panic!("Couldn't read ID_TOKENS_FILE ({})", e),
+    };
+
+    match json::parse(&content) {
+        Ok(result) => Some(result),
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    }
+}
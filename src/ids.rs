@@ -0,0 +1,43 @@
+use uuid::Uuid;
+
+use crate::config;
+use crate::storage;
+
+/// Generate a unique id of a message or a transaction.
+///
+/// Deterministic mode which is enabled with `DETERMINISTIC_SEED` generates sequential UUIDs following the seed,
+/// otherwise ids are random UUID v4.
+pub fn uuid() -> String {
+    match seed() {
+        Some(seed) => format!("00000000-0000-4000-8000-{:012x}", seed.wrapping_add(storage::next_id_sequence()) & 0xffff_ffff_ffff),
+        None => Uuid::new_v4().to_string(),
+    }
+}
+
+/// Generate a random number.
+///
+/// Numbers of deterministic mode form a sequence which is the same for the same seed.
+pub fn random() -> u64 {
+    match seed() {
+        Some(seed) => {
+            // SplitMix64 of the seeded sequence.
+            let mut value = seed.wrapping_add(storage::next_random_sequence().wrapping_mul(0x9e37_79b9_7f4a_7c15));
+
+            value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+            value ^ (value >> 31)
+        },
+        // UUID v4 is used as a source of randomness.
+        None => Uuid::new_v4().as_bytes()[..8].iter().fold(0, |random, byte| (random << 8) | *byte as u64),
+    }
+}
+
+/// Check if deterministic mode is enabled with `DETERMINISTIC_SEED`.
+pub fn is_deterministic() -> bool {
+    seed().is_some()
+}
+
+fn seed() -> Option<u64> {
+    config::var("DETERMINISTIC_SEED").ok().and_then(|var| var.parse::<u64>().ok())
+}
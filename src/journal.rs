@@ -0,0 +1,130 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use json::JsonValue;
+
+use crate::config;
+use crate::storage;
+use crate::transactions;
+
+/// Append a TransactionEvent request to `TRANSACTION_JOURNAL_FILE` before it's queued.
+///
+/// Each line of the journal holds the id and the state of the transaction together with the request.
+pub fn add_event(transaction_id: &str, transaction: &JsonValue, msg: &str) {
+    let entry = object!{
+        "transactionId" => transaction_id,
+        "transaction" => transaction.clone(),
+        "message" => json::parse(msg).unwrap_or(JsonValue::Null),
+    };
+
+    append(&entry);
+}
+
+/// Record in the journal that CSMS has received the TransactionEvent request with the given message id.
+pub fn acknowledge(msg_id: &str) {
+    append(&object!{ "ack" => msg_id });
+}
+
+/// Recover transactions which were active when the emulator stopped unexpectedly.
+///
+/// Requests which CSMS hasn't received are queued again. Transactions without "Ended" request which are not
+/// restored otherwise, e.g. from a snapshot, are resumed and ended with reason `PowerLoss`, while the station is
+/// offline their requests are flagged as `offline`. The journal starts over with the recovered requests.
+pub fn recover() {
+    let path = match journal_file() {
+        Some(res) => res,
+        None => return,
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(res) => res,
+        Err(_) => return,
+    };
+
+    // Requests which weren't acknowledged and the last state of transactions which weren't ended.
+    let mut pending: Vec<JsonValue> = Vec::new();
+    let mut active: Vec<(String, JsonValue)> = Vec::new();
+
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        // Last line may be incomplete if the emulator stopped while writing it.
+        let entry = match json::parse(line) {
+            Ok(result) => result,
+            Err(_) => {
+                println!("Incomplete journal entry is skipped: {}", line);
+
+                continue;
+            },
+        };
+
+        if let Some(msg_id) = entry["ack"].as_str() {
+            pending.retain(|event| event["message"][1] != msg_id);
+
+            continue;
+        }
+
+        let transaction_id = entry["transactionId"].to_string();
+
+        active.retain(|(id, _)| *id != transaction_id);
+
+        if entry["message"][3]["eventType"] != "Ended" {
+            active.push((transaction_id, entry["transaction"].clone()));
+        }
+
+        pending.push(entry);
+    }
+
+    if let Err(e) = fs::write(&path, "") {
+        println!("Couldn't reset the transaction journal ({})", e);
+    }
+
+    let queued = storage::queue_messages();
+
+    for entry in pending {
+        let msg_id = entry["message"][1].to_string();
+        let msg = entry["message"].dump();
+
+        // Requests restored from a snapshot are queued already.
+        if !queued.iter().any(|queued_msg| json::parse(queued_msg).is_ok_and(|parsed_msg| parsed_msg[1] == msg_id.as_str())) {
+            storage::set_message(msg_id, msg.to_owned());
+            storage::queue_add(msg);
+        }
+
+        append(&entry);
+    }
+
+    for (transaction_id, transaction) in active {
+        if !storage::get_transaction(&transaction_id).is_empty() {
+            continue;
+        }
+
+        println!("Transaction {} was interrupted and is ended.", transaction_id);
+
+        storage::set_transaction(transaction_id.to_owned(), transaction.dump());
+
+        if let Err(e) = transactions::end(&transaction_id, "AbnormalCondition", "PowerLoss") {
+            println!("Transaction {} couldn't be ended ({})", transaction_id, e);
+        }
+    }
+}
+
+fn append(entry: &JsonValue) {
+    let path = match journal_file() {
+        Some(res) => res,
+        None => return,
+    };
+
+    let result = OpenOptions::new().create(true).append(true).open(&path)
+        .and_then(|mut file| writeln!(file, "{}", entry.dump()));
+
+    if let Err(e) = result {
+        println!("Couldn't write to the transaction journal ({})", e);
+    }
+}
+
+fn journal_file() -> Option<String> {
+    match config::var("TRANSACTION_JOURNAL_FILE") {
+        Ok(var) => if var.is_empty() { None } else { Some(var) },
+        _ => None,
+    }
+}
@@ -0,0 +1,233 @@
+//! Electric vehicle charging station emulator which supports OCPP 2.0 and 2.0.1.
+//!
+//! The emulator is configured with environment variables which are described in `.env.example`.
+//! `Station` sets them up programmatically to embed the emulator, e.g. in integration tests of a CSMS.
+//!
+//! Every station keeps its own state and configuration, variables which aren't given to `StationBuilder` are taken
+//! from the environment of the process. `Station::start` runs a station on a background thread until it's stopped
+//! with `StationHandle::stop`, so a process may run several stations at once. `Fleet` runs a number of stations
+//! configured with the environment this way.
+
+extern crate mio_extras;
+extern crate time;
+#[macro_use]
+extern crate json;
+extern crate chrono;
+
+use std::thread::{self, JoinHandle};
+
+pub use errors::EmulatorError;
+pub use fleet::Fleet;
+pub use storage::{Connector, Ev, MemoryStorage, Storage};
+
+use storage::State;
+
+mod requests;
+mod responses;
+mod components;
+mod storage;
+mod clock;
+mod ids;
+mod client;
+mod schemas;
+mod errors;
+mod snapshot;
+mod journal;
+mod authorization;
+mod reservations;
+mod charging_profiles;
+mod display_messages;
+mod data_transfer;
+mod firmware;
+mod publish_firmware;
+mod logs;
+mod customer_information;
+mod monitoring;
+mod events;
+mod faults;
+mod certificates;
+mod plug_and_charge;
+mod security_events;
+mod transactions;
+mod retries;
+mod meter;
+mod ocmf;
+mod external_limits;
+mod load_management;
+mod topology;
+mod temperature;
+mod state_machine;
+mod ev;
+mod id_tokens;
+mod drivers;
+mod connection;
+mod proxy;
+mod fleet;
+mod config;
+
+/// Charging station which connects to CSMS.
+#[derive(Debug)]
+pub struct Station {
+    csms_url: String,
+    station_id: String,
+    state: &'static State,
+}
+
+/// Handle of a charging station which runs on a background thread.
+#[derive(Debug)]
+pub struct StationHandle {
+    thread: JoinHandle<()>,
+    state: &'static State,
+}
+
+/// Builder of a charging station.
+///
+/// Settings which aren't given are taken from the environment.
+#[derive(Debug, Default)]
+pub struct StationBuilder {
+    variables: Vec<(String, String)>,
+    storage: Option<Box<dyn Storage>>,
+}
+
+impl Station {
+    /// Start building a charging station.
+    pub fn builder() -> StationBuilder {
+        StationBuilder::default()
+    }
+
+    /// Initialize the station and run its WebSocket client on the current thread.
+    ///
+    /// The station connects to CSMS and reconnects whenever the connection is lost, so the call returns only when
+    /// the station is stopped with the handle of `start`.
+    pub fn run(&self) {
+        storage::enter(self.state);
+
+        topology::load();
+        components::load();
+        meter::load_registers();
+        snapshot::load();
+        journal::recover();
+        certificates::load_station_certificate();
+
+        println!("CSMS url: {:?}", self.csms_url);
+        println!("Station id: {:?}", self.station_id);
+
+        connection::run(&self.station_id);
+    }
+
+    /// Run the station on a background thread.
+    pub fn start(self) -> StationHandle {
+        let state = self.state;
+
+        storage::with_state(state, || storage::set_stop_requested(false));
+
+        StationHandle {
+            thread: thread::spawn(move || self.run()),
+            state,
+        }
+    }
+
+    /// Get the id which the station uses to identify itself.
+    pub fn station_id(&self) -> &str {
+        &self.station_id
+    }
+}
+
+impl StationHandle {
+    /// Stop the station and wait until it's finished.
+    ///
+    /// The connection to CSMS is closed and the station doesn't reconnect.
+    pub fn stop(self) {
+        storage::with_state(self.state, storage::request_stop);
+
+        self.join();
+    }
+
+    /// Check if the station is still running.
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Wait until the station is finished.
+    pub(crate) fn join(self) {
+        if self.thread.join().is_err() {
+            println!("Station thread has panicked.");
+        }
+    }
+}
+
+impl StationBuilder {
+    /// Set the id which the station uses to identify itself (`STATION_ID`).
+    pub fn station_id(self, station_id: &str) -> Self {
+        self.env("STATION_ID", station_id)
+    }
+
+    /// Set the URL of CSMS or a comma separated list of URLs of fallback network profiles (`CSMS_URL`).
+    pub fn csms_url(self, csms_url: &str) -> Self {
+        self.env("CSMS_URL", csms_url)
+    }
+
+    /// Set the identity which the station reports in BootNotification.
+    pub fn identity(self, vendor_name: &str, model: &str, serial_number: &str, firmware_version: &str) -> Self {
+        self.env("VENDOR_NAME", vendor_name)
+            .env("MODEL", model)
+            .env("SERIAL_NUMBER", serial_number)
+            .env("FIRMWARE_VERSION", firmware_version)
+    }
+
+    /// Set the number of EVSE and connectors of each EVSE (`EVSE_COUNT` and `CONNECTORS_PER_EVSE`).
+    pub fn evses(self, evse_count: usize, connectors_per_evse: usize) -> Self {
+        self.env("EVSE_COUNT", &evse_count.to_string())
+            .env("CONNECTORS_PER_EVSE", &connectors_per_evse.to_string())
+    }
+
+    /// Set the path to a JSON file with the station topology (`TOPOLOGY_FILE`).
+    pub fn topology_file(self, path: &str) -> Self {
+        self.env("TOPOLOGY_FILE", path)
+    }
+
+    /// Set whether the cable is plugged in and out together with starting and stopping transactions (`AUTO_PLUG`).
+    pub fn auto_plug(self, auto_plug: bool) -> Self {
+        self.env("AUTO_PLUG", &auto_plug.to_string())
+    }
+
+    /// Set the simulation speed multiplier (`SIMULATION_SPEED`).
+    pub fn simulation_speed(self, speed: f64) -> Self {
+        self.env("SIMULATION_SPEED", &speed.to_string())
+    }
+
+    /// Set the backend which keeps the state of the station instead of the in-memory `MemoryStorage`.
+    pub fn storage(mut self, storage: Box<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Set any other behavior of the station with its environment variable from `.env.example`.
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.variables.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Build the charging station with its own state.
+    ///
+    /// Returns an error if the CSMS URL or the station id are neither given nor set in the environment.
+    pub fn build(self) -> Result<Station, EmulatorError> {
+        let state = State::create(self.variables.into_iter().collect());
+        let backend = self.storage;
+
+        storage::with_state(state, || {
+            if let Some(backend) = backend {
+                storage::set_backend(backend);
+            }
+
+            let csms_url = config::var("CSMS_URL").map_err(|_| EmulatorError::MissingVariable("CSMS_URL"))?;
+            let station_id = config::var("STATION_ID").map_err(|_| EmulatorError::MissingVariable("STATION_ID"))?;
+
+            Ok(Station {
+                csms_url,
+                station_id,
+                state,
+            })
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use crate::config;
+use crate::ev;
+use crate::storage;
+use crate::transactions;
+
+/// Distribute the site capacity among connectors which offer power in their ongoing transactions.
+///
+/// Offers are given as EVSE index, connector index and offered power (W). Without `SITE_CAPACITY` (W) every
+/// connector gets the power it offers, discharging connectors always do. Otherwise connectors share the capacity according to `LOAD_BALANCING`:
+/// `equal` (default) shares it equally and passes power which an EV doesn't take to the others, `fifo` serves
+/// transactions in the order of their start.
+/// Returns the power (W) allocated to each offer.
+pub fn allocate(offers: &[(usize, usize, f64)]) -> Vec<f64> {
+    let capacity = match config::var("SITE_CAPACITY").ok().and_then(|var| var.parse::<f64>().ok()) {
+        Some(res) if res >= 0.0 => res,
+        _ => return offers.iter().map(|(_, _, power)| *power).collect(),
+    };
+
+    // EV doesn't claim more than it accepts.
+    let claims: Vec<f64> = offers.iter()
+        .map(|(evse_index, connector_index, power)| ev::demand(*evse_index, *connector_index).map_or(*power, |demand| power.min(demand)).max(0.0))
+        .collect();
+
+    let mut allocated: Vec<f64> = offers.iter().map(|(_, _, power)| power.min(0.0)).collect();
+    let mut remaining = capacity;
+
+    if config::var("LOAD_BALANCING").is_ok_and(|var| var == "fifo") {
+        let mut order: Vec<usize> = (0..offers.len()).filter(|index| claims[*index] > 0.0).collect();
+        order.sort_by_key(|index| started_at(offers[*index].0, offers[*index].1));
+
+        for index in order {
+            allocated[index] = claims[index].min(remaining);
+            remaining -= allocated[index];
+        }
+    } else {
+        let mut unsatisfied: Vec<usize> = (0..offers.len()).filter(|index| claims[*index] > 0.0).collect();
+
+        // Power left over by EVs which need less than the share is distributed again.
+        while !unsatisfied.is_empty() && remaining > 0.001 {
+            let share = remaining / unsatisfied.len() as f64;
+
+            for index in unsatisfied.iter() {
+                let power = (claims[*index] - allocated[*index]).min(share);
+
+                allocated[*index] += power;
+                remaining -= power;
+            }
+
+            unsatisfied.retain(|index| claims[*index] - allocated[*index] > 0.001);
+        }
+    }
+
+    allocated
+}
+
+/// Get the moment (RFC 3339) at which the transaction on the connector was started.
+fn started_at(evse_index: usize, connector_index: usize) -> String {
+    transactions::find(evse_index, connector_index)
+        .and_then(|transaction_id| json::parse(&storage::get_transaction(&transaction_id)).ok())
+        .and_then(|transaction| transaction["startedAt"].as_str().map(String::from))
+        .unwrap_or_default()
+}
@@ -0,0 +1,146 @@
+use std::thread;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use json::JsonValue;
+
+use crate::ids;
+use crate::requests;
+use crate::storage;
+
+// Default interval between upload attempts (s).
+const DEFAULT_RETRY_INTERVAL: u64 = 30;
+// Interval between checks of the upload state (ms).
+const POLL_INTERVAL: u64 = 1000;
+// Boundary of the multipart upload body.
+const BOUNDARY: &str = "station-emulator-log";
+
+/// Start a log upload from a GetLog request.
+///
+/// Returns the status of the request and the name of the uploaded file.
+/// The upload runs in the background and reports its progress through LogStatusNotification requests.
+pub fn get_log(request_id: u64, payload: &JsonValue) -> (&'static str, Option<String>) {
+    let remote_location: String = match payload["log"]["remoteLocation"].as_str() {
+        Some(res) => res.to_string(),
+        None => return ("Rejected", None),
+    };
+
+    let log_type: &str = &payload["logType"].to_string();
+
+    if log_type != "DiagnosticsLog" && log_type != "SecurityLog" {
+        return ("Rejected", None);
+    }
+
+    let oldest_timestamp = parse_date(&payload["log"]["oldestTimestamp"]);
+    let latest_timestamp = parse_date(&payload["log"]["latestTimestamp"]);
+    let retries: u64 = payload["retries"].as_u64().unwrap_or(0);
+    let retry_interval: u64 = payload["retryInterval"].as_u64().unwrap_or(DEFAULT_RETRY_INTERVAL);
+
+    let filename = format!("{}-{}.log", log_type, request_id);
+    let content = package(oldest_timestamp, latest_timestamp);
+
+    // New request cancels the ongoing upload.
+    let status = match storage::get_log_request() {
+        Some(_) => "AcceptedCanceled",
+        None => "Accepted",
+    };
+
+    storage::set_log_request(Some(request_id));
+
+    let upload_filename = filename.to_owned();
+
+    storage::spawn(move || run(request_id, &remote_location, &upload_filename, &content, retries, retry_interval));
+
+    (status, Some(filename))
+}
+
+/// Collect the message transcript within the time window into a log file.
+fn package(oldest_timestamp: Option<DateTime<Utc>>, latest_timestamp: Option<DateTime<Utc>>) -> String {
+    let mut content = String::new();
+
+    for (timestamp, line) in storage::get_transcript() {
+        if oldest_timestamp.is_some_and(|oldest| timestamp < oldest.timestamp()) {
+            continue;
+        }
+
+        if latest_timestamp.is_some_and(|latest| timestamp > latest.timestamp()) {
+            continue;
+        }
+
+        content.push_str(&line);
+        content.push('\n');
+    }
+
+    content
+}
+
+/// Upload log file.
+fn run(request_id: u64, remote_location: &str, filename: &str, content: &str, retries: u64, retry_interval: u64) {
+    for attempt in 0..=retries {
+        if !is_active(request_id) {
+            return;
+        }
+
+        if attempt > 0 {
+            thread::sleep(Duration::from_secs(retry_interval));
+
+            if !is_active(request_id) {
+                return;
+            }
+        }
+
+        notify(request_id, "Uploading");
+
+        match upload(remote_location, filename, content) {
+            Ok(_) => {
+                notify(request_id, "Uploaded");
+                storage::set_log_request(None);
+                return;
+            },
+            Err(e) => println!("Log upload failed: {}", e),
+        };
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL));
+    }
+
+    notify(request_id, "UploadFailure");
+    storage::set_log_request(None);
+}
+
+/// Upload a file as multipart form data.
+fn upload(remote_location: &str, filename: &str, content: &str) -> Result<(), String> {
+    let body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\nContent-Type: text/plain\r\n\r\n{content}\r\n--{boundary}--\r\n",
+        boundary = BOUNDARY,
+        filename = filename,
+        content = content,
+    );
+
+    ureq::post(remote_location)
+        .set("Content-Type", &format!("multipart/form-data; boundary={}", BOUNDARY))
+        .send_string(&body)
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Queue a LogStatusNotification request.
+fn notify(request_id: u64, status: &str) {
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::log_status_notification(msg_id, status, request_id);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+fn is_active(request_id: u64) -> bool {
+    storage::get_log_request() == Some(request_id)
+}
+
+fn parse_date(value: &JsonValue) -> Option<DateTime<Utc>> {
+    match value.as_str() {
+        Some(res) => DateTime::parse_from_rfc3339(res).ok().map(|date| date.with_timezone(&Utc)),
+        None => None,
+    }
+}
@@ -0,0 +1,397 @@
+use std::fs;
+
+use json::JsonValue;
+
+use crate::charging_profiles;
+use crate::clock;
+use crate::components;
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::ev;
+use crate::ids;
+use crate::ocmf;
+use crate::requests;
+use crate::storage;
+use crate::temperature;
+use crate::transactions;
+
+// Measurands which the meter is able to sample, phase values are selected with a phase suffix.
+pub const MEASURANDS: [&str; 16] = [
+    "Energy.Active.Import.Register", "Power.Active.Import", "Current.Import", "Voltage", "SoC", "Power.Factor", "Frequency", "Temperature",
+    "Energy.Active.Export.Register", "Power.Active.Export",
+    "Current.Import.L1", "Current.Import.L2", "Current.Import.L3", "Voltage.L1", "Voltage.L2", "Voltage.L3",
+];
+// Phases of AC supply which are sampled separately.
+const PHASES: [&str; 3] = ["L1", "L2", "L3"];
+// Nominal values of AC supply.
+const DEFAULT_FREQUENCY: f64 = 50.0;
+const DEFAULT_POWER_FACTOR: f64 = 0.98;
+
+/// Integrate energy delivered since the previous tick and send due MeterValues requests.
+///
+/// Sampled values are sent in TransactionEvent requests every `SampledDataCtrlr.TxUpdatedInterval` seconds
+/// for EVSE with an ongoing transaction, clock aligned values are sent every `AlignedDataCtrlr.Interval` seconds for all EVSE.
+/// Values sampled every `SampledDataCtrlr.TxEndedInterval` and `AlignedDataCtrlr.TxEndedInterval` seconds are
+/// collected and sent at the end of the transaction.
+pub fn tick() -> Result<(), EmulatorError> {
+    // Energy is integrated over the local time which isn't affected by clock synchronization.
+    let local_now = clock::local_now().timestamp_millis();
+
+    if let Some(previous_tick) = storage::get_meter_timestamp("tick") {
+        let hours = (local_now - previous_tick) as f64 / 3_600_000.0;
+        let mut registered = false;
+
+        for evse_index in 0..storage::evse_count() {
+            for connector_index in 0..storage::connector_count(evse_index) {
+                // Registers never decrease, discharged energy is exported.
+                let energy = storage::get_connector(evse_index, connector_index).map_or(0.0, |connector| connector.power) * hours;
+
+                if energy > 0.0 {
+                    storage::add_connector_energy(evse_index, connector_index, energy);
+                } else if energy < 0.0 {
+                    storage::add_connector_export_energy(evse_index, connector_index, -energy);
+                }
+
+                if energy != 0.0 {
+                    ev::charge(evse_index, connector_index, energy);
+
+                    registered = true;
+                }
+            }
+        }
+
+        if registered {
+            save_registers();
+        }
+
+        for evse_index in 0..storage::evse_count() {
+            temperature::update(evse_index, hours * 3600.0)?;
+        }
+    }
+
+    // Charging power follows current limits and the state of charge of EVs with ongoing transactions.
+    charging_profiles::enforce_limits();
+
+    storage::set_meter_timestamp("tick".to_string(), local_now);
+
+    let now = clock::now().timestamp_millis();
+
+    // Clock aligned values.
+
+    let aligned_interval = interval("AlignedDataCtrlr", "Interval");
+
+    if aligned_interval > 0 {
+        let aligned_moment = now - now % aligned_interval;
+
+        if storage::get_meter_timestamp("aligned").is_some_and(|previous| previous < aligned_moment) {
+            let measurands = measurands("AlignedDataCtrlr", "Measurands");
+
+            for evse_index in 0..storage::evse_count() {
+                queue_meter_values(evse_index, sample(evse_index, &measurands, "Sample.Clock")?);
+            }
+        }
+
+        storage::set_meter_timestamp("aligned".to_string(), aligned_moment);
+    }
+
+    // Sampled values of ongoing transactions.
+
+    let sampled_interval = interval("SampledDataCtrlr", "TxUpdatedInterval");
+
+    if sampled_interval > 0 {
+        let measurands = measurands("SampledDataCtrlr", "TxUpdatedMeasurands");
+
+        for (transaction_id, evse_index) in transaction_evse_indexes() {
+            let key = format!("sampled:{}", transaction_id);
+
+            match storage::get_meter_timestamp(&key) {
+                Some(previous) if now - previous < sampled_interval => continue,
+                Some(_) => queue_transaction_meter_values(&transaction_id, transaction_meter_value(evse_index, &measurands, "Sample.Periodic")?)?,
+                None => (),
+            }
+
+            storage::set_meter_timestamp(key, now);
+        }
+    }
+
+    // Values which are sent at the end of ongoing transactions.
+
+    let ended_interval = interval("SampledDataCtrlr", "TxEndedInterval");
+    let aligned_ended_interval = interval("AlignedDataCtrlr", "TxEndedInterval");
+
+    for (transaction_id, evse_index) in transaction_evse_indexes() {
+        if ended_interval > 0 {
+            let key = format!("ended:{}", transaction_id);
+
+            match storage::get_meter_timestamp(&key) {
+                Some(previous) if now - previous < ended_interval => (),
+                Some(_) => {
+                    transactions::add_ended_meter_value(&transaction_id, transaction_meter_value(evse_index, &measurands("SampledDataCtrlr", "TxEndedMeasurands"), "Sample.Periodic")?)?;
+
+                    storage::set_meter_timestamp(key, now);
+                },
+                None => storage::set_meter_timestamp(key, now),
+            }
+        }
+
+        if aligned_ended_interval > 0 {
+            let key = format!("alignedEnded:{}", transaction_id);
+            let aligned_moment = now - now % aligned_ended_interval;
+
+            if storage::get_meter_timestamp(&key).is_some_and(|previous| previous < aligned_moment) {
+                transactions::add_ended_meter_value(&transaction_id, transaction_meter_value(evse_index, &measurands("AlignedDataCtrlr", "TxEndedMeasurands"), "Sample.Clock")?)?;
+            }
+
+            storage::set_meter_timestamp(key, aligned_moment);
+        }
+    }
+
+    Ok(())
+}
+
+/// Restore energy registers of the connectors which were saved before the emulator was stopped.
+///
+/// The file referenced by `METER_REGISTER_FILE` contains an array of EVSE arrays with the energy (Wh) registered
+/// by each connector. The file is created once energy is registered.
+pub fn load_registers() {
+    let path = match register_file() {
+        Some(res) => res,
+        None => return,
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(res) => res,
+        Err(_) => return,
+    };
+
+    let registers = match json::parse(&content) {
+        Ok(result) => result,
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    };
+
+    for evse_index in 0..storage::evse_count() {
+        for connector_index in 0..storage::connector_count(evse_index) {
+            if let Some(energy) = registers[evse_index][connector_index].as_f64().filter(|energy| *energy > 0.0) {
+                storage::add_connector_energy(evse_index, connector_index, energy);
+            }
+        }
+    }
+
+    println!("Meter registers were restored from {}.", path);
+}
+
+fn save_registers() {
+    let path = match register_file() {
+        Some(res) => res,
+        None => return,
+    };
+
+    let registers: Vec<Vec<f64>> = (0..storage::evse_count())
+        .map(|evse_index| storage::get_connectors(evse_index).iter().map(|connector| connector.energy).collect())
+        .collect();
+
+    if let Err(e) = fs::write(&path, json::stringify(registers)) {
+        println!("Couldn't save meter registers ({})", e);
+    }
+}
+
+fn register_file() -> Option<String> {
+    match config::var("METER_REGISTER_FILE") {
+        Ok(var) => if var.is_empty() { None } else { Some(var) },
+        _ => None,
+    }
+}
+
+/// Sample the meter of the EVSE.
+///
+/// Returns a meter value with sampled values of the given measurands.
+pub fn sample(evse_index: usize, measurands: &[String], context: &str) -> Result<JsonValue, EmulatorError> {
+    let timestamp = clock::timestamp()?;
+
+    let energy = energy(evse_index);
+    let power = power(evse_index);
+    let voltage = env_f64("METER_NOMINAL_VOLTAGE", charging_profiles::NOMINAL_VOLTAGE);
+    let phases = phases(evse_index);
+    let mut sampled_value: JsonValue = JsonValue::new_array();
+
+    for measurand in measurands {
+        // Phase values are reported with the plain measurand and the phase field.
+        let (name, phase) = match measurand.rsplit_once('.') {
+            Some((name, phase)) if PHASES.contains(&phase) => (name, Some(phase)),
+            _ => (measurand.as_str(), None),
+        };
+
+        let (value, unit) = match (name, phase) {
+            ("Energy.Active.Import.Register", None) => (energy.round(), "Wh"),
+            ("Energy.Active.Export.Register", None) => (export_energy(evse_index).round(), "Wh"),
+            ("Power.Active.Import", None) => (power.max(0.0).round(), "W"),
+            ("Power.Active.Export", None) => ((-power).max(0.0).round(), "W"),
+            ("Current.Import", None) => (round(power.max(0.0) / (voltage * phases.max(1) as f64), 1), "A"),
+            // Current flows only through phases which the connector supplies.
+            ("Current.Import", Some(phase)) => match PHASES.iter().position(|supplied| *supplied == phase) {
+                Some(index) if index < phases && power > 0.0 => (round(noise(power / (voltage * phases as f64)), 1), "A"),
+                _ => (0.0, "A"),
+            },
+            ("Voltage", _) => (round(noise(voltage), 1), "V"),
+            ("Power.Factor", None) => (round(noise(env_f64("METER_POWER_FACTOR", DEFAULT_POWER_FACTOR)).min(1.0), 2), ""),
+            ("Frequency", None) => (round(noise(env_f64("METER_FREQUENCY", DEFAULT_FREQUENCY)), 2), ""),
+            ("Temperature", None) => (round(temperature::get(evse_index), 1), "Celsius"),
+            ("SoC", None) => match ev::soc(evse_index) {
+                Some(res) => (res.round(), "Percent"),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        let mut item = object!{
+            "value" => value,
+            "context" => context,
+            "measurand" => name,
+        };
+
+        // Voltage is measured between the phase and the neutral.
+        if let Some(phase) = phase {
+            item["phase"] = if name == "Voltage" { format!("{}-N", phase).into() } else { phase.into() };
+        }
+
+        if !unit.is_empty() {
+            item["unitOfMeasure"] = object!{ "unit" => unit };
+        }
+
+        let _ = sampled_value.push(item);
+    }
+
+    Ok(object!{
+        "timestamp" => timestamp,
+        "sampledValue" => sampled_value,
+    })
+}
+
+/// Sample the meter of the EVSE at the beginning or at the end of a transaction.
+///
+/// Returns a meter value array with measurands configured by the `SampledDataCtrlr` variable.
+pub fn transaction_sample(evse_index: usize, variable_name: &str, context: &str) -> Result<JsonValue, EmulatorError> {
+    Ok(array![transaction_meter_value(evse_index, &measurands("SampledDataCtrlr", variable_name), context)?])
+}
+
+/// Sample the meter of the EVSE for a TransactionEvent request.
+///
+/// Energy register values are signed if `SampledDataCtrlr.SignReadings` is enabled.
+fn transaction_meter_value(evse_index: usize, measurands: &[String], context: &str) -> Result<JsonValue, EmulatorError> {
+    let meter_value = sample(evse_index, measurands, context)?;
+
+    match components::get_variable("SampledDataCtrlr", "SignReadings").1 {
+        Some(value) if value == "true" => Ok(ocmf::sign(meter_value)),
+        _ => Ok(meter_value),
+    }
+}
+
+/// Get the energy (Wh) which the EVSE meter has registered with all its connectors.
+pub fn energy(evse_index: usize) -> f64 {
+    storage::get_connectors(evse_index).iter().map(|connector| connector.energy).sum()
+}
+
+/// Get the energy (Wh) which the EVSE meter has registered as exported by discharging EVs.
+pub fn export_energy(evse_index: usize) -> f64 {
+    storage::get_connectors(evse_index).iter().map(|connector| connector.energy_export).sum()
+}
+
+/// Get the power (W) which the EVSE delivers with all its connectors, it's negative while EVs discharge.
+pub fn power(evse_index: usize) -> f64 {
+    storage::get_connectors(evse_index).iter().map(|connector| connector.power).sum()
+}
+
+/// Get the number of AC phases which the EVSE supplies.
+///
+/// The connector which is used for charging determines the phases, otherwise the connector with most phases does.
+fn phases(evse_index: usize) -> usize {
+    let connectors = storage::get_connectors(evse_index);
+
+    match connectors.iter().find(|connector| connector.status == "Occupied") {
+        Some(connector) => connector.phases as usize,
+        None => connectors.iter().map(|connector| connector.phases as usize).max().unwrap_or(0),
+    }
+}
+
+/// Add random noise to a sampled value.
+///
+/// `METER_NOISE` is the maximum deviation (%) from the value, there is no noise by default.
+fn noise(value: f64) -> f64 {
+    let deviation = env_f64("METER_NOISE", 0.0);
+
+    if deviation <= 0.0 {
+        return value;
+    }
+
+    let random: u64 = ids::random();
+    let factor = random as f64 / u64::MAX as f64 * 2.0 - 1.0;
+
+    value * (1.0 + factor * deviation / 100.0)
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+
+    (value * scale).round() / scale
+}
+
+fn env_f64(name: &str, default: f64) -> f64 {
+    match config::var(name) {
+        Ok(var) => var.parse::<f64>().unwrap_or(default),
+        _ => default,
+    }
+}
+
+/// Get ongoing transactions which are started already with indexes of their EVSE.
+fn transaction_evse_indexes() -> Vec<(String, usize)> {
+    storage::get_transactions().into_iter()
+        .filter_map(|(transaction_id, transaction)| json::parse(&transaction).ok().map(|transaction| (transaction_id, transaction)))
+        .filter(|(_, transaction)| transaction["started"] == true)
+        .filter_map(|(transaction_id, transaction)| transaction["evseId"].as_usize().map(|evse_id| (transaction_id, evse_id)))
+        .filter(|(_, evse_id)| *evse_id > 0)
+        .map(|(transaction_id, evse_id)| (transaction_id, evse_id - 1))
+        .collect()
+}
+
+/// Queue MeterValues with the clock aligned measurands of the EVSE which CSMS has triggered.
+pub fn queue_triggered(evse_index: usize) -> Result<(), EmulatorError> {
+    queue_meter_values(evse_index, sample(evse_index, &measurands("AlignedDataCtrlr", "Measurands"), "Trigger")?);
+
+    Ok(())
+}
+
+/// Queue a MeterValues request.
+fn queue_meter_values(evse_index: usize, meter_value: JsonValue) {
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::meter_values(msg_id, evse_index + 1, array![meter_value]);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+/// Queue a TransactionEvent request with periodic meter values of the transaction.
+fn queue_transaction_meter_values(transaction_id: &str, meter_value: JsonValue) -> Result<(), EmulatorError> {
+    transactions::queue_event(transaction_id, requests::TransactionEvent {
+        event_type: "Updated",
+        trigger_reason: "MeterValuePeriodic",
+        meter_value: Some(array![meter_value]),
+        ..Default::default()
+    })
+}
+
+/// Get an interval (ms) configured in the device model.
+fn interval(component_name: &str, variable_name: &str) -> i64 {
+    match components::get_variable(component_name, variable_name).1 {
+        Some(value) => value.parse::<i64>().unwrap_or(0) * 1000,
+        None => 0,
+    }
+}
+
+/// Get a list of measurands configured in the device model.
+fn measurands(component_name: &str, variable_name: &str) -> Vec<String> {
+    match components::get_variable(component_name, variable_name).1 {
+        Some(value) => value.split(',').map(|measurand| measurand.trim().to_string()).filter(|measurand| !measurand.is_empty()).collect(),
+        None => Vec::new(),
+    }
+}
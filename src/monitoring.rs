@@ -0,0 +1,298 @@
+use chrono::prelude::*;
+use json::JsonValue;
+
+use crate::clock;
+use crate::components;
+use crate::errors::EmulatorError;
+use crate::events;
+use crate::storage;
+
+// Supported monitor types.
+const MONITOR_TYPES: [&str; 5] = ["UpperThreshold", "LowerThreshold", "Delta", "Periodic", "PeriodicClockAligned"];
+
+/// Validate and store a monitor from a SetVariableMonitoring request.
+///
+/// Returns the id of the monitor and the status of the request.
+pub fn set(monitoring_data: &JsonValue) -> Result<(Option<u64>, &'static str), EmulatorError> {
+    let component = &monitoring_data["component"];
+    let variable = &monitoring_data["variable"];
+    let monitor_type: &str = &monitoring_data["type"].to_string();
+
+    match components::get_component_attribute(component, variable, "Actual").0 {
+        "UnknownComponent" => return Ok((None, "UnknownComponent")),
+        "UnknownVariable" => return Ok((None, "UnknownVariable")),
+        // Values of write-only variables must not be disclosed by events.
+        "Rejected" => return Ok((None, "Rejected")),
+        _ => (),
+    };
+
+    if !MONITOR_TYPES.contains(&monitor_type) {
+        return Ok((None, "UnsupportedMonitorType"));
+    }
+
+    let value: f64 = match monitoring_data["value"].as_f64() {
+        Some(res) => res,
+        None => return Ok((None, "Rejected")),
+    };
+
+    // Periodic monitors require an interval of at least a second.
+    if value < 1.0 && (monitor_type == "Periodic" || monitor_type == "PeriodicClockAligned") {
+        return Ok((None, "Rejected"));
+    }
+
+    match monitoring_data["severity"].as_u8() {
+        Some(severity) if severity <= 9 => (),
+        _ => return Ok((None, "Rejected")),
+    };
+
+    let requested_id: Option<u64> = monitoring_data["id"].as_u64();
+
+    let monitors = storage::get_monitors();
+
+    if let Some(id) = requested_id {
+        // Only existing monitors can be addressed by id.
+        if !monitors.iter().any(|(monitor_id, _)| *monitor_id == id) {
+            return Ok((None, "Rejected"));
+        }
+    }
+
+    for (id, monitor) in monitors.iter() {
+        let monitor = parse(monitor)?;
+
+        if Some(*id) != requested_id
+            && components::variable_key(&monitor["component"], &monitor["variable"]) == components::variable_key(component, variable)
+            && monitor["type"] == monitor_type {
+            return Ok((None, "Duplicate"));
+        }
+    }
+
+    let id = match requested_id {
+        Some(id) => id,
+        None => monitors.iter().map(|(id, _)| *id).max().unwrap_or(0) + 1,
+    };
+
+    let monitor = object!{
+        "id" => id,
+        "transaction" => monitoring_data["transaction"].as_bool().unwrap_or(false),
+        "value" => value,
+        "type" => monitor_type,
+        "severity" => monitoring_data["severity"].as_u8(),
+        "component" => component.clone(),
+        "variable" => variable.clone(),
+    };
+
+    storage::set_monitor(id, monitor.dump());
+
+    Ok((Some(id), "Accepted"))
+}
+
+/// Remove a monitor.
+///
+/// Returns the status of the request: `Accepted` or `NotFound`.
+pub fn clear(id: u64) -> &'static str {
+    if storage::get_monitor(id).is_empty() {
+        return "NotFound";
+    }
+
+    storage::delete_monitor(id);
+
+    "Accepted"
+}
+
+/// Apply a monitoring base from a SetMonitoringBase request.
+///
+/// The emulator has no preconfigured monitors, so falling back to factory defaults removes all custom monitors.
+pub fn set_base(monitoring_base: &str) -> &'static str {
+    match monitoring_base {
+        "All" => "Accepted",
+        "FactoryDefault" | "HardWiredOnly" => {
+            for (id, _) in storage::get_monitors() {
+                storage::delete_monitor(id);
+            }
+
+            "Accepted"
+        },
+        _ => "Rejected",
+    }
+}
+
+/// Find monitors which match criteria from a GetMonitoringReport request.
+///
+/// Returns monitors grouped by their component and variable.
+pub fn report(monitoring_criteria: &JsonValue, component_variables: &JsonValue) -> Result<Vec<JsonValue>, EmulatorError> {
+    let mut monitors: Vec<JsonValue> = storage::get_monitors().into_iter()
+        .map(|(_, monitor)| parse(&monitor))
+        .collect::<Result<Vec<JsonValue>, EmulatorError>>()?
+        .into_iter()
+        .filter(|monitor| monitoring_criteria.is_empty() || monitoring_criteria.members().any(|criterion| {
+            match criterion.as_str() {
+                Some("ThresholdMonitoring") => monitor["type"] == "UpperThreshold" || monitor["type"] == "LowerThreshold",
+                Some("DeltaMonitoring") => monitor["type"] == "Delta",
+                Some("PeriodicMonitoring") => monitor["type"] == "Periodic" || monitor["type"] == "PeriodicClockAligned",
+                _ => false,
+            }
+        }))
+        .filter(|monitor| component_variables.is_empty() || component_variables.members().any(|component_variable| {
+            let component = &component_variable["component"];
+            let variable = &component_variable["variable"];
+
+            // Instances and EVSE narrow the selection only if they are given.
+            monitor["component"]["name"] == components::component_name(component)
+                && (component["instance"].is_null() || monitor["component"]["instance"] == component["instance"])
+                && (component["evse"].is_null() || monitor["component"]["evse"] == component["evse"])
+                && (variable.is_null() || monitor["variable"]["name"] == variable["name"])
+                && (variable["instance"].is_null() || monitor["variable"]["instance"] == variable["instance"])
+        }))
+        .collect();
+
+    monitors.sort_by_key(|monitor| monitor["id"].as_u64());
+
+    let mut report: Vec<JsonValue> = Vec::new();
+
+    for monitor in monitors {
+        let variable_monitoring = object!{
+            "id" => monitor["id"].as_u64(),
+            "transaction" => monitor["transaction"].as_bool(),
+            "value" => monitor["value"].as_f64(),
+            "type" => monitor["type"].as_str(),
+            "severity" => monitor["severity"].as_u8(),
+        };
+
+        let position = report.iter().position(|item| item["component"] == monitor["component"] && item["variable"] == monitor["variable"]);
+
+        match position {
+            Some(index) => report[index]["variableMonitoring"].push(variable_monitoring).unwrap(),
+            None => report.push(object!{
+                "component" => monitor["component"].clone(),
+                "variable" => monitor["variable"].clone(),
+                "variableMonitoring" => array![variable_monitoring],
+            }),
+        };
+    }
+
+    Ok(report)
+}
+
+/// Check monitored variables and queue NotifyEvent requests for triggered monitors.
+pub fn check() -> Result<(), EmulatorError> {
+    check_monitors(None)
+}
+
+/// Check monitors of the variable right after its value was changed, e.g. with SetVariables.
+pub fn check_variable(component: &JsonValue, variable: &JsonValue) -> Result<(), EmulatorError> {
+    check_monitors(Some(components::variable_key(component, variable)))
+}
+
+/// Check monitors of all variables or only of the variable with the given key.
+fn check_monitors(variable_key: Option<String>) -> Result<(), EmulatorError> {
+    let now = clock::now();
+    let monitoring_level = storage::get_monitoring_level();
+
+    for (id, monitor) in storage::get_monitors() {
+        let mut monitor = parse(&monitor)?;
+
+        if variable_key.as_ref().is_some_and(|key| *key != components::variable_key(&monitor["component"], &monitor["variable"])) {
+            continue;
+        }
+
+        // Monitors with severity above the monitoring level are not reported.
+        if monitor["severity"].as_u8().unwrap_or(9) > monitoring_level {
+            continue;
+        }
+
+        let actual_value: String = match components::get_component_variable(&monitor["component"], &monitor["variable"]).1 {
+            Some(res) => res,
+            None => continue,
+        };
+
+        let threshold: f64 = monitor["value"].as_f64().unwrap_or(0.0);
+        // Interval (s) of periodic monitors, monitors restored with a shorter one report every second.
+        let interval: i64 = (threshold as i64).max(1);
+        let numeric_value: Option<f64> = actual_value.parse().ok();
+        let active: bool = monitor["active"].as_bool().unwrap_or(false);
+
+        match monitor["type"].as_str() {
+            Some("UpperThreshold") | Some("LowerThreshold") => {
+                let exceeded = match numeric_value {
+                    Some(value) if monitor["type"] == "UpperThreshold" => value > threshold,
+                    Some(value) => value < threshold,
+                    None => continue,
+                };
+
+                // Alert is sent when the threshold is crossed and cleared when the value returns back.
+                if exceeded != active {
+                    notify(&monitor, "Alerting", &actual_value, !exceeded)?;
+
+                    monitor["active"] = exceeded.into();
+                }
+            },
+            Some("Delta") => {
+                let reference = monitor["reference"].to_string();
+
+                let changed = match (numeric_value, reference.parse::<f64>().ok()) {
+                    (Some(value), Some(reference)) => (value - reference).abs() >= threshold,
+                    _ => reference != actual_value,
+                };
+
+                if monitor["reference"].is_null() {
+                    monitor["reference"] = actual_value.to_owned().into();
+                } else if changed {
+                    notify(&monitor, "Delta", &actual_value, false)?;
+
+                    monitor["reference"] = actual_value.to_owned().into();
+                }
+            },
+            Some("Periodic") => {
+                let last_report = monitor["lastReport"].as_i64().unwrap_or(0);
+
+                if now.timestamp() - last_report >= interval {
+                    notify(&monitor, "Periodic", &actual_value, false)?;
+
+                    monitor["lastReport"] = now.timestamp().into();
+                }
+            },
+            Some("PeriodicClockAligned") => {
+                // Reports are aligned to the interval counted from midnight.
+                let seconds_from_midnight = now.num_seconds_from_midnight() as i64;
+                let aligned = now.timestamp() - seconds_from_midnight % interval;
+
+                if monitor["lastReport"].is_null() {
+                    monitor["lastReport"] = aligned.into();
+                } else if aligned > monitor["lastReport"].as_i64().unwrap_or(0) {
+                    notify(&monitor, "Periodic", &actual_value, false)?;
+
+                    monitor["lastReport"] = aligned.into();
+                }
+            },
+            _ => continue,
+        };
+
+        storage::set_monitor(id, monitor.dump());
+    }
+
+    Ok(())
+}
+
+/// Queue a NotifyEvent request for a triggered monitor.
+fn notify(monitor: &JsonValue, trigger: &str, actual_value: &str, cleared: bool) -> Result<(), EmulatorError> {
+    let mut event_data = object!{
+        "trigger" => trigger,
+        "actualValue" => actual_value,
+        "component" => monitor["component"].clone(),
+        "variableMonitoringId" => monitor["id"].as_u64(),
+        "eventNotificationType" => "CustomMonitor",
+        "variable" => monitor["variable"].clone(),
+    };
+
+    if cleared {
+        event_data["cleared"] = true.into();
+    }
+
+    events::notify(event_data)?;
+
+    Ok(())
+}
+
+fn parse(value: &str) -> Result<JsonValue, EmulatorError> {
+    Ok(json::parse(value)?)
+}
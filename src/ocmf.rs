@@ -0,0 +1,129 @@
+use std::fs;
+
+use chrono::prelude::*;
+use json::JsonValue;
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+
+use crate::clock;
+use crate::components;
+use crate::config;
+
+// Signing method of OCMF signatures.
+const SIGNING_METHOD: &str = "ECDSA-secp256r1-SHA256";
+// OBIS code of the energy import register.
+const ENERGY_REGISTER: &str = "1-b:1.8.0";
+
+/// Check if a meter signing key is configured with `METER_SIGNING_KEY_FILE`.
+pub fn is_configured() -> bool {
+    key_file().is_some()
+}
+
+/// Sign energy register values of a meter value.
+///
+/// Every `Energy.Active.Import.Register` sampled value gets a `signedMeterValue` with an OCMF payload signed with
+/// the EC key (secp256r1) in the PEM file `METER_SIGNING_KEY_FILE`. Meter value is returned unchanged without the key.
+pub fn sign(mut meter_value: JsonValue) -> JsonValue {
+    let key = match key() {
+        Some(res) => res,
+        None => return meter_value,
+    };
+
+    let public_key = match key.public_key_to_der() {
+        Ok(res) => base64::encode_block(&res),
+        Err(_) => return meter_value,
+    };
+
+    let timestamp = DateTime::parse_from_rfc3339(&meter_value["timestamp"].to_string()).unwrap_or_else(|_| clock::now().into());
+
+    for sampled_value in meter_value["sampledValue"].members_mut() {
+        if sampled_value["measurand"] != "Energy.Active.Import.Register" {
+            continue;
+        }
+
+        let reading = object!{
+            "TM" => format!("{} U", timestamp.format("%Y-%m-%dT%H:%M:%S,%3f%z")),
+            "TX" => reading_type(&sampled_value["context"]),
+            // OCMF readings are kWh.
+            "RV" => sampled_value["value"].as_f64().unwrap_or(0.0) / 1000.0,
+            "RI" => ENERGY_REGISTER,
+            "RU" => "kWh",
+            "ST" => "G",
+        };
+
+        let payload = object!{
+            "FV" => "1.0",
+            "GI" => variable("Model"),
+            "GS" => variable("SerialNumber"),
+            "GV" => variable("FirmwareVersion"),
+            "PG" => "T1",
+            "MV" => variable("VendorName"),
+            "MM" => variable("Model"),
+            "MS" => variable("SerialNumber"),
+            "MF" => variable("FirmwareVersion"),
+            "IS" => false,
+            "RD" => array![reading],
+        }.dump();
+
+        let signature = match signature(&key, &payload) {
+            Some(res) => res,
+            None => continue,
+        };
+
+        let signed_data = format!("OCMF|{}|{}", payload, object!{ "SA" => SIGNING_METHOD, "SD" => signature }.dump());
+
+        sampled_value["signedMeterValue"] = object!{
+            "signedMeterData" => base64::encode_block(signed_data.as_bytes()),
+            "signingMethod" => SIGNING_METHOD,
+            "encodingMethod" => "OCMF",
+            "publicKey" => public_key.as_str(),
+        };
+    }
+
+    meter_value
+}
+
+/// Get the OCMF reading type of a sampling context: begin, end or intermediate value of a transaction.
+fn reading_type(context: &JsonValue) -> &'static str {
+    match context.as_str() {
+        Some("Transaction.Begin") => "B",
+        Some("Transaction.End") => "E",
+        _ => "T",
+    }
+}
+
+/// Get a hex encoded signature of the payload.
+fn signature(key: &PKey<Private>, payload: &str) -> Option<String> {
+    let mut signer = Signer::new(MessageDigest::sha256(), key).ok()?;
+
+    signer.update(payload.as_bytes()).ok()?;
+
+    Some(signer.sign_to_vec().ok()?.iter().map(|byte| format!("{:02X}", byte)).collect())
+}
+
+fn variable(variable_name: &str) -> String {
+    components::get_variable("ChargingStation", variable_name).1.unwrap_or_default()
+}
+
+fn key() -> Option<PKey<Private>> {
+    let path = key_file()?;
+
+    let content = match fs::read(&path) {
+        Ok(res) => res,
+        Err(e) => panic!("Couldn't read METER_SIGNING_KEY_FILE ({})", e),
+    };
+
+    match PKey::private_key_from_pem(&content) {
+        Ok(res) => Some(res),
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    }
+}
+
+fn key_file() -> Option<String> {
+    match config::var("METER_SIGNING_KEY_FILE") {
+        Ok(var) => if var.is_empty() { None } else { Some(var) },
+        _ => None,
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs;
+
+use chrono::prelude::*;
+use json::JsonValue;
+use openssl::base64;
+
+use crate::authorization;
+use crate::certificates;
+use crate::charging_profiles;
+use crate::clock;
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::ids;
+use crate::requests;
+use crate::storage;
+use crate::transactions;
+
+// ISO 15118 schema version of the simulated EV.
+const ISO15118_SCHEMA_VERSION: &str = "urn:iso:15118:2:2013:MsgDef";
+// Charging parameters of the simulated DC EV.
+const EV_MAX_CURRENT: f64 = 200.0;
+const EV_MAX_VOLTAGE: f64 = 400.0;
+const DEFAULT_ENERGY_AMOUNT: f64 = 40000.0;
+// Default time until departure of the simulated EV (s).
+const DEFAULT_DEPARTURE_DURATION: i64 = 14400;
+
+/// Plug in a simulated EV which supports ISO 15118 Plug & Charge.
+///
+/// EV without an installed contract certificate requests it with Get15118EVCertificate first if `ISO15118_EXI_STUB`
+/// is enabled, then the station authorizes the eMAID of the contract.
+/// Returns `Accepted` if the session is started or `Rejected` if the EVSE can't be used.
+pub fn plug_in(evse_id: usize, emaid: &str) -> Result<&'static str, EmulatorError> {
+    if evse_id == 0 || evse_id > storage::evse_count() || emaid.is_empty() {
+        return Ok("Rejected");
+    }
+
+    if transactions::free_connector(evse_id - 1).and_then(|connector_index| storage::get_connector(evse_id - 1, connector_index)).is_none_or(|connector| connector.status == "Reserved") {
+        return Ok("Rejected");
+    }
+
+    let has_contract_certificate = storage::get_contract_certificate(emaid).is_some();
+
+    if !has_contract_certificate && !is_exi_stub_enabled() {
+        println!("EV {} has no contract certificate and ISO15118_EXI_STUB is disabled.", emaid);
+
+        return Ok("Rejected");
+    }
+
+    storage::set_pending_authorization(emaid.to_string(), Some(evse_id - 1));
+
+    if has_contract_certificate {
+        queue_authorize(emaid)?;
+    } else {
+        // STUB: ISO 15118 messages are not emulated, the "EXI request" is base64 of {"eMAID": ...} which only
+        // identifies the contract. It isn't EXI encoded, so only CSMS which accept the stub can answer it.
+        let exi_request = base64::encode_block(object!{ "eMAID" => emaid }.dump().as_bytes());
+
+        let msg_id: &str = &ids::uuid();
+        let msg = requests::get_15118_ev_certificate(msg_id, ISO15118_SCHEMA_VERSION, "Install", &exi_request);
+
+        storage::set_message(msg_id.to_string(), msg.to_owned());
+
+        storage::queue_add(msg);
+    }
+
+    Ok("Accepted")
+}
+
+/// Handle Get15118EVCertificate response: install the contract certificate and authorize the EV.
+///
+/// Only responses to stub EXI requests of `plug_in` are handled.
+pub fn certificate_received(request: &JsonValue, response: &JsonValue) -> Result<(), EmulatorError> {
+    let emaid = match base64::decode_block(&request["exiRequest"].to_string()).ok()
+        .and_then(|exi| String::from_utf8(exi).ok())
+        .and_then(|exi| json::parse(&exi).ok())
+        .and_then(|exi| exi["eMAID"].as_str().map(String::from)) {
+        Some(res) => res,
+        None => {
+            println!("Get15118EVCertificate response doesn't refer to a stub EXI request, it's ignored.");
+
+            return Ok(());
+        },
+    };
+
+    if response["status"] != "Accepted" {
+        println!("Contract certificate of {} was not installed.", emaid);
+
+        storage::set_pending_authorization(emaid, None);
+
+        return Ok(());
+    }
+
+    storage::set_contract_certificate(emaid.to_owned(), response["exiResponse"].to_string());
+
+    queue_authorize(&emaid)
+}
+
+/// Check if the stub EXI request of Get15118EVCertificate is sent for EVs without a contract certificate.
+fn is_exi_stub_enabled() -> bool {
+    config::var("ISO15118_EXI_STUB").is_ok_and(|var| var == "true")
+}
+
+/// Send NotifyEVChargingNeeds when a session of an ISO 15118 DC vehicle starts.
+///
+/// The simulated EV is a DC vehicle if `EV_ENERGY_TRANSFER` is `DC`, its energy amount (Wh)
+/// and time until departure (s) are configured with `EV_ENERGY_AMOUNT` and `EV_DEPARTURE_DURATION`.
+pub fn session_started(evse_index: usize, transaction_id: &str) -> Result<(), EmulatorError> {
+    if config::var("EV_ENERGY_TRANSFER").unwrap_or_default() != "DC" {
+        return Ok(());
+    }
+
+    let energy_amount: f64 = config::var("EV_ENERGY_AMOUNT").ok().and_then(|var| var.parse().ok()).unwrap_or(DEFAULT_ENERGY_AMOUNT);
+    let departure_duration: i64 = config::var("EV_DEPARTURE_DURATION").ok().and_then(|var| var.parse().ok()).unwrap_or(DEFAULT_DEPARTURE_DURATION);
+
+    let departure_time = match (clock::now() + chrono::Duration::seconds(departure_duration)).with_nanosecond(0) {
+        Some(res) => res.to_rfc3339(),
+        None => return Err(EmulatorError::InvalidDate),
+    };
+
+    // Departure time is used to request the charging schedule of the EV.
+    if let Ok(mut transaction) = json::parse(&storage::get_transaction(transaction_id)) {
+        transaction["departureTime"] = departure_time.to_owned().into();
+
+        storage::set_transaction(transaction_id.to_string(), transaction.dump());
+    }
+
+    let charging_needs = object!{
+        "requestedEnergyTransfer" => "DC",
+        "departureTime" => departure_time,
+        "dcChargingParameters" => object!{
+            "evMaxCurrent" => EV_MAX_CURRENT,
+            "evMaxVoltage" => EV_MAX_VOLTAGE,
+            "evMaxPower" => EV_MAX_CURRENT * EV_MAX_VOLTAGE,
+            "energyAmount" => energy_amount,
+        },
+    };
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::notify_ev_charging_needs(msg_id, evse_index + 1, charging_needs);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+
+    Ok(())
+}
+
+/// Send NotifyEVChargingSchedule after CSMS has set a charging profile for an ISO 15118 DC session.
+///
+/// The simulated EV follows the composite schedule of the EVSE until its departure.
+pub fn profile_received(evse_id: usize) -> Result<(), EmulatorError> {
+    let departure_time = storage::get_transactions().into_iter()
+        .filter_map(|(_, transaction)| json::parse(&transaction).ok())
+        .find(|transaction| transaction["evseId"].as_usize() == Some(evse_id))
+        .and_then(|transaction| transaction["departureTime"].as_str().and_then(|date| DateTime::parse_from_rfc3339(date).ok()));
+
+    let departure_time = match departure_time {
+        Some(res) => res.with_timezone(&Utc),
+        None => return Ok(()),
+    };
+
+    let time_base = match clock::now().with_nanosecond(0) {
+        Some(res) => res,
+        None => return Err(EmulatorError::InvalidDate),
+    };
+
+    let duration = (departure_time - time_base).num_seconds().max(1);
+
+    let mut periods: JsonValue = JsonValue::new_array();
+
+    for (start_period, limit) in charging_profiles::composite_schedule(evse_id, time_base, duration) {
+        let _ = periods.push(object!{
+            "startPeriod" => start_period,
+            "limit" => limit,
+        });
+    }
+
+    let charging_schedule = object!{
+        "id" => 1,
+        "startSchedule" => time_base.to_rfc3339(),
+        "duration" => duration,
+        "chargingRateUnit" => "W",
+        "chargingSchedulePeriod" => periods,
+    };
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::notify_ev_charging_schedule(msg_id, &time_base.to_rfc3339(), evse_id, charging_schedule);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+
+    Ok(())
+}
+
+/// Queue Authorize request for the eMAID with OCSP data of the configured contract certificate.
+fn queue_authorize(emaid: &str) -> Result<(), EmulatorError> {
+    let id_token = object!{
+        "idToken" => emaid,
+        "type" => "eMAID",
+    };
+
+    authorization::queue_authorize(id_token, contract_certificate_hash_data()?);
+
+    Ok(())
+}
+
+/// Read OCSP request data of the contract certificate referenced by `CONTRACT_CERTIFICATE_FILE`.
+fn contract_certificate_hash_data() -> Result<Option<JsonValue>, EmulatorError> {
+    let path = match config::var("CONTRACT_CERTIFICATE_FILE") {
+        Ok(var) => if var.is_empty() { return Ok(None) } else { var },
+        _ => return Ok(None),
+    };
+
+    let certificate = fs::read_to_string(&path)?;
+
+    Ok(certificates::ocsp_request_data(&certificate).map(|hash_data| array![hash_data]))
+}
@@ -0,0 +1,264 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use openssl::base64;
+
+use crate::config;
+
+/// Open a local tunnel to the CSMS endpoint through the proxy configured with `PROXY_URL`.
+///
+/// `http://` proxies are used with HTTP CONNECT, `socks5://` proxies with the SOCKS5 protocol.
+/// Credentials of the proxy are taken from the user info of the URL.
+/// Returns the local address which the WebSocket client connects to or `None` if no proxy is configured.
+pub fn tunnel(host: &str, port: u16) -> Option<SocketAddr> {
+    let proxy_url = match config::var("PROXY_URL") {
+        Ok(var) => if var.is_empty() { return None } else { var },
+        _ => return None,
+    };
+
+    let proxy = match url::Url::parse(&proxy_url) {
+        Ok(res) => res,
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    };
+
+    let listener = match TcpListener::bind("127.0.0.1:0") {
+        Ok(res) => res,
+        Err(e) => panic!("Couldn't open the proxy tunnel ({})", e),
+    };
+
+    let local_address = listener.local_addr().ok()?;
+    let target = (host.to_string(), port);
+
+    // The tunnel serves a single connection attempt.
+    thread::spawn(move || {
+        let client = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) => return println!("Proxy tunnel wasn't used ({})", e),
+        };
+
+        match connect(&proxy, &target.0, target.1) {
+            Ok(upstream) => forward(client, upstream),
+            Err(e) => println!("Couldn't connect through the proxy ({})", e),
+        }
+    });
+
+    Some(local_address)
+}
+
+/// Connect to the target through the proxy.
+fn connect(proxy: &url::Url, host: &str, port: u16) -> io::Result<TcpStream> {
+    let proxy_host = proxy.host_str().unwrap_or_default();
+    let default_port = if proxy.scheme() == "socks5" { 1080 } else { 8080 };
+    let mut stream = TcpStream::connect((proxy_host, proxy.port().unwrap_or(default_port)))?;
+
+    let username = proxy.username();
+    let password = proxy.password().unwrap_or_default();
+
+    match proxy.scheme() {
+        "http" => {
+            let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n", host = host, port = port);
+
+            if !username.is_empty() {
+                request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", base64::encode_block(format!("{}:{}", username, password).as_bytes())));
+            }
+
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes())?;
+
+            // Read the response headers byte by byte not to consume data of the tunnel.
+            let mut response: Vec<u8> = Vec::new();
+            let mut byte = [0u8; 1];
+
+            while !response.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte)?;
+                response.push(byte[0]);
+            }
+
+            let status_line = String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string();
+
+            if status_line.split_whitespace().nth(1) != Some("200") {
+                return Err(io::Error::other(format!("proxy responded with {}", status_line)));
+            }
+        },
+        "socks5" => {
+            // Greeting with the supported authentication methods.
+            if username.is_empty() {
+                stream.write_all(&[5, 1, 0])?;
+            } else {
+                stream.write_all(&[5, 2, 0, 2])?;
+            }
+
+            let mut reply = [0u8; 2];
+            stream.read_exact(&mut reply)?;
+
+            match reply[1] {
+                0 => (),
+                2 => {
+                    let mut request: Vec<u8> = vec![1, username.len() as u8];
+                    request.extend_from_slice(username.as_bytes());
+                    request.push(password.len() as u8);
+                    request.extend_from_slice(password.as_bytes());
+
+                    stream.write_all(&request)?;
+                    stream.read_exact(&mut reply)?;
+
+                    if reply[1] != 0 {
+                        return Err(io::Error::other("proxy rejected the credentials"));
+                    }
+                },
+                _ => return Err(io::Error::other("proxy doesn't support offered authentication methods")),
+            }
+
+            // Connect request with the domain name of the target.
+            let mut request: Vec<u8> = vec![5, 1, 0, 3, host.len() as u8];
+            request.extend_from_slice(host.as_bytes());
+            request.extend_from_slice(&port.to_be_bytes());
+
+            stream.write_all(&request)?;
+
+            let mut header = [0u8; 4];
+            stream.read_exact(&mut header)?;
+
+            if header[1] != 0 {
+                return Err(io::Error::other(format!("proxy responded with code {}", header[1])));
+            }
+
+            // Skip the bound address and port.
+            let address_length = match header[3] {
+                1 => 4,
+                4 => 16,
+                _ => {
+                    let mut length = [0u8; 1];
+                    stream.read_exact(&mut length)?;
+
+                    length[0] as usize
+                },
+            };
+
+            let mut bound_address = vec![0u8; address_length + 2];
+            stream.read_exact(&mut bound_address)?;
+        },
+        scheme => return Err(io::Error::other(format!("proxy scheme {} is not supported", scheme))),
+    }
+
+    Ok(stream)
+}
+
+/// Copy data between the client and the proxy in both directions until one of them closes the connection.
+fn forward(client: TcpStream, upstream: TcpStream) {
+    let (mut client_reader, mut upstream_writer) = match (client.try_clone(), upstream.try_clone()) {
+        (Ok(client_reader), Ok(upstream_writer)) => (client_reader, upstream_writer),
+        _ => return,
+    };
+
+    thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut upstream_writer);
+        let _ = upstream_writer.shutdown(std::net::Shutdown::Both);
+    });
+
+    let (mut upstream_reader, mut client_writer) = (upstream, client);
+
+    let _ = io::copy(&mut upstream_reader, &mut client_writer);
+    let _ = client_writer.shutdown(std::net::Shutdown::Both);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start a fake proxy which serves one connection and returns what the client has sent.
+    fn fake_proxy<F: FnOnce(&mut TcpStream) -> Vec<u8> + Send + 'static>(scheme: &str, serve: F) -> (url::Url, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            serve(&mut stream)
+        });
+
+        (url::Url::parse(&format!("{}://user:secret@127.0.0.1:{}", scheme, port)).unwrap(), handle)
+    }
+
+    fn read_headers(stream: &mut TcpStream) -> Vec<u8> {
+        let mut request: Vec<u8> = Vec::new();
+        let mut byte = [0u8; 1];
+
+        while !request.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            request.push(byte[0]);
+        }
+
+        request
+    }
+
+    #[test]
+    fn http_connect_sends_credentials_and_accepts_200() {
+        let (proxy, handle) = fake_proxy("http", |stream| {
+            let request = read_headers(stream);
+
+            stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").unwrap();
+
+            request
+        });
+
+        assert!(connect(&proxy, "csms.example", 443).is_ok());
+
+        let request = String::from_utf8(handle.join().unwrap()).unwrap();
+
+        assert!(request.starts_with("CONNECT csms.example:443 HTTP/1.1\r\n"));
+        assert!(request.contains(&format!("Proxy-Authorization: Basic {}\r\n", base64::encode_block(b"user:secret"))));
+    }
+
+    #[test]
+    fn http_connect_fails_on_other_status() {
+        let (proxy, handle) = fake_proxy("http", |stream| {
+            let request = read_headers(stream);
+
+            stream.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").unwrap();
+
+            request
+        });
+
+        assert!(connect(&proxy, "csms.example", 443).is_err());
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn socks5_authenticates_and_connects_to_the_domain() {
+        let (proxy, handle) = fake_proxy("socks5", |stream| {
+            let mut sent: Vec<u8> = Vec::new();
+
+            let mut greeting = [0u8; 4];
+            stream.read_exact(&mut greeting).unwrap();
+            sent.extend_from_slice(&greeting);
+            stream.write_all(&[5, 2]).unwrap();
+
+            let mut credentials = [0u8; 13];
+            stream.read_exact(&mut credentials).unwrap();
+            sent.extend_from_slice(&credentials);
+            stream.write_all(&[1, 0]).unwrap();
+
+            let mut request = [0u8; 19];
+            stream.read_exact(&mut request).unwrap();
+            sent.extend_from_slice(&request);
+            stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80]).unwrap();
+
+            sent
+        });
+
+        assert!(connect(&proxy, "csms.example", 443).is_ok());
+
+        let mut expected: Vec<u8> = vec![5, 2, 0, 2, 1, 4];
+        expected.extend_from_slice(b"user");
+        expected.push(6);
+        expected.extend_from_slice(b"secret");
+        expected.extend_from_slice(&[5, 1, 0, 3, 12]);
+        expected.extend_from_slice(b"csms.example");
+        expected.extend_from_slice(&443u16.to_be_bytes());
+
+        assert_eq!(handle.join().unwrap(), expected);
+    }
+}
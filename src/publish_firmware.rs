@@ -0,0 +1,190 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use json::JsonValue;
+use openssl::hash::{hash, MessageDigest};
+
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::firmware;
+use crate::ids;
+use crate::requests;
+use crate::storage;
+
+// Default port of the HTTP server which hosts published firmware.
+const DEFAULT_PORT: u16 = 8081;
+// Interval between checks for incoming connections (ms).
+const POLL_INTERVAL: u64 = 100;
+
+/// Start publishing firmware from a PublishFirmware request.
+///
+/// Firmware is downloaded in the background, verified with its MD5 checksum and hosted over HTTP
+/// on `PUBLISH_FIRMWARE_PORT` for the charging stations behind the local controller.
+pub fn publish(request_id: u64, payload: &JsonValue) -> &'static str {
+    let location: String = match payload["location"].as_str() {
+        Some(res) => res.to_string(),
+        None => return "Rejected",
+    };
+
+    let checksum: String = match payload["checksum"].as_str() {
+        Some(res) => res.to_lowercase(),
+        None => return "Rejected",
+    };
+
+    let retries: u64 = payload["retries"].as_u64().unwrap_or(0);
+    let retry_interval: u64 = payload["retryInterval"].as_u64().unwrap_or(30);
+
+    let published_firmware = object!{
+        "requestId" => request_id,
+        "checksum" => checksum.to_owned(),
+        "state" => "Downloading",
+    };
+
+    storage::set_published_firmware(Some(published_firmware.dump()));
+
+    storage::spawn(move || run(request_id, &location, &checksum, retries, retry_interval));
+
+    "Accepted"
+}
+
+/// Stop publishing firmware from an UnpublishFirmware request.
+///
+/// Returns the status of the request: `Unpublished`, `NoFirmware` or `DownloadOngoing`.
+pub fn unpublish(checksum: &str) -> Result<&'static str, EmulatorError> {
+    let published_firmware = match storage::get_published_firmware() {
+        Some(res) => parse(&res)?,
+        None => return Ok("NoFirmware"),
+    };
+
+    if published_firmware["checksum"] != checksum.to_lowercase() {
+        return Ok("NoFirmware");
+    }
+
+    if published_firmware["state"] == "Downloading" {
+        return Ok("DownloadOngoing");
+    }
+
+    storage::set_published_firmware(None);
+
+    Ok("Unpublished")
+}
+
+/// Download, verify and host firmware.
+fn run(request_id: u64, location: &str, checksum: &str, retries: u64, retry_interval: u64) {
+    let mut data: Option<Vec<u8>> = None;
+
+    for attempt in 0..=retries {
+        if !is_active(request_id) {
+            return;
+        }
+
+        if attempt > 0 {
+            thread::sleep(Duration::from_secs(retry_interval));
+        }
+
+        notify(request_id, "Downloading", None);
+
+        match firmware::download(location) {
+            Ok(res) => {
+                data = Some(res);
+                break;
+            },
+            Err(e) => println!("Firmware download failed: {}", e),
+        };
+    }
+
+    let data = match data {
+        Some(res) => res,
+        None => {
+            notify(request_id, "DownloadFailed", None);
+            storage::set_published_firmware(None);
+            return;
+        },
+    };
+
+    notify(request_id, "Downloaded", None);
+
+    let digest: String = match hash(MessageDigest::md5(), &data) {
+        Ok(res) => res.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        Err(_) => String::from(""),
+    };
+
+    if digest != checksum {
+        notify(request_id, "InvalidChecksum", None);
+        storage::set_published_firmware(None);
+        return;
+    }
+
+    notify(request_id, "ChecksumVerified", None);
+
+    let port: u16 = config::var("PUBLISH_FIRMWARE_PORT").ok().and_then(|var| var.parse().ok()).unwrap_or(DEFAULT_PORT);
+
+    let listener = match TcpListener::bind(("0.0.0.0", port)).and_then(|listener| listener.set_nonblocking(true).map(|_| listener)) {
+        Ok(res) => res,
+        Err(e) => {
+            println!("Couldn't host firmware ({})", e);
+            notify(request_id, "PublishFailed", None);
+            storage::set_published_firmware(None);
+            return;
+        },
+    };
+
+    if let Some(Ok(mut published_firmware)) = storage::get_published_firmware().map(|published_firmware| parse(&published_firmware)) {
+        published_firmware["state"] = "Published".into();
+
+        storage::set_published_firmware(Some(published_firmware.dump()));
+    }
+
+    let host = match config::var("PUBLISH_FIRMWARE_HOST") {
+        Ok(var) => if var.is_empty() { String::from("localhost") } else { var },
+        _ => String::from("localhost"),
+    };
+    let published_location = format!("http://{}:{}/firmware-{}", host, port, request_id);
+
+    notify(request_id, "Published", Some(array![published_location]));
+
+    // Serve firmware until it's unpublished.
+    while is_active(request_id) {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                let _ = stream.set_nonblocking(false);
+
+                // Every request is answered with the firmware file.
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+
+                let header = format!("HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", data.len());
+
+                if let Err(e) = stream.write_all(header.as_bytes()).and_then(|_| stream.write_all(&data)) {
+                    println!("Couldn't send firmware ({})", e);
+                }
+            },
+            Err(_) => thread::sleep(Duration::from_millis(POLL_INTERVAL)),
+        };
+    }
+
+    println!("Firmware {} was unpublished.", request_id);
+}
+
+/// Queue a PublishFirmwareStatusNotification request.
+fn notify(request_id: u64, status: &str, location: Option<JsonValue>) {
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::publish_firmware_status_notification(msg_id, status, request_id, location);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
+
+fn is_active(request_id: u64) -> bool {
+    match storage::get_published_firmware() {
+        Some(published_firmware) => parse(&published_firmware).is_ok_and(|published_firmware| published_firmware["requestId"] == request_id),
+        None => false,
+    }
+}
+
+fn parse(value: &str) -> Result<JsonValue, EmulatorError> {
+    Ok(json::parse(value)?)
+}
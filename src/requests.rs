@@ -1,5 +1,9 @@
-use chrono::prelude::*;
 use json::stringify;
+use json::JsonValue;
+
+use crate::clock;
+use crate::errors::EmulatorError;
+use crate::storage;
 
 // OCPP constant.
 const CALL: u8 = 2;
@@ -9,7 +13,7 @@ fn wrap_call(msg_id: &str, action: &str, payload: &str) -> String {
     format!("[{}, \"{}\", \"{}\", {}]", CALL, msg_id, action, payload)
 }
 
-pub fn boot_notification(msg_id: &str, reason: &str, model: &str, vendor_name: &str, serial_number: Option<String>) -> String {
+pub fn boot_notification(msg_id: &str, reason: &str, model: &str, vendor_name: &str, serial_number: Option<String>, firmware_version: Option<String>, modem: Option<JsonValue>) -> String {
     let action = "BootNotification";
     let mut payload = object!{
         "reason" => reason,
@@ -19,20 +23,24 @@ pub fn boot_notification(msg_id: &str, reason: &str, model: &str, vendor_name: &
         },
     };
 
-    match serial_number {
-        Some(data) => payload["chargingStation"]["serialNumber"] = data.into(),
-        _ => (),
-    };
+    if let Some(data) = serial_number {
+        payload["chargingStation"]["serialNumber"] = data.into();
+    }
+
+    if let Some(data) = firmware_version {
+        payload["chargingStation"]["firmwareVersion"] = data.into();
+    }
+
+    if let Some(data) = modem {
+        payload["chargingStation"]["modem"] = data;
+    }
 
     wrap_call(msg_id, action, &stringify(payload))
 }
 
-pub fn status_notification(msg_id: &str, evse_id: u8, connector_id: u8, status: &str) -> String {
+pub fn status_notification(msg_id: &str, evse_id: u8, connector_id: u8, status: &str) -> Result<String, EmulatorError> {
     let action = "StatusNotification";
-    let now = match Utc::now().with_nanosecond(0) {
-        Some(res) => res.to_rfc3339(),
-        None => panic!("Current date is empty."),
-    };
+    let now = clock::timestamp()?;
     let payload = object!{
         "timestamp" => now,
         "connectorStatus" => status,
@@ -40,7 +48,7 @@ pub fn status_notification(msg_id: &str, evse_id: u8, connector_id: u8, status:
         "connectorId" => connector_id,
     };
 
-    wrap_call(msg_id, action, &stringify(payload))
+    Ok(wrap_call(msg_id, action, &stringify(payload)))
 }
 
 pub fn heartbeat(msg_id: &str) -> String {
@@ -50,35 +58,337 @@ pub fn heartbeat(msg_id: &str) -> String {
     wrap_call(msg_id, action, payload)
 }
 
-pub fn transaction_event(msg_id: &str, transaction_id: &str, event_type: &str, trigger_reason: &str, charging_state: Option<&str>, remote_start_id: Option<u64>, stopped_reason: Option<&str>) -> String {
+/// Data of a TransactionEvent request which is kept by the transaction.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionEvent<'a> {
+    pub event_type: &'a str,
+    pub trigger_reason: &'a str,
+    // Sequence number of the event within the transaction.
+    pub seq_no: u64,
+    // Whether the event happened while the station was offline.
+    pub offline: bool,
+    pub charging_state: Option<&'a str>,
+    pub remote_start_id: Option<u64>,
+    pub stopped_reason: Option<&'a str>,
+    pub id_token: Option<JsonValue>,
+    pub meter_value: Option<JsonValue>,
+    pub reservation_id: Option<u64>,
+    pub number_of_phases_used: Option<u8>,
+    // Maximum current (A) of the connector cable.
+    pub cable_max_current: Option<f64>,
+    pub evse: Option<JsonValue>,
+}
+
+pub fn transaction_event(msg_id: &str, transaction_id: &str, event: TransactionEvent) -> Result<String, EmulatorError> {
     let action = "TransactionEvent";
-    let now = match Utc::now().with_nanosecond(0) {
-        Some(res) => res.to_rfc3339(),
-        None => panic!("Current date is empty."),
+    let now = clock::timestamp()?;
+    // Transaction data was renamed in OCPP 2.0.1.
+    let (data_key, id_key) = match storage::get_protocol() {
+        "ocpp2.0.1" => ("transactionInfo", "transactionId"),
+        _ => ("transactionData", "id"),
     };
     let mut payload = object!{
-        "eventType" => event_type,
+        "eventType" => event.event_type,
         "timestamp" => now,
-        "triggerReason" => trigger_reason,
-        "seqNo" => 0,
-        "transactionData" => object!{
-            "id" => transaction_id,
+        "triggerReason" => event.trigger_reason,
+        "seqNo" => event.seq_no,
+    };
+
+    payload[data_key] = object!{};
+    payload[data_key][id_key] = transaction_id.into();
+
+    if event.offline {
+        payload["offline"] = true.into();
+    }
+
+    if let Some(data) = event.charging_state {
+        payload[data_key]["chargingState"] = data.into();
+    }
+
+    if let Some(data) = event.remote_start_id {
+        payload[data_key]["remoteStartId"] = data.into();
+    }
+
+    if let Some(data) = event.stopped_reason {
+        payload[data_key]["stoppedReason"] = data.into();
+    }
+
+    if let Some(data) = event.id_token {
+        payload["idToken"] = data;
+    }
+
+    if let Some(data) = event.meter_value {
+        payload["meterValue"] = data;
+    }
+
+    if let Some(data) = event.reservation_id {
+        payload["reservationId"] = data.into();
+    }
+
+    if let Some(data) = event.number_of_phases_used {
+        payload["numberOfPhasesUsed"] = data.into();
+    }
+
+    if let Some(data) = event.cable_max_current {
+        payload["cableMaxCurrent"] = (data.round() as i64).into();
+    }
+
+    if let Some(data) = event.evse {
+        payload["evse"] = data;
+    }
+
+    Ok(wrap_call(msg_id, action, &stringify(payload)))
+}
+
+/// Get the transaction id of a TransactionEvent request payload of OCPP 2.0 or 2.0.1.
+pub fn transaction_event_id(payload: &JsonValue) -> String {
+    if payload["transactionInfo"].is_null() {
+        payload["transactionData"]["id"].to_string()
+    } else {
+        payload["transactionInfo"]["transactionId"].to_string()
+    }
+}
+
+pub fn reservation_status_update(msg_id: &str, reservation_id: u64, status: &str) -> String {
+    let action = "ReservationStatusUpdate";
+    let payload = object!{
+        "reservationId" => reservation_id,
+        "reservationUpdateStatus" => status,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_display_messages(msg_id: &str, request_id: u64, message_info: JsonValue, tbc: bool) -> String {
+    let action = "NotifyDisplayMessages";
+    let payload = object!{
+        "requestId" => request_id,
+        "tbc" => tbc,
+        "messageInfo" => message_info,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn data_transfer(msg_id: &str, vendor_id: &str, message_id: Option<&str>, data: Option<JsonValue>) -> String {
+    let action = "DataTransfer";
+    let mut payload = object!{
+        "vendorId" => vendor_id,
+    };
+
+    if let Some(data) = message_id {
+        payload["messageId"] = data.into();
+    }
+
+    if let Some(data) = data {
+        payload["data"] = data;
+    }
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn firmware_status_notification(msg_id: &str, status: &str, request_id: u64) -> String {
+    let action = "FirmwareStatusNotification";
+    let payload = object!{
+        "status" => status,
+        "requestId" => request_id,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn publish_firmware_status_notification(msg_id: &str, status: &str, request_id: u64, location: Option<JsonValue>) -> String {
+    let action = "PublishFirmwareStatusNotification";
+    let mut payload = object!{
+        "status" => status,
+        "requestId" => request_id,
+    };
+
+    if let Some(data) = location {
+        payload["location"] = data;
+    }
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn log_status_notification(msg_id: &str, status: &str, request_id: u64) -> String {
+    let action = "LogStatusNotification";
+    let payload = object!{
+        "status" => status,
+        "requestId" => request_id,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_customer_information(msg_id: &str, data: &str, tbc: bool, seq_no: u64, generated_at: &str, request_id: u64) -> String {
+    let action = "NotifyCustomerInformation";
+    let payload = object!{
+        "data" => data,
+        "tbc" => tbc,
+        "seqNo" => seq_no,
+        "generatedAt" => generated_at,
+        "requestId" => request_id,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_event(msg_id: &str, generated_at: &str, seq_no: u64, event_data: JsonValue, tbc: bool) -> String {
+    let action = "NotifyEvent";
+    let payload = object!{
+        "generatedAt" => generated_at,
+        "tbc" => tbc,
+        "seqNo" => seq_no,
+        "eventData" => event_data,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_report(msg_id: &str, request_id: u64, generated_at: &str, seq_no: u64, report_data: JsonValue, tbc: bool) -> String {
+    let action = "NotifyReport";
+    let payload = object!{
+        "requestId" => request_id,
+        "generatedAt" => generated_at,
+        "tbc" => tbc,
+        "seqNo" => seq_no,
+        "reportData" => report_data,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_monitoring_report(msg_id: &str, request_id: u64, generated_at: &str, seq_no: u64, monitor: JsonValue, tbc: bool) -> String {
+    let action = "NotifyMonitoringReport";
+    let payload = object!{
+        "requestId" => request_id,
+        "tbc" => tbc,
+        "seqNo" => seq_no,
+        "generatedAt" => generated_at,
+        "monitor" => monitor,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn sign_certificate(msg_id: &str, csr: &str, certificate_type: &str) -> String {
+    let action = "SignCertificate";
+    let payload = object!{
+        "csr" => csr,
+        "certificateType" => certificate_type,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn get_15118_ev_certificate(msg_id: &str, iso15118_schema_version: &str, action: &str, exi_request: &str) -> String {
+    let action_name = "Get15118EVCertificate";
+    let payload = object!{
+        "iso15118SchemaVersion" => iso15118_schema_version,
+        "action" => action,
+        "exiRequest" => exi_request,
+    };
+
+    wrap_call(msg_id, action_name, &stringify(payload))
+}
+
+pub fn authorize(msg_id: &str, id_token: &str, id_token_type: &str, additional_info: Option<JsonValue>, certificate_hash_data: Option<JsonValue>) -> String {
+    let action = "Authorize";
+    let mut payload = object!{
+        "idToken" => object!{
+            "idToken" => id_token,
+            "type" => id_token_type,
         },
     };
 
-    match charging_state {
-        Some(data) => payload["transactionData"]["chargingState"] = data.into(),
-        _ => (),
+    if let Some(data) = additional_info {
+        payload["idToken"]["additionalInfo"] = data;
+    }
+
+    if let Some(data) = certificate_hash_data {
+        payload["iso15118CertificateHashData"] = data;
+    }
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn get_certificate_status(msg_id: &str, ocsp_request_data: JsonValue) -> String {
+    let action = "GetCertificateStatus";
+    let payload = object!{
+        "ocspRequestData" => ocsp_request_data,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn security_event_notification(msg_id: &str, event_type: &str, timestamp: &str, tech_info: Option<&str>) -> String {
+    let action = "SecurityEventNotification";
+    let mut payload = object!{
+        "type" => event_type,
+        "timestamp" => timestamp,
+    };
+
+    if let Some(data) = tech_info {
+        payload["techInfo"] = data.into();
+    }
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn meter_values(msg_id: &str, evse_id: usize, meter_value: JsonValue) -> String {
+    let action = "MeterValues";
+    let payload = object!{
+        "evseId" => evse_id,
+        "meterValue" => meter_value,
+    };
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_charging_limit(msg_id: &str, charging_limit: JsonValue, evse_id: Option<JsonValue>, charging_schedule: JsonValue) -> String {
+    let action = "NotifyChargingLimit";
+    let mut payload = object!{
+        "chargingLimit" => charging_limit,
+        "chargingSchedule" => charging_schedule,
     };
 
-    match remote_start_id {
-        Some(data) => payload["transactionData"]["remoteStartId"] = data.into(),
-        _ => (),
+    if let Some(data) = evse_id {
+        payload["evseId"] = data;
+    }
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn cleared_charging_limit(msg_id: &str, charging_limit_source: &str, evse_id: Option<JsonValue>) -> String {
+    let action = "ClearedChargingLimit";
+    let mut payload = object!{
+        "chargingLimitSource" => charging_limit_source,
+    };
+
+    if let Some(data) = evse_id {
+        payload["evseId"] = data;
+    }
+
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_ev_charging_needs(msg_id: &str, evse_id: usize, charging_needs: JsonValue) -> String {
+    let action = "NotifyEVChargingNeeds";
+    let payload = object!{
+        "evseId" => evse_id,
+        "chargingNeeds" => charging_needs,
     };
 
-    match stopped_reason {
-        Some(data) => payload["transactionData"]["stoppedReason"] = data.into(),
-        _ => (),
+    wrap_call(msg_id, action, &stringify(payload))
+}
+
+pub fn notify_ev_charging_schedule(msg_id: &str, time_base: &str, evse_id: usize, charging_schedule: JsonValue) -> String {
+    let action = "NotifyEVChargingSchedule";
+    let payload = object!{
+        "timeBase" => time_base,
+        "evseId" => evse_id,
+        "chargingSchedule" => charging_schedule,
     };
 
     wrap_call(msg_id, action, &stringify(payload))
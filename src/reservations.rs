@@ -0,0 +1,166 @@
+use chrono::prelude::*;
+use json::JsonValue;
+
+use crate::clock;
+use crate::errors::EmulatorError;
+use crate::ids;
+use crate::requests;
+use crate::state_machine;
+use crate::state_machine::Event;
+use crate::storage;
+
+/// Create a reservation from a ReserveNow request.
+///
+/// Reservation with the same id on another EVSE is moved, the EVSE which it held becomes available.
+/// Returns the index of the reserved EVSE and the number of milliseconds until the reservation
+/// expires, or the status to reject the request with.
+pub fn reserve(reservation_id: u64, payload: &JsonValue) -> Result<(usize, u64), &'static str> {
+    let expiry_date_time = match DateTime::parse_from_rfc3339(&payload["expiryDateTime"].to_string()) {
+        Ok(res) => res.with_timezone(&Utc),
+        Err(_) => return Err("Rejected"),
+    };
+
+    let now = clock::now();
+
+    if expiry_date_time <= now {
+        return Err("Rejected");
+    }
+
+    // Reservations without EVSE are made on the first EVSE.
+    let evse_id: usize = payload["evseId"].as_usize().unwrap_or(1);
+
+    if evse_id == 0 || evse_id > storage::evse_count() {
+        return Err("Rejected");
+    }
+
+    // Reservation with the same id replaces the existing one, the one which can't be parsed is just overwritten.
+    let replaced_evse_index = match get(reservation_id) {
+        Ok(Some(reservation)) => reservation["evseId"].as_usize().map(|id| id - 1),
+        _ => None,
+    };
+
+    // EVSE can be reserved if any of its connectors is available.
+    if replaced_evse_index != Some(evse_id - 1) && storage::find_connector(evse_id - 1, &["Available"]).is_none() {
+        let connectors = storage::get_connectors(evse_id - 1);
+
+        if connectors.iter().all(|connector| !connector.operational || connector.status == "Unavailable") {
+            return Err("Unavailable");
+        }
+
+        if connectors.iter().all(|connector| connector.status == "Faulted") {
+            return Err("Faulted");
+        }
+
+        return Err("Occupied");
+    }
+
+    let reservation = object!{
+        "id" => reservation_id,
+        "evseId" => evse_id,
+        "idToken" => payload["idToken"].clone(),
+        "expiryDateTime" => payload["expiryDateTime"].to_string(),
+    };
+
+    storage::set_reservation(reservation_id, reservation.dump());
+
+    if let Some(evse_index) = replaced_evse_index.filter(|evse_index| *evse_index != evse_id - 1) {
+        // Set status of connectors of the previously reserved EVSE to "Available" and send StatusNotification with updated status.
+        state_machine::handle_evse(evse_index, Event::ReservationEnded);
+    }
+
+    Ok((evse_id - 1, (expiry_date_time - now).num_milliseconds() as u64))
+}
+
+/// Get a stored reservation by its id.
+pub fn get(reservation_id: u64) -> Result<Option<JsonValue>, EmulatorError> {
+    let reservation = storage::get_reservation(reservation_id);
+
+    if reservation.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(json::parse(&reservation)?))
+}
+
+/// Cancel a reservation.
+///
+/// Returns the index of the EVSE which was reserved.
+pub fn cancel(reservation_id: u64) -> Result<Option<usize>, EmulatorError> {
+    let reservation = match get(reservation_id)? {
+        Some(res) => res,
+        None => return Ok(None),
+    };
+
+    storage::delete_reservation(reservation_id);
+
+    Ok(reservation["evseId"].as_usize().map(|id| id - 1))
+}
+
+/// Consume a reservation of the EVSE made for the given id token.
+///
+/// Returns the id of the consumed reservation.
+pub fn consume(evse_index: usize, id_token: &str) -> Result<Option<u64>, EmulatorError> {
+    for (reservation_id, reservation) in storage::get_reservations() {
+        let reservation = json::parse(&reservation)?;
+
+        if reservation["evseId"].as_usize() == Some(evse_index + 1) && reservation["idToken"]["idToken"] == id_token {
+            storage::delete_reservation(reservation_id);
+
+            return Ok(Some(reservation_id));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Remove expired reservations.
+///
+/// Returns ids of the removed reservations together with indexes of the released EVSEs.
+pub fn remove_expired() -> Result<Vec<(u64, usize)>, EmulatorError> {
+    let now = clock::now();
+    let mut expired = Vec::new();
+
+    for (reservation_id, reservation) in storage::get_reservations() {
+        let reservation = json::parse(&reservation)?;
+        let expiry_date_time = DateTime::parse_from_rfc3339(&reservation["expiryDateTime"].to_string())?.with_timezone(&Utc);
+
+        if expiry_date_time <= now {
+            storage::delete_reservation(reservation_id);
+
+            if let Some(evse_id) = reservation["evseId"].as_usize() {
+                expired.push((reservation_id, evse_id - 1));
+            }
+        }
+    }
+
+    Ok(expired)
+}
+
+/// Remove reservations of the EVSE.
+///
+/// Returns ids of the removed reservations.
+pub fn remove_for_evse(evse_index: usize) -> Result<Vec<u64>, EmulatorError> {
+    let mut removed = Vec::new();
+
+    for (reservation_id, reservation) in storage::get_reservations() {
+        let reservation = json::parse(&reservation)?;
+
+        if reservation["evseId"].as_usize() == Some(evse_index + 1) {
+            storage::delete_reservation(reservation_id);
+
+            removed.push(reservation_id);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Queue a ReservationStatusUpdate request.
+pub fn notify(reservation_id: u64, status: &str) {
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::reservation_status_update(msg_id, reservation_id, status);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+}
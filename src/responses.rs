@@ -1,14 +1,29 @@
 use json::stringify;
 use json::JsonValue;
 
-// OCPP constant.
+use crate::storage;
+
+// OCPP constants.
 const CALLRESULT: u8 = 3;
+const CALLERROR: u8 = 4;
 
 /// Wrap a CALLRESULT message.
 fn wrap_call_result(msg_id: &str, payload: &str) -> String {
     format!("[{}, \"{}\", {}]", CALLRESULT, msg_id, payload)
 }
 
+/// Build a CALLERROR message.
+///
+/// `FormatViolation` of OCPP 2.0.1 is called `FormationViolation` in OCPP 2.0.
+pub fn call_error(msg_id: &str, error_code: &str, error_description: &str) -> String {
+    let error_code = match error_code {
+        "FormatViolation" if storage::get_protocol() == "ocpp2.0" => "FormationViolation",
+        error_code => error_code,
+    };
+
+    format!("[{}, \"{}\", \"{}\", {}, {{}}]", CALLERROR, msg_id, error_code, stringify(error_description))
+}
+
 pub fn set_variables(msg_id: &str, variables: JsonValue) -> String {
     let payload = object!{
         "setVariableResult" => variables,
@@ -25,12 +40,20 @@ pub fn get_variables(msg_id: &str, variables: JsonValue) -> String {
     wrap_call_result(msg_id, &stringify(payload))
 }
 
-pub fn request_start_transaction(msg_id: &str, remote_start_id: u64, status: &str) -> String {
-    let payload = object!{
-        "remoteStartId" => remote_start_id,
+pub fn request_start_transaction(msg_id: &str, remote_start_id: u64, status: &str, transaction_id: Option<String>) -> String {
+    let mut payload = object!{
         "status" => status,
     };
 
+    // OCPP 2.0.1 doesn't echo the remote start id but reports the transaction which was started on the EVSE before.
+    if storage::get_protocol() == "ocpp2.0.1" {
+        if let Some(data) = transaction_id {
+            payload["transactionId"] = data.into();
+        }
+    } else {
+        payload["remoteStartId"] = remote_start_id.into();
+    }
+
     wrap_call_result(msg_id, &stringify(payload))
 }
 
@@ -41,3 +64,261 @@ pub fn request_stop_transaction(msg_id: &str, status: &str) -> String {
 
     wrap_call_result(msg_id, &stringify(payload))
 }
+
+pub fn send_local_list(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn get_local_list_version(msg_id: &str, version_number: u64) -> String {
+    let payload = object!{
+        "versionNumber" => version_number,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn reserve_now(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn change_availability(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn cancel_reservation(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn set_charging_profile(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn clear_charging_profile(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn get_composite_schedule(msg_id: &str, status: &str, schedule: Option<JsonValue>) -> String {
+    let mut payload = object!{
+        "status" => status,
+    };
+
+    if let Some(data) = schedule {
+        payload["schedule"] = data;
+    }
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn set_display_message(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn get_display_messages(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn clear_display_message(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn data_transfer(msg_id: &str, status: &str, data: Option<JsonValue>) -> String {
+    let mut payload = object!{
+        "status" => status,
+    };
+
+    if let Some(data) = data {
+        payload["data"] = data;
+    }
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn update_firmware(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn publish_firmware(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn unpublish_firmware(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn get_log(msg_id: &str, status: &str, filename: Option<String>) -> String {
+    let mut payload = object!{
+        "status" => status,
+    };
+
+    if let Some(data) = filename {
+        payload["filename"] = data.into();
+    }
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn customer_information(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn cost_updated(msg_id: &str) -> String {
+    let payload = "{}";
+
+    wrap_call_result(msg_id, payload)
+}
+
+pub fn get_transaction_status(msg_id: &str, ongoing_indicator: Option<bool>, messages_in_queue: bool) -> String {
+    let mut payload = object!{
+        "messagesInQueue" => messages_in_queue,
+    };
+
+    if let Some(data) = ongoing_indicator {
+        payload["ongoingIndicator"] = data.into();
+    }
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn set_variable_monitoring(msg_id: &str, results: JsonValue) -> String {
+    let payload = object!{
+        "setMonitoringResult" => results,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn clear_variable_monitoring(msg_id: &str, results: JsonValue) -> String {
+    let payload = object!{
+        "clearMonitoringResult" => results,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn set_monitoring_base(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn set_monitoring_level(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn get_base_report(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn get_monitoring_report(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn install_certificate(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn delete_certificate(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn get_installed_certificate_ids(msg_id: &str, status: &str, certificate_hash_data_chain: JsonValue) -> String {
+    let mut payload = object!{
+        "status" => status,
+    };
+
+    if !certificate_hash_data_chain.is_empty() {
+        payload["certificateHashDataChain"] = certificate_hash_data_chain;
+    }
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn certificate_signed(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
+
+pub fn trigger_message(msg_id: &str, status: &str) -> String {
+    let payload = object!{
+        "status" => status,
+    };
+
+    wrap_call_result(msg_id, &stringify(payload))
+}
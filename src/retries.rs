@@ -0,0 +1,95 @@
+use crate::clock;
+use crate::components;
+use crate::storage;
+
+// Error codes after which the request can't succeed if it's sent again.
+const PERMANENT_ERROR_CODES: [&str; 3] = ["NotImplemented", "NotSupported", "SecurityError"];
+// Requests which are sent again after any error, transaction data must not be lost.
+const ESCALATED_ACTIONS: [&str; 1] = ["TransactionEvent"];
+
+/// Decide what to do with a request which CSMS has answered with CALLERROR.
+///
+/// Requests are sent again up to `OCPPCommCtrlr.MessageAttempts` times with the interval
+/// `OCPPCommCtrlr.MessageAttemptInterval` multiplied by the number of the attempt.
+/// Requests failed with permanent errors are dropped unless they carry transaction data.
+/// Returns the action taken: `Retry` or `Drop`.
+pub fn handle_call_error(msg_id: &str, error_code: &str) -> &'static str {
+    let msg = storage::get_message(msg_id);
+
+    let action: String = match json::parse(&msg) {
+        Ok(result) => result[2].to_string(),
+        Err(_) => return "Drop",
+    };
+
+    let attempt = storage::get_message_attempts(msg_id) + 1;
+
+    let retryable = ESCALATED_ACTIONS.contains(&action.as_str()) || !PERMANENT_ERROR_CODES.contains(&error_code);
+
+    if !retryable {
+        println!("{} ({}) was dropped after {} attempt(s).", action, msg_id, attempt);
+
+        storage::set_message_attempts(msg_id.to_string(), None);
+
+        return "Drop";
+    }
+
+    retry(msg_id, msg, &action, attempt)
+}
+
+/// Decide what to do with a request which CSMS hasn't answered within `OCPPCommCtrlr.MessageTimeout`.
+///
+/// Request is sent again like after CALLERROR, once `OCPPCommCtrlr.MessageAttempts` are used up it's dropped
+/// and logged in the transcript as a dead letter unless it carries transaction data.
+/// Returns the action taken: `Retry` or `Drop`.
+pub fn handle_timeout(msg_id: &str) -> &'static str {
+    let msg = storage::get_message(msg_id);
+
+    let action: String = match json::parse(&msg) {
+        Ok(result) => result[2].to_string(),
+        Err(_) => return "Drop",
+    };
+
+    println!("{} ({}) was not answered in time.", action, msg_id);
+
+    retry(msg_id, msg, &action, storage::get_message_attempts(msg_id) + 1)
+}
+
+/// Send the request again after the interval of the given attempt unless its attempts are used up.
+///
+/// Requests with transaction data are escalated instead of being dropped: they're sent again with the interval
+/// of the last attempt until CSMS accepts them.
+fn retry(msg_id: &str, msg: String, action: &str, attempt: u32) -> &'static str {
+    let max_attempts = variable("MessageAttempts");
+    let escalated = attempt >= max_attempts && ESCALATED_ACTIONS.contains(&action);
+
+    if attempt >= max_attempts && !escalated {
+        println!("{} ({}) was dropped after {} attempt(s).", action, msg_id, attempt);
+
+        storage::set_message_attempts(msg_id.to_string(), None);
+        storage::transcript_add("Dropped", &msg);
+
+        return "Drop";
+    }
+
+    storage::set_message_attempts(msg_id.to_string(), Some(attempt));
+
+    let interval = variable("MessageAttemptInterval") as i64 * attempt.min(max_attempts.max(1)) as i64;
+
+    if escalated {
+        println!("{} ({}) has used up {} attempt(s) and is escalated, it will be sent again in {} s.", action, msg_id, max_attempts, interval);
+    } else {
+        println!("{} ({}) will be sent again in {} s.", action, msg_id, interval);
+    }
+
+    storage::delay_message(clock::local_now().timestamp_millis() + interval * 1000, msg);
+
+    "Retry"
+}
+
+/// Get the value of an OCPPCommCtrlr variable.
+fn variable(variable_name: &str) -> u32 {
+    match components::get_variable("OCPPCommCtrlr", variable_name).1 {
+        Some(value) => value.parse().unwrap_or(0),
+        None => 0,
+    }
+}
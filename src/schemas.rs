@@ -0,0 +1,424 @@
+use std::fs;
+use std::path::Path;
+
+use json::JsonValue;
+
+use crate::config;
+use crate::storage;
+
+// Official OCPP 2.0.1 schemas of all messages, they're used on OCPP 2.0.1 connections unless `OCPP_SCHEMAS_DIR` has
+// the schema of the message.
+const EMBEDDED_SCHEMAS: [(&str, &str); 128] = [
+    ("AuthorizeRequest", include_str!("../schemas/AuthorizeRequest.json")),
+    ("AuthorizeResponse", include_str!("../schemas/AuthorizeResponse.json")),
+    ("BootNotificationRequest", include_str!("../schemas/BootNotificationRequest.json")),
+    ("BootNotificationResponse", include_str!("../schemas/BootNotificationResponse.json")),
+    ("CancelReservationRequest", include_str!("../schemas/CancelReservationRequest.json")),
+    ("CancelReservationResponse", include_str!("../schemas/CancelReservationResponse.json")),
+    ("CertificateSignedRequest", include_str!("../schemas/CertificateSignedRequest.json")),
+    ("CertificateSignedResponse", include_str!("../schemas/CertificateSignedResponse.json")),
+    ("ChangeAvailabilityRequest", include_str!("../schemas/ChangeAvailabilityRequest.json")),
+    ("ChangeAvailabilityResponse", include_str!("../schemas/ChangeAvailabilityResponse.json")),
+    ("ClearCacheRequest", include_str!("../schemas/ClearCacheRequest.json")),
+    ("ClearCacheResponse", include_str!("../schemas/ClearCacheResponse.json")),
+    ("ClearChargingProfileRequest", include_str!("../schemas/ClearChargingProfileRequest.json")),
+    ("ClearChargingProfileResponse", include_str!("../schemas/ClearChargingProfileResponse.json")),
+    ("ClearDisplayMessageRequest", include_str!("../schemas/ClearDisplayMessageRequest.json")),
+    ("ClearDisplayMessageResponse", include_str!("../schemas/ClearDisplayMessageResponse.json")),
+    ("ClearVariableMonitoringRequest", include_str!("../schemas/ClearVariableMonitoringRequest.json")),
+    ("ClearVariableMonitoringResponse", include_str!("../schemas/ClearVariableMonitoringResponse.json")),
+    ("ClearedChargingLimitRequest", include_str!("../schemas/ClearedChargingLimitRequest.json")),
+    ("ClearedChargingLimitResponse", include_str!("../schemas/ClearedChargingLimitResponse.json")),
+    ("CostUpdatedRequest", include_str!("../schemas/CostUpdatedRequest.json")),
+    ("CostUpdatedResponse", include_str!("../schemas/CostUpdatedResponse.json")),
+    ("CustomerInformationRequest", include_str!("../schemas/CustomerInformationRequest.json")),
+    ("CustomerInformationResponse", include_str!("../schemas/CustomerInformationResponse.json")),
+    ("DataTransferRequest", include_str!("../schemas/DataTransferRequest.json")),
+    ("DataTransferResponse", include_str!("../schemas/DataTransferResponse.json")),
+    ("DeleteCertificateRequest", include_str!("../schemas/DeleteCertificateRequest.json")),
+    ("DeleteCertificateResponse", include_str!("../schemas/DeleteCertificateResponse.json")),
+    ("FirmwareStatusNotificationRequest", include_str!("../schemas/FirmwareStatusNotificationRequest.json")),
+    ("FirmwareStatusNotificationResponse", include_str!("../schemas/FirmwareStatusNotificationResponse.json")),
+    ("Get15118EVCertificateRequest", include_str!("../schemas/Get15118EVCertificateRequest.json")),
+    ("Get15118EVCertificateResponse", include_str!("../schemas/Get15118EVCertificateResponse.json")),
+    ("GetBaseReportRequest", include_str!("../schemas/GetBaseReportRequest.json")),
+    ("GetBaseReportResponse", include_str!("../schemas/GetBaseReportResponse.json")),
+    ("GetCertificateStatusRequest", include_str!("../schemas/GetCertificateStatusRequest.json")),
+    ("GetCertificateStatusResponse", include_str!("../schemas/GetCertificateStatusResponse.json")),
+    ("GetChargingProfilesRequest", include_str!("../schemas/GetChargingProfilesRequest.json")),
+    ("GetChargingProfilesResponse", include_str!("../schemas/GetChargingProfilesResponse.json")),
+    ("GetCompositeScheduleRequest", include_str!("../schemas/GetCompositeScheduleRequest.json")),
+    ("GetCompositeScheduleResponse", include_str!("../schemas/GetCompositeScheduleResponse.json")),
+    ("GetDisplayMessagesRequest", include_str!("../schemas/GetDisplayMessagesRequest.json")),
+    ("GetDisplayMessagesResponse", include_str!("../schemas/GetDisplayMessagesResponse.json")),
+    ("GetInstalledCertificateIdsRequest", include_str!("../schemas/GetInstalledCertificateIdsRequest.json")),
+    ("GetInstalledCertificateIdsResponse", include_str!("../schemas/GetInstalledCertificateIdsResponse.json")),
+    ("GetLocalListVersionRequest", include_str!("../schemas/GetLocalListVersionRequest.json")),
+    ("GetLocalListVersionResponse", include_str!("../schemas/GetLocalListVersionResponse.json")),
+    ("GetLogRequest", include_str!("../schemas/GetLogRequest.json")),
+    ("GetLogResponse", include_str!("../schemas/GetLogResponse.json")),
+    ("GetMonitoringReportRequest", include_str!("../schemas/GetMonitoringReportRequest.json")),
+    ("GetMonitoringReportResponse", include_str!("../schemas/GetMonitoringReportResponse.json")),
+    ("GetReportRequest", include_str!("../schemas/GetReportRequest.json")),
+    ("GetReportResponse", include_str!("../schemas/GetReportResponse.json")),
+    ("GetTransactionStatusRequest", include_str!("../schemas/GetTransactionStatusRequest.json")),
+    ("GetTransactionStatusResponse", include_str!("../schemas/GetTransactionStatusResponse.json")),
+    ("GetVariablesRequest", include_str!("../schemas/GetVariablesRequest.json")),
+    ("GetVariablesResponse", include_str!("../schemas/GetVariablesResponse.json")),
+    ("HeartbeatRequest", include_str!("../schemas/HeartbeatRequest.json")),
+    ("HeartbeatResponse", include_str!("../schemas/HeartbeatResponse.json")),
+    ("InstallCertificateRequest", include_str!("../schemas/InstallCertificateRequest.json")),
+    ("InstallCertificateResponse", include_str!("../schemas/InstallCertificateResponse.json")),
+    ("LogStatusNotificationRequest", include_str!("../schemas/LogStatusNotificationRequest.json")),
+    ("LogStatusNotificationResponse", include_str!("../schemas/LogStatusNotificationResponse.json")),
+    ("MeterValuesRequest", include_str!("../schemas/MeterValuesRequest.json")),
+    ("MeterValuesResponse", include_str!("../schemas/MeterValuesResponse.json")),
+    ("NotifyChargingLimitRequest", include_str!("../schemas/NotifyChargingLimitRequest.json")),
+    ("NotifyChargingLimitResponse", include_str!("../schemas/NotifyChargingLimitResponse.json")),
+    ("NotifyCustomerInformationRequest", include_str!("../schemas/NotifyCustomerInformationRequest.json")),
+    ("NotifyCustomerInformationResponse", include_str!("../schemas/NotifyCustomerInformationResponse.json")),
+    ("NotifyDisplayMessagesRequest", include_str!("../schemas/NotifyDisplayMessagesRequest.json")),
+    ("NotifyDisplayMessagesResponse", include_str!("../schemas/NotifyDisplayMessagesResponse.json")),
+    ("NotifyEVChargingNeedsRequest", include_str!("../schemas/NotifyEVChargingNeedsRequest.json")),
+    ("NotifyEVChargingNeedsResponse", include_str!("../schemas/NotifyEVChargingNeedsResponse.json")),
+    ("NotifyEVChargingScheduleRequest", include_str!("../schemas/NotifyEVChargingScheduleRequest.json")),
+    ("NotifyEVChargingScheduleResponse", include_str!("../schemas/NotifyEVChargingScheduleResponse.json")),
+    ("NotifyEventRequest", include_str!("../schemas/NotifyEventRequest.json")),
+    ("NotifyEventResponse", include_str!("../schemas/NotifyEventResponse.json")),
+    ("NotifyMonitoringReportRequest", include_str!("../schemas/NotifyMonitoringReportRequest.json")),
+    ("NotifyMonitoringReportResponse", include_str!("../schemas/NotifyMonitoringReportResponse.json")),
+    ("NotifyReportRequest", include_str!("../schemas/NotifyReportRequest.json")),
+    ("NotifyReportResponse", include_str!("../schemas/NotifyReportResponse.json")),
+    ("PublishFirmwareRequest", include_str!("../schemas/PublishFirmwareRequest.json")),
+    ("PublishFirmwareResponse", include_str!("../schemas/PublishFirmwareResponse.json")),
+    ("PublishFirmwareStatusNotificationRequest", include_str!("../schemas/PublishFirmwareStatusNotificationRequest.json")),
+    ("PublishFirmwareStatusNotificationResponse", include_str!("../schemas/PublishFirmwareStatusNotificationResponse.json")),
+    ("ReportChargingProfilesRequest", include_str!("../schemas/ReportChargingProfilesRequest.json")),
+    ("ReportChargingProfilesResponse", include_str!("../schemas/ReportChargingProfilesResponse.json")),
+    ("RequestStartTransactionRequest", include_str!("../schemas/RequestStartTransactionRequest.json")),
+    ("RequestStartTransactionResponse", include_str!("../schemas/RequestStartTransactionResponse.json")),
+    ("RequestStopTransactionRequest", include_str!("../schemas/RequestStopTransactionRequest.json")),
+    ("RequestStopTransactionResponse", include_str!("../schemas/RequestStopTransactionResponse.json")),
+    ("ReservationStatusUpdateRequest", include_str!("../schemas/ReservationStatusUpdateRequest.json")),
+    ("ReservationStatusUpdateResponse", include_str!("../schemas/ReservationStatusUpdateResponse.json")),
+    ("ReserveNowRequest", include_str!("../schemas/ReserveNowRequest.json")),
+    ("ReserveNowResponse", include_str!("../schemas/ReserveNowResponse.json")),
+    ("ResetRequest", include_str!("../schemas/ResetRequest.json")),
+    ("ResetResponse", include_str!("../schemas/ResetResponse.json")),
+    ("SecurityEventNotificationRequest", include_str!("../schemas/SecurityEventNotificationRequest.json")),
+    ("SecurityEventNotificationResponse", include_str!("../schemas/SecurityEventNotificationResponse.json")),
+    ("SendLocalListRequest", include_str!("../schemas/SendLocalListRequest.json")),
+    ("SendLocalListResponse", include_str!("../schemas/SendLocalListResponse.json")),
+    ("SetChargingProfileRequest", include_str!("../schemas/SetChargingProfileRequest.json")),
+    ("SetChargingProfileResponse", include_str!("../schemas/SetChargingProfileResponse.json")),
+    ("SetDisplayMessageRequest", include_str!("../schemas/SetDisplayMessageRequest.json")),
+    ("SetDisplayMessageResponse", include_str!("../schemas/SetDisplayMessageResponse.json")),
+    ("SetMonitoringBaseRequest", include_str!("../schemas/SetMonitoringBaseRequest.json")),
+    ("SetMonitoringBaseResponse", include_str!("../schemas/SetMonitoringBaseResponse.json")),
+    ("SetMonitoringLevelRequest", include_str!("../schemas/SetMonitoringLevelRequest.json")),
+    ("SetMonitoringLevelResponse", include_str!("../schemas/SetMonitoringLevelResponse.json")),
+    ("SetNetworkProfileRequest", include_str!("../schemas/SetNetworkProfileRequest.json")),
+    ("SetNetworkProfileResponse", include_str!("../schemas/SetNetworkProfileResponse.json")),
+    ("SetVariableMonitoringRequest", include_str!("../schemas/SetVariableMonitoringRequest.json")),
+    ("SetVariableMonitoringResponse", include_str!("../schemas/SetVariableMonitoringResponse.json")),
+    ("SetVariablesRequest", include_str!("../schemas/SetVariablesRequest.json")),
+    ("SetVariablesResponse", include_str!("../schemas/SetVariablesResponse.json")),
+    ("SignCertificateRequest", include_str!("../schemas/SignCertificateRequest.json")),
+    ("SignCertificateResponse", include_str!("../schemas/SignCertificateResponse.json")),
+    ("StatusNotificationRequest", include_str!("../schemas/StatusNotificationRequest.json")),
+    ("StatusNotificationResponse", include_str!("../schemas/StatusNotificationResponse.json")),
+    ("TransactionEventRequest", include_str!("../schemas/TransactionEventRequest.json")),
+    ("TransactionEventResponse", include_str!("../schemas/TransactionEventResponse.json")),
+    ("TriggerMessageRequest", include_str!("../schemas/TriggerMessageRequest.json")),
+    ("TriggerMessageResponse", include_str!("../schemas/TriggerMessageResponse.json")),
+    ("UnlockConnectorRequest", include_str!("../schemas/UnlockConnectorRequest.json")),
+    ("UnlockConnectorResponse", include_str!("../schemas/UnlockConnectorResponse.json")),
+    ("UnpublishFirmwareRequest", include_str!("../schemas/UnpublishFirmwareRequest.json")),
+    ("UnpublishFirmwareResponse", include_str!("../schemas/UnpublishFirmwareResponse.json")),
+    ("UpdateFirmwareRequest", include_str!("../schemas/UpdateFirmwareRequest.json")),
+    ("UpdateFirmwareResponse", include_str!("../schemas/UpdateFirmwareResponse.json")),
+];
+
+/// Validate the payload of a request or a response against the OCPP JSON schema of the action.
+///
+/// Official schemas are read from `OCPP_SCHEMAS_DIR` which contains files named `<Action>Request.json` and
+/// `<Action>Response.json`, the embedded OCPP 2.0.1 schemas are used for the messages which aren't there if the
+/// connection uses OCPP 2.0.1.
+/// Validation is skipped if there's no schema of the message.
+/// Returns the OCPP error code and the description of the first violation.
+pub fn validate(action: &str, kind: &str, payload: &JsonValue) -> Result<(), (&'static str, String)> {
+    let schema = match schema(&format!("{}{}", action, kind))? {
+        Some(res) => res,
+        None => return Ok(()),
+    };
+
+    check(&schema, &schema, payload, "payload")
+}
+
+/// Validate a message which is sent to CSMS.
+///
+/// Violations are reported according to `SCHEMA_VALIDATION_OUTBOUND`: `warn` (default) logs them and `error` also
+/// keeps the message from being sent. Returns `false` if the message must not be sent.
+pub fn check_sent(action: &str, kind: &str, payload: &JsonValue) -> bool {
+    let (error_code, description) = match validate(action, kind, payload) {
+        Ok(()) => return true,
+        Err(e) => e,
+    };
+
+    match config::var("SCHEMA_VALIDATION_OUTBOUND").as_deref() {
+        Ok("error") => {
+            println!("{}{} is invalid and isn't sent: {} ({})", action, kind, description, error_code);
+
+            false
+        },
+        _ => {
+            println!("Sent {}{} is invalid: {} ({})", action, kind, description, error_code);
+
+            true
+        },
+    }
+}
+
+/// Get the schema with the given name, schemas are read once and kept in the storage.
+///
+/// Schema which isn't a valid JSON is reported as InternalError.
+fn schema(name: &str) -> Result<Option<JsonValue>, (&'static str, String)> {
+    let schema = match storage::get_schema(name) {
+        Some(res) => res,
+        None => {
+            // Missing schema is kept as empty to avoid reading it again.
+            let schema = official_schema(name).unwrap_or_default();
+
+            storage::set_schema(name.to_string(), schema.to_owned());
+
+            schema
+        },
+    };
+    let schema = match embedded_schema(name) {
+        Some(res) if schema.is_empty() => res.to_string(),
+        _ => schema,
+    };
+
+    if schema.is_empty() {
+        return Ok(None);
+    }
+
+    match json::parse(&schema) {
+        Ok(result) => Ok(Some(result)),
+        Err(e) => Err(("InternalError", format!("Schema {} couldn't be parsed ({}).", name, e))),
+    }
+}
+
+/// Get the embedded schema with the given name, messages of OCPP 2.0 have different fields so they aren't validated
+/// against the OCPP 2.0.1 schemas.
+fn embedded_schema(name: &str) -> Option<&'static str> {
+    if storage::get_protocol() != "ocpp2.0.1" {
+        return None;
+    }
+
+    EMBEDDED_SCHEMAS.iter().find(|(embedded_name, _)| *embedded_name == name).map(|(_, schema)| *schema)
+}
+
+/// Read the schema with the given name from `OCPP_SCHEMAS_DIR`.
+fn official_schema(name: &str) -> Option<String> {
+    let dir = match config::var("OCPP_SCHEMAS_DIR") {
+        Ok(var) => if var.is_empty() { return None } else { var },
+        _ => return None,
+    };
+
+    fs::read_to_string(Path::new(&dir).join(format!("{}.json", name))).ok()
+}
+
+/// Check the value against the schema, references are resolved in the root schema.
+///
+/// Supports the keywords of JSON Schema Draft 06 which are used by the OCPP schemas.
+fn check(root: &JsonValue, schema: &JsonValue, value: &JsonValue, path: &str) -> Result<(), (&'static str, String)> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        return check(root, resolve(root, reference), value, path);
+    }
+
+    if let Some(kind) = schema["type"].as_str() {
+        if !has_type(value, kind) {
+            return Err(("TypeConstraintViolation", format!("{} must be of type {}.", path, kind)));
+        }
+    }
+
+    if schema["enum"].is_array() && !schema["enum"].members().any(|item| item == value) {
+        return Err(("PropertyConstraintViolation", format!("{} has a value which is not allowed.", path)));
+    }
+
+    if let Some(string) = value.as_str() {
+        let length = string.chars().count();
+
+        if schema["maxLength"].as_usize().is_some_and(|max_length| length > max_length) {
+            return Err(("PropertyConstraintViolation", format!("{} is longer than {} characters.", path, schema["maxLength"])));
+        }
+
+        if schema["minLength"].as_usize().is_some_and(|min_length| length < min_length) {
+            return Err(("PropertyConstraintViolation", format!("{} is shorter than {} characters.", path, schema["minLength"])));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if schema["minimum"].as_f64().is_some_and(|minimum| number < minimum) {
+            return Err(("PropertyConstraintViolation", format!("{} is less than {}.", path, schema["minimum"])));
+        }
+
+        if schema["maximum"].as_f64().is_some_and(|maximum| number > maximum) {
+            return Err(("PropertyConstraintViolation", format!("{} is greater than {}.", path, schema["maximum"])));
+        }
+    }
+
+    if value.is_object() {
+        for key in schema["required"].members().filter_map(|key| key.as_str()) {
+            if !value.has_key(key) {
+                return Err(("OccurrenceConstraintViolation", format!("{}.{} is required.", path, key)));
+            }
+        }
+
+        for (key, item) in value.entries() {
+            let item_path = format!("{}.{}", path, key);
+
+            if schema["properties"].has_key(key) {
+                check(root, &schema["properties"][key], item, &item_path)?;
+            } else if schema["additionalProperties"] == false {
+                return Err(("FormatViolation", format!("{} is not allowed.", item_path)));
+            }
+        }
+    }
+
+    if value.is_array() {
+        if schema["minItems"].as_usize().is_some_and(|min_items| value.len() < min_items) {
+            return Err(("OccurrenceConstraintViolation", format!("{} has less than {} items.", path, schema["minItems"])));
+        }
+
+        if schema["maxItems"].as_usize().is_some_and(|max_items| value.len() > max_items) {
+            return Err(("OccurrenceConstraintViolation", format!("{} has more than {} items.", path, schema["maxItems"])));
+        }
+
+        if !schema["items"].is_null() {
+            for (index, item) in value.members().enumerate() {
+                check(root, &schema["items"], item, &format!("{}[{}]", path, index))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the schema at the JSON pointer of the reference, e.g. `#/definitions/StatusInfoType`.
+fn resolve<'a>(root: &'a JsonValue, reference: &str) -> &'a JsonValue {
+    reference.trim_start_matches('#').split('/').filter(|segment| !segment.is_empty()).fold(root, |schema, segment| &schema[segment])
+}
+
+fn has_type(value: &JsonValue, kind: &str) -> bool {
+    match kind {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requests;
+
+    fn schema() -> JsonValue {
+        object!{
+            "definitions" => object!{
+                "StatusEnumType" => object!{"type" => "string", "enum" => array!["Accepted", "Rejected"]},
+            },
+            "type" => "object",
+            "properties" => object!{
+                "status" => object!{"$ref" => "#/definitions/StatusEnumType"},
+                "interval" => object!{"type" => "integer", "minimum" => 0},
+                "reasonCode" => object!{"type" => "string", "maxLength" => 5},
+                "evseId" => object!{"type" => "array", "items" => object!{"type" => "integer"}, "minItems" => 1},
+            },
+            "required" => array!["status"],
+            "additionalProperties" => false
+        }
+    }
+
+    fn error_code(payload: JsonValue) -> Option<&'static str> {
+        let schema = schema();
+
+        check(&schema, &schema, &payload, "payload").err().map(|(error_code, _)| error_code)
+    }
+
+    #[test]
+    fn check_accepts_valid_payload() {
+        assert_eq!(error_code(object!{"status" => "Accepted", "interval" => 300, "reasonCode" => "Busy", "evseId" => array![1, 2]}), None);
+    }
+
+    #[test]
+    fn check_reports_violations() {
+        assert_eq!(error_code(object!{"interval" => 300}), Some("OccurrenceConstraintViolation"));
+        assert_eq!(error_code(object!{"status" => "Pending"}), Some("PropertyConstraintViolation"));
+        assert_eq!(error_code(object!{"status" => "Accepted", "interval" => 1.5}), Some("TypeConstraintViolation"));
+        assert_eq!(error_code(object!{"status" => "Accepted", "interval" => -1}), Some("PropertyConstraintViolation"));
+        assert_eq!(error_code(object!{"status" => "Accepted", "reasonCode" => "Unavailable"}), Some("PropertyConstraintViolation"));
+        assert_eq!(error_code(object!{"status" => "Accepted", "evseId" => array![]}), Some("OccurrenceConstraintViolation"));
+        assert_eq!(error_code(object!{"status" => "Accepted", "evseId" => array!["1"]}), Some("TypeConstraintViolation"));
+        assert_eq!(error_code(object!{"status" => "Accepted", "unknown" => true}), Some("FormatViolation"));
+    }
+
+    #[test]
+    fn check_reports_path_of_violation() {
+        let schema = schema();
+
+        let (_, description) = check(&schema, &schema, &object!{"status" => "Accepted", "evseId" => array![1, "2"]}, "payload").unwrap_err();
+
+        assert_eq!(description, "payload.evseId[1] must be of type integer.");
+    }
+
+    fn embedded_error_code(name: &str, payload: &JsonValue) -> Option<&'static str> {
+        let schema = EMBEDDED_SCHEMAS.iter().find(|(embedded_name, _)| *embedded_name == name).map(|(_, schema)| json::parse(schema).unwrap()).unwrap();
+
+        check(&schema, &schema, payload, "payload").err().map(|(error_code, _)| error_code)
+    }
+
+    #[test]
+    fn embedded_schemas_are_valid() {
+        for (name, schema) in EMBEDDED_SCHEMAS.iter() {
+            assert!(json::parse(schema).is_ok_and(|schema| schema.is_object()), "{} isn't a valid schema", name);
+        }
+
+        assert_eq!(embedded_error_code("BootNotificationResponse", &object!{"currentTime" => "2020-01-01T00:00:00Z", "interval" => 300, "status" => "Accepted"}), None);
+        assert_eq!(embedded_error_code("BootNotificationResponse", &object!{"currentTime" => "2020-01-01T00:00:00Z", "status" => "Accepted"}), Some("OccurrenceConstraintViolation"));
+    }
+
+    #[test]
+    fn embedded_schemas_cover_both_kinds_of_actions() {
+        for (name, _) in EMBEDDED_SCHEMAS.iter() {
+            let counterpart = match name.strip_suffix("Request") {
+                Some(action) => format!("{}Response", action),
+                None => name.replace("Response", "Request"),
+            };
+
+            assert!(EMBEDDED_SCHEMAS.iter().any(|(embedded_name, _)| *embedded_name == counterpart), "{} is missing", counterpart);
+        }
+    }
+
+    #[test]
+    fn sent_requests_follow_embedded_schemas() {
+        let requests = [
+            ("BootNotificationRequest", requests::boot_notification("1", "PowerUp", "Model", "Vendor", Some("1".to_string()), None, None)),
+            ("HeartbeatRequest", requests::heartbeat("1")),
+            ("ReservationStatusUpdateRequest", requests::reservation_status_update("1", 1, "Expired")),
+            ("FirmwareStatusNotificationRequest", requests::firmware_status_notification("1", "Downloading", 1)),
+            ("LogStatusNotificationRequest", requests::log_status_notification("1", "Uploading", 1)),
+            ("SecurityEventNotificationRequest", requests::security_event_notification("1", "StartupOfTheDevice", "2020-01-01T00:00:00Z", None)),
+        ];
+
+        for (name, message) in requests.iter() {
+            assert_eq!(embedded_error_code(name, &json::parse(message).unwrap()[3]), None, "{} is invalid", name);
+        }
+    }
+}
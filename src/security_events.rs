@@ -0,0 +1,51 @@
+use crate::clock;
+use crate::components;
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::ids;
+use crate::requests;
+use crate::storage;
+
+// Security event types which the station reports.
+pub const EVENT_TYPES: [&str; 9] = [
+    "FailedToAuthenticateAtCsms",
+    "FirmwareUpdated",
+    "InvalidFirmwareSignature",
+    "InvalidFirmwareSigningCertificate",
+    "MemoryExhaustion",
+    "ReconfigurationOfSecurityParameters",
+    "ResetOrReboot",
+    "SettingSystemTime",
+    "StartupOfTheDevice",
+];
+
+/// Queue a SecurityEventNotification request.
+///
+/// Only event types listed in `SecurityCtrlr.SecurityEvents` are reported, all types are reported if it's empty.
+pub fn notify(event_type: &str, tech_info: Option<&str>) -> Result<(), EmulatorError> {
+    if !is_enabled(event_type) {
+        return Ok(());
+    }
+
+    let timestamp = clock::timestamp()?;
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::security_event_notification(msg_id, event_type, &timestamp, tech_info);
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    storage::queue_add(msg);
+
+    Ok(())
+}
+
+/// Get the initial list of reported security event types, CSMS changes it with SetVariables.
+pub fn enabled_events() -> String {
+    config::var("SECURITY_EVENTS").unwrap_or_default()
+}
+
+fn is_enabled(event_type: &str) -> bool {
+    let enabled_events = components::get_variable("SecurityCtrlr", "SecurityEvents").1.unwrap_or_default();
+
+    enabled_events.trim().is_empty() || enabled_events.split(',').any(|enabled_event| enabled_event.trim() == event_type)
+}
@@ -0,0 +1,177 @@
+use std::fs;
+
+use json::JsonValue;
+
+use crate::clock;
+use crate::config;
+use crate::storage;
+use crate::storage::{Connector, Ev};
+
+// Connector statuses and charging states which can be restored.
+const STATUSES: [&str; 6] = ["Inoperative", "Available", "Occupied", "Reserved", "Unavailable", "Faulted"];
+const CHARGING_STATES: [&str; 5] = ["Idle", "EVConnected", "Charging", "SuspendedEV", "SuspendedEVSE"];
+
+/// Get the state of the emulator: connectors with their meter registers and EVs, transactions, sent and queued
+/// messages and values of device model variables.
+pub fn export() -> JsonValue {
+    let evses: Vec<JsonValue> = (0..storage::evse_count())
+        .map(|evse_index| JsonValue::Array(storage::get_connectors(evse_index).iter().map(connector_state).collect()))
+        .collect();
+
+    let mut transactions = JsonValue::new_object();
+
+    for (transaction_id, transaction) in storage::get_transactions() {
+        transactions[transaction_id] = json::parse(&transaction).unwrap_or(JsonValue::Null);
+    }
+
+    let mut messages = JsonValue::new_object();
+
+    for (msg_id, msg) in storage::get_messages() {
+        messages[msg_id] = json::parse(&msg).unwrap_or(JsonValue::Null);
+    }
+
+    let queue: Vec<JsonValue> = storage::queue_messages().iter().map(|msg| json::parse(msg).unwrap_or(JsonValue::Null)).collect();
+
+    let mut variables = JsonValue::new_object();
+
+    for (key, value) in storage::get_variable_values() {
+        variables[key] = value.into();
+    }
+
+    object!{
+        "createdAt" => clock::now().to_rfc3339(),
+        "evses" => evses,
+        "transactions" => transactions,
+        "messages" => messages,
+        "queue" => queue,
+        "variables" => variables,
+    }
+}
+
+/// Write an exported state of the emulator to `SNAPSHOT_FILE`.
+///
+/// Returns `false` if the file isn't configured or can't be written.
+pub fn save(snapshot: &JsonValue) -> bool {
+    let path = match snapshot_file() {
+        Some(res) => res,
+        None => return false,
+    };
+
+    match fs::write(&path, snapshot.pretty(2)) {
+        Ok(_) => {
+            println!("Snapshot was saved to {}.", path);
+
+            true
+        },
+        Err(e) => {
+            println!("Couldn't save the snapshot ({})", e);
+
+            false
+        },
+    }
+}
+
+/// Restore the state of the emulator from `SNAPSHOT_FILE` which was created with `save`.
+///
+/// Connectors are matched by their position in the station topology, those which don't exist anymore are skipped.
+/// Nothing is restored if the file doesn't exist.
+pub fn load() {
+    let path = match snapshot_file() {
+        Some(res) => res,
+        None => return,
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(res) => res,
+        Err(_) => return,
+    };
+
+    let snapshot = match json::parse(&content) {
+        Ok(result) => result,
+        Err(e) => panic!("Error during parsing: {:?}", e),
+    };
+
+    for (evse_index, connectors) in snapshot["evses"].members().enumerate().take(storage::evse_count()) {
+        for (connector_index, state) in connectors.members().enumerate().take(storage::connector_count(evse_index)) {
+            if let Some(mut connector) = storage::get_connector(evse_index, connector_index) {
+                restore_connector(&mut connector, state);
+
+                storage::set_connector(evse_index, connector_index, connector);
+            }
+        }
+    }
+
+    for (transaction_id, transaction) in snapshot["transactions"].entries() {
+        storage::set_transaction(transaction_id.to_string(), transaction.dump());
+    }
+
+    for (msg_id, msg) in snapshot["messages"].entries() {
+        storage::set_message(msg_id.to_string(), msg.dump());
+    }
+
+    for msg in snapshot["queue"].members() {
+        storage::queue_add(msg.dump());
+    }
+
+    for (key, value) in snapshot["variables"].entries() {
+        storage::set_variable_value(key.to_string(), value.to_string());
+    }
+
+    println!("State was restored from the snapshot {} created at {}.", path, snapshot["createdAt"]);
+}
+
+fn connector_state(connector: &Connector) -> JsonValue {
+    let ev = match &connector.ev {
+        Some(ev) => object!{
+            "capacity" => ev.capacity,
+            "energy" => ev.energy,
+            "maxPower" => ev.max_power,
+            "paused" => ev.paused,
+        },
+        None => JsonValue::Null,
+    };
+
+    object!{
+        "status" => connector.status,
+        "operational" => connector.operational,
+        "chargingState" => connector.charging_state,
+        "power" => connector.power,
+        "energy" => connector.energy,
+        "energyExport" => connector.energy_export,
+        "ev" => ev,
+    }
+}
+
+/// Apply the saved state to a connector, its type and ratings are kept from the station topology.
+fn restore_connector(connector: &mut Connector, state: &JsonValue) {
+    if let Some(status) = STATUSES.iter().find(|status| state["status"] == **status) {
+        connector.status = status;
+    }
+
+    if let Some(charging_state) = CHARGING_STATES.iter().find(|charging_state| state["chargingState"] == **charging_state) {
+        connector.charging_state = charging_state;
+    }
+
+    connector.operational = state["operational"].as_bool().unwrap_or(connector.operational);
+    connector.power = state["power"].as_f64().unwrap_or(0.0);
+    connector.energy = state["energy"].as_f64().unwrap_or(connector.energy);
+    connector.energy_export = state["energyExport"].as_f64().unwrap_or(connector.energy_export);
+
+    connector.ev = if state["ev"].is_object() {
+        Some(Ev {
+            capacity: state["ev"]["capacity"].as_f64().unwrap_or(0.0),
+            energy: state["ev"]["energy"].as_f64().unwrap_or(0.0),
+            max_power: state["ev"]["maxPower"].as_f64().unwrap_or(0.0),
+            paused: state["ev"]["paused"] == true,
+        })
+    } else {
+        None
+    };
+}
+
+fn snapshot_file() -> Option<String> {
+    match config::var("SNAPSHOT_FILE") {
+        Ok(var) => if var.is_empty() { None } else { Some(var) },
+        _ => None,
+    }
+}
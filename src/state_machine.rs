@@ -0,0 +1,152 @@
+use crate::client;
+use crate::ev;
+use crate::storage;
+
+/// Events which change the state of a connector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    // Station is registered by CSMS.
+    Boot,
+    // EVSE is reserved or its reservation has ended.
+    Reserve,
+    ReservationEnded,
+    // Cable of an EV is plugged in or out.
+    PlugIn,
+    PlugOut,
+    // Energy transfer is started, suspended by the EV or the EVSE or stopped together with the transaction.
+    StartCharging,
+    SuspendEV,
+    SuspendEVSE,
+    StopCharging,
+    // Connector is broken or repaired.
+    Fault,
+    ClearFault,
+    // Connector is made inoperative or operative by ChangeAvailability.
+    Disable,
+    Enable,
+}
+
+/// Get the next state of a connector: connector status and charging state.
+///
+/// Inoperative connector becomes unavailable as soon as it isn't occupied or faulted.
+/// Returns `None` if the event is not allowed in the current state.
+fn next(status: &'static str, charging_state: &'static str, operational: bool, event: Event) -> Option<(&'static str, &'static str)> {
+    let ev_connected = charging_state != "Idle";
+
+    match (event, status) {
+        (Event::Boot, "Faulted") | (Event::Boot, "Reserved") => Some((status, charging_state)),
+        (Event::Boot, _) if !operational => Some(("Unavailable", charging_state)),
+        (Event::Boot, _) => Some((if ev_connected { "Occupied" } else { "Available" }, charging_state)),
+        (Event::Disable, "Available") | (Event::Disable, "Reserved") | (Event::Disable, "Unavailable") => Some(("Unavailable", charging_state)),
+        (Event::Enable, "Unavailable") => Some((if ev_connected { "Occupied" } else { "Available" }, charging_state)),
+        (Event::Reserve, "Available") => Some(("Reserved", charging_state)),
+        (Event::ReservationEnded, "Reserved") => Some(("Available", charging_state)),
+        (Event::PlugIn, "Available") | (Event::PlugIn, "Reserved") => Some(("Occupied", "EVConnected")),
+        (Event::PlugOut, "Occupied") if !operational => Some(("Unavailable", "Idle")),
+        (Event::PlugOut, "Occupied") => Some(("Available", "Idle")),
+        (Event::PlugOut, "Faulted") if ev_connected => Some(("Faulted", "Idle")),
+        (Event::StartCharging, "Occupied") if ev_connected => Some(("Occupied", "Charging")),
+        (Event::SuspendEV, "Occupied") if ev_connected => Some(("Occupied", "SuspendedEV")),
+        (Event::SuspendEVSE, "Occupied") if ev_connected => Some(("Occupied", "SuspendedEVSE")),
+        (Event::StopCharging, "Occupied") | (Event::StopCharging, "Faulted") if ev_connected => Some((status, "EVConnected")),
+        (Event::Fault, _) => Some(("Faulted", if charging_state == "Charging" { "SuspendedEVSE" } else { charging_state })),
+        (Event::ClearFault, "Faulted") if !operational && !ev_connected => Some(("Unavailable", charging_state)),
+        (Event::ClearFault, "Faulted") => Some((if ev_connected { "Occupied" } else { "Available" }, charging_state)),
+        _ => None,
+    }
+}
+
+/// Handle an event of the connector.
+///
+/// StatusNotification is sent if the connector status changes or the station boots.
+/// Operational status is changed by `Disable` and `Enable` even if the connector status can't change yet.
+/// Returns `false` if the event is not allowed in the current state of the connector.
+pub fn handle(evse_index: usize, connector_index: usize, event: Event) -> bool {
+    if event == Event::Disable || event == Event::Enable {
+        storage::set_connector_operational_status(evse_index, connector_index, event == Event::Enable);
+    }
+
+    let connector = match storage::get_connector(evse_index, connector_index) {
+        Some(res) => res,
+        None => return false,
+    };
+
+    let (status, charging_state) = match next(connector.status, connector.charging_state, connector.operational, event) {
+        Some(res) => res,
+        None => return false,
+    };
+
+    storage::set_connector_charging_state(evse_index, connector_index, charging_state);
+
+    // Connector doesn't deliver energy unless it's charging.
+    if charging_state != "Charging" {
+        storage::set_connector_power(evse_index, connector_index, 0.0);
+    }
+
+    match event {
+        Event::PlugIn => ev::plug_in(evse_index, connector_index),
+        Event::PlugOut => storage::set_connector_ev(evse_index, connector_index, None),
+        _ => (),
+    };
+
+    if status != connector.status || event == Event::Boot {
+        if let Err(e) = client::change_connector_status(evse_index, connector_index, status) {
+            println!("Status of connector {} of EVSE {} couldn't be reported ({})", connector_index + 1, evse_index + 1, e);
+        }
+    }
+
+    true
+}
+
+/// Handle an event of all connectors of the EVSE for which the event is allowed.
+pub fn handle_evse(evse_index: usize, event: Event) {
+    for connector_index in 0..storage::connector_count(evse_index) {
+        handle(evse_index, connector_index, event);
+    }
+}
+
+/// Set the power (W) which the connector offers to the connected EV, negative power discharges the EV.
+///
+/// The EV takes the power it's able to accept or to discharge. Charging is suspended by the EVSE while
+/// the power is limited to zero and by the EV when its battery is full or empty.
+/// Returns `true` if the charging state has changed.
+pub fn set_power(evse_index: usize, connector_index: usize, power: f64) -> bool {
+    let (power, ev_limit) = if power < 0.0 {
+        match ev::supply(evse_index, connector_index) {
+            Some(supply) => (power.max(-supply), Some(supply)),
+            None => (power, None),
+        }
+    } else {
+        match ev::demand(evse_index, connector_index) {
+            Some(demand) => (power.min(demand), Some(demand)),
+            None => (power, None),
+        }
+    };
+
+    let event = if power != 0.0 {
+        Event::StartCharging
+    } else if ev_limit == Some(0.0) {
+        Event::SuspendEV
+    } else {
+        Event::SuspendEVSE
+    };
+
+    let previous = charging_state(evse_index, connector_index);
+
+    if !handle(evse_index, connector_index, event) {
+        return false;
+    }
+
+    let current = charging_state(evse_index, connector_index);
+
+    if current == "Charging" {
+        storage::set_connector_power(evse_index, connector_index, power);
+    }
+
+    current != previous
+}
+
+/// Get the charging state of the connector which is reported in TransactionEvent requests.
+pub fn charging_state(evse_index: usize, connector_index: usize) -> &'static str {
+    storage::get_connector(evse_index, connector_index).map_or("Idle", |connector| connector.charging_state)
+}
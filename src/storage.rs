@@ -1,13 +1,197 @@
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::Duration;
 
-use queues::*;
+use crate::clock;
+use crate::config;
+use crate::security_events;
+
+// Maximum number of messages kept in the transcript.
+const TRANSCRIPT_SIZE: usize = 10000;
+// Maximum number of responses kept to answer duplicate requests.
+const RECENT_RESPONSES_SIZE: usize = 100;
+// Maximum number of queued messages which are not related to transactions while the station is offline.
+const OFFLINE_QUEUE_SIZE: usize = 100;
+// Default maximum number of queued messages and time (s) a message waits for space with the `block` overflow policy.
+const DEFAULT_QUEUE_CAPACITY: usize = 1000;
+const DEFAULT_QUEUE_BLOCK_TIMEOUT: u64 = 10;
+// Actions of queued messages which are sent before others and those which are sent after others.
+const URGENT_ACTIONS: [&str; 2] = ["TransactionEvent", "StatusNotification"];
+const DEFERRED_ACTIONS: [&str; 2] = ["Heartbeat", "MeterValues"];
 
 // Connector struct.
 #[derive(Clone, Debug)]
 pub struct Connector {
     pub status: &'static str,
     pub operational: bool,
+    pub power: f64,
+    // Charging state which is reported in TransactionEvent requests.
+    pub charging_state: &'static str,
+    // Energy meter registers (Wh) of imported energy and energy exported by discharging the EV.
+    pub energy: f64,
+    pub energy_export: f64,
+    // Connector type as reported to CSMS, e.g. "cType2" or "cCCS2".
+    pub connector_type: String,
+    // Maximum current (A) and voltage (V) of the connector.
+    pub max_current: f64,
+    pub max_voltage: f64,
+    // Number of AC phases, 0 for DC connectors.
+    pub phases: u8,
+    // Maximum power (W) which the connector is able to deliver.
+    pub max_power: f64,
+    // Simulated EV which is connected to the connector.
+    pub ev: Option<Ev>,
+}
+
+// Simulated EV struct.
+#[derive(Clone, Debug)]
+pub struct Ev {
+    // Battery capacity and energy stored in the battery (Wh).
+    pub capacity: f64,
+    pub energy: f64,
+    // Maximum charging power (W).
+    pub max_power: f64,
+    // EV has paused charging on its own.
+    pub paused: bool,
+}
+
+/// Backend which keeps sent messages, the message queue, transactions, connectors and the device model.
+///
+/// Values are stringified JSON as elsewhere in the storage. `MemoryStorage` is used by default, another backend is
+/// plugged in with `set_backend` before the station runs.
+pub trait Storage: Debug + Send {
+    fn set_message(&mut self, key: String, value: String);
+    fn get_message(&self, key: &str) -> Option<String>;
+    fn get_messages(&self) -> Vec<(String, String)>;
+    fn delete_message(&mut self, key: &str);
+
+    fn add_queued_message(&mut self, value: String);
+    /// Remove the queued message at the given position, 0 is the oldest one.
+    fn remove_queued_message(&mut self, index: usize) -> Option<String>;
+    fn get_queued_messages(&self) -> Vec<String>;
+
+    fn set_transaction(&mut self, key: String, value: String);
+    fn get_transaction(&self, key: &str) -> Option<String>;
+    fn get_transactions(&self) -> Vec<(String, String)>;
+    fn delete_transaction(&mut self, key: &str);
+
+    fn set_evses(&mut self, value: Vec<Vec<Connector>>);
+    fn evse_count(&self) -> usize;
+    fn connector_count(&self, evse_index: usize) -> usize;
+    fn set_connector(&mut self, evse_index: usize, connector_index: usize, value: Connector);
+    fn get_connector(&self, evse_index: usize, connector_index: usize) -> Option<Connector>;
+
+    fn set_device_model(&mut self, value: Vec<String>);
+    fn get_device_model(&self) -> Vec<String>;
+    fn set_variable_value(&mut self, key: String, value: String);
+    fn get_variable_value(&self, key: &str) -> Option<String>;
+    fn get_variable_values(&self) -> Vec<(String, String)>;
+}
+
+/// Storage which keeps the state in memory for the lifetime of the process.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    // EVSE each item of which contains connectors of the EVSE. Initialized from the station topology.
+    evses: Vec<Vec<Connector>>,
+    // Sent OCPP messages hash map: message id => stringified message.
+    messages: HashMap<String, String>,
+    // Saved transactions ordered by id, so they're iterated reproducibly. transaction id => stringified transaction.
+    transactions: BTreeMap<String, String>,
+    // Pending messages queue.
+    queue: Vec<String>,
+    // Variable definitions loaded from the device model file as stringified report data.
+    device_model: Vec<String>,
+    // Values of device model variables set by CSMS. "component.variable" => value.
+    variable_values: HashMap<String, String>,
+}
+
+impl Storage for MemoryStorage {
+    fn set_message(&mut self, key: String, value: String) {
+        self.messages.insert(key, value);
+    }
+
+    fn get_message(&self, key: &str) -> Option<String> {
+        self.messages.get(key).cloned()
+    }
+
+    fn get_messages(&self) -> Vec<(String, String)> {
+        self.messages.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn delete_message(&mut self, key: &str) {
+        self.messages.remove(key);
+    }
+
+    fn add_queued_message(&mut self, value: String) {
+        self.queue.push(value);
+    }
+
+    fn remove_queued_message(&mut self, index: usize) -> Option<String> {
+        if index < self.queue.len() { Some(self.queue.remove(index)) } else { None }
+    }
+
+    fn get_queued_messages(&self) -> Vec<String> {
+        self.queue.clone()
+    }
+
+    fn set_transaction(&mut self, key: String, value: String) {
+        self.transactions.insert(key, value);
+    }
+
+    fn get_transaction(&self, key: &str) -> Option<String> {
+        self.transactions.get(key).cloned()
+    }
+
+    fn get_transactions(&self) -> Vec<(String, String)> {
+        self.transactions.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn delete_transaction(&mut self, key: &str) {
+        self.transactions.remove(key);
+    }
+
+    fn set_evses(&mut self, value: Vec<Vec<Connector>>) {
+        self.evses = value;
+    }
+
+    fn evse_count(&self) -> usize {
+        self.evses.len()
+    }
+
+    fn connector_count(&self, evse_index: usize) -> usize {
+        self.evses.get(evse_index).map_or(0, |connectors| connectors.len())
+    }
+
+    fn set_connector(&mut self, evse_index: usize, connector_index: usize, value: Connector) {
+        self.evses[evse_index][connector_index] = value;
+    }
+
+    fn get_connector(&self, evse_index: usize, connector_index: usize) -> Option<Connector> {
+        self.evses.get(evse_index)?.get(connector_index).cloned()
+    }
+
+    fn set_device_model(&mut self, value: Vec<String>) {
+        self.device_model = value;
+    }
+
+    fn get_device_model(&self) -> Vec<String> {
+        self.device_model.clone()
+    }
+
+    fn set_variable_value(&mut self, key: String, value: String) {
+        self.variable_values.insert(key, value);
+    }
+
+    fn get_variable_value(&self, key: &str) -> Option<String> {
+        self.variable_values.get(key).cloned()
+    }
+
+    fn get_variable_values(&self) -> Vec<(String, String)> {
+        self.variable_values.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
 }
 
 // Basic information about sent message.
@@ -17,80 +201,1204 @@ pub struct SentMessage {
     pub timestamp: Option<u64>,
 }
 
-lazy_static! {
-    // Array of EVSE each item of which contains an array of connectors.
-    static ref EVSES: Mutex<[[Connector; 1]; 1]> = Mutex::new([[Connector { status: "Inoperative", operational: true }]]);
-    // Sent OCPP messages hash map: message id => stringified message.
-    static ref MESSAGES: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
-    // Saved transactions. transaction id => stringified transaction.
-    static ref TRANSACTIONS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
-    // Pending messages queue.
-    static ref QUEUE: Mutex<Queue<String>> = Mutex::new(queue![]);
+/// State of a charging station: its configuration, the storage backend and everything it keeps at runtime.
+///
+/// Every station has its own state, so several stations may run on threads of one process. Functions of the storage
+/// work with the state of the station which runs on the current thread, see `enter`.
+pub struct State {
+    // Variables which the station is configured with instead of the environment of the process.
+    config: HashMap<String, String>,
+    // Backend of messages, the queue, transactions, connectors and the device model.
+    backend: Mutex<Box<dyn Storage>>,
+    // Signaled when a message leaves the queue.
+    queue_space: Condvar,
     // Last sent message.
-    static ref LAST_SENT_MESSAGE: Mutex<SentMessage> = Mutex::new(SentMessage { id: None, timestamp: None });
+    last_sent_message: Mutex<SentMessage>,
+    // Local authorization list. id token => stringified id token info.
+    local_list: Mutex<HashMap<String, String>>,
+    // Version of the local authorization list.
+    local_list_version: Mutex<u64>,
+    // Reservations. reservation id => stringified reservation.
+    reservations: Mutex<BTreeMap<u64, String>>,
+    // Charging profiles. charging profile id => stringified charging profile.
+    charging_profiles: Mutex<BTreeMap<u64, String>>,
+    // Display messages. message id => stringified message info.
+    display_messages: Mutex<HashMap<u64, String>>,
+    // Request id of the ongoing firmware update.
+    firmware_request: Mutex<Option<u64>>,
+    // Firmware published by the local controller: stringified request id, checksum and state.
+    published_firmware: Mutex<Option<String>>,
+    // Request id of the ongoing log upload.
+    log_request: Mutex<Option<u64>>,
+    // Transcript of sent and received messages: (timestamp, log line).
+    transcript: Mutex<Vec<(i64, String)>>,
+    // Variable monitors. monitor id => stringified monitor.
+    monitors: Mutex<BTreeMap<u64, String>>,
+    // Maximum severity of monitors which are reported.
+    monitoring_level: Mutex<u8>,
+    // Id of the last generated event.
+    event_id: Mutex<u64>,
+    // Events which are not cleared yet. component and variable => event id.
+    active_events: Mutex<HashMap<String, u64>>,
+    // Installed certificates. issuer name hash and serial number => stringified certificate.
+    certificates: Mutex<HashMap<String, String>>,
+    // Private keys of pending certificate signing requests. certificate type => PEM encoded key.
+    pending_private_keys: Mutex<HashMap<String, String>>,
+    // Certificates of the charging station. certificate type => stringified certificate chain and key.
+    station_certificates: Mutex<HashMap<String, String>>,
+    // Contract certificates of simulated EVs. eMAID => EXI encoded certificate installation response.
+    contract_certificates: Mutex<HashMap<String, String>>,
+    // Sessions waiting for an Authorize response. id token => EVSE index.
+    pending_authorizations: Mutex<HashMap<String, usize>>,
+    // Remote starts waiting for authorization of their id token. id token => stringified transaction details.
+    pending_remote_starts: Mutex<HashMap<String, String>>,
+    // Authorization cache. id token => stringified id token info.
+    auth_cache: Mutex<HashMap<String, String>>,
+    // Moments of the last meter samples. sample key => timestamp (ms).
+    meter_timestamps: Mutex<HashMap<String, i64>>,
+    // Limits imposed by external systems. "evse id:source" => stringified limit.
+    external_limits: Mutex<HashMap<String, String>>,
+    // Limits which bound the power of EVSE. EVSE index => "source:limit".
+    enforced_limits: Mutex<HashMap<usize, String>>,
+    // Responses to recent requests of CSMS: (message id, response).
+    recent_responses: Mutex<Vec<(String, String)>>,
+    // Actions of requests of CSMS which are not answered yet. message id => action.
+    received_calls: Mutex<HashMap<String, String>>,
+    // Loaded JSON schemas of OCPP messages, empty if there's no schema. schema name => stringified schema.
+    schemas: Mutex<HashMap<String, String>>,
+    // Number of failed attempts to send a request. message id => attempts.
+    message_attempts: Mutex<HashMap<String, u32>>,
+    // Moments (ms) when sent messages were stored or answered. message id => timestamp.
+    message_times: Mutex<HashMap<String, i64>>,
+    // Requests which are sent again later: (moment (ms), message).
+    delayed_messages: Mutex<Vec<(i64, String)>>,
+    // Callback which makes the client send queued messages, set while the station is connected.
+    queue_waker: Mutex<Option<Box<dyn Fn() + Send>>>,
+    // Thread which runs the WebSocket client and sends queued messages.
+    client_thread: Mutex<Option<ThreadId>>,
+    // Callback which closes the open connection to CSMS.
+    connection_closer: Mutex<Option<Box<dyn Fn() + Send>>>,
+    // Whether the station has to stop instead of reconnecting.
+    stop_requested: Mutex<bool>,
+    // Whether the message queue has overflowed since messages last fitted into it.
+    queue_exhausted: Mutex<bool>,
+    // Moment when the last message was sent to CSMS (ms).
+    last_send_time: Mutex<Option<i64>>,
+    // Offset of the clock synchronized with CSMS (ms).
+    clock_offset: Mutex<i64>,
+    // Whether the clock was synchronized with CSMS.
+    clock_synchronized: Mutex<bool>,
+    // Real moment (ms) from which the simulated time runs faster.
+    simulation_start: Mutex<Option<i64>>,
+    // Time (ms) by which the simulated clock was advanced manually.
+    clock_advance: Mutex<i64>,
+    // Sequences of generated ids and random numbers of deterministic mode.
+    id_sequence: Mutex<u64>,
+    random_sequence: Mutex<u64>,
+    // Registration status from the last BootNotification response, empty before the first response.
+    registration_status: Mutex<&'static str>,
+    // Number of consecutive rejected BootNotification requests.
+    boot_rejections: Mutex<u32>,
+    // Reason of the last BootNotification request.
+    boot_reason: Mutex<String>,
+    // Whether the connection has to be reestablished after it is closed.
+    reconnect: Mutex<bool>,
+    // Whether the WebSocket connection to CSMS is open.
+    online: Mutex<bool>,
+    // Moment (s) when the connection to CSMS was lost.
+    offline_since: Mutex<Option<i64>>,
+    // Whether the connection was established since the last check.
+    connection_opened: Mutex<bool>,
+    // Host and port of the CSMS endpoint which is connected through a proxy tunnel.
+    tunneled_endpoint: Mutex<Option<(String, u16)>>,
+    // OCPP protocol which CSMS has selected for the connection.
+    protocol: Mutex<&'static str>,
+    // Index of the active network profile in the list of CSMS endpoints.
+    network_slot: Mutex<usize>,
+    // Index of the network profile with higher priority whose CSMS was found reachable.
+    reachable_network_slot: Mutex<Option<usize>>,
+    // Basic authentication password set by CSMS and the password used before it.
+    basic_auth_password: Mutex<Option<String>>,
+    previous_basic_auth_password: Mutex<Option<String>>,
+    // Internal temperatures of EVSE (°C). EVSE index => temperature.
+    temperatures: Mutex<HashMap<usize, f64>>,
+    // Indexes of EVSE which derate their power because of overheating.
+    derated_evses: Mutex<Vec<usize>>,
+    // Sessions of randomly arriving drivers. EVSE index => stringified session.
+    driver_sessions: Mutex<HashMap<usize, String>>,
+}
+
+impl State {
+    /// Create the state of a station with the given configuration variables, it's kept for the lifetime of the
+    /// process since threads of the station may outlive the station.
+    pub fn create(config: HashMap<String, String>) -> &'static State {
+        Box::leak(Box::new(State {
+            config,
+            backend: Mutex::new(Box::new(MemoryStorage::default())),
+            queue_space: Condvar::new(),
+            last_sent_message: Mutex::new(SentMessage { id: None, timestamp: None }),
+            local_list: Mutex::new(HashMap::new()),
+            local_list_version: Mutex::new(0),
+            reservations: Mutex::new(BTreeMap::new()),
+            charging_profiles: Mutex::new(BTreeMap::new()),
+            display_messages: Mutex::new(HashMap::new()),
+            firmware_request: Mutex::new(None),
+            published_firmware: Mutex::new(None),
+            log_request: Mutex::new(None),
+            transcript: Mutex::new(Vec::new()),
+            monitors: Mutex::new(BTreeMap::new()),
+            monitoring_level: Mutex::new(9),
+            event_id: Mutex::new(0),
+            active_events: Mutex::new(HashMap::new()),
+            certificates: Mutex::new(HashMap::new()),
+            pending_private_keys: Mutex::new(HashMap::new()),
+            station_certificates: Mutex::new(HashMap::new()),
+            contract_certificates: Mutex::new(HashMap::new()),
+            pending_authorizations: Mutex::new(HashMap::new()),
+            pending_remote_starts: Mutex::new(HashMap::new()),
+            auth_cache: Mutex::new(HashMap::new()),
+            meter_timestamps: Mutex::new(HashMap::new()),
+            external_limits: Mutex::new(HashMap::new()),
+            enforced_limits: Mutex::new(HashMap::new()),
+            recent_responses: Mutex::new(Vec::new()),
+            received_calls: Mutex::new(HashMap::new()),
+            schemas: Mutex::new(HashMap::new()),
+            message_attempts: Mutex::new(HashMap::new()),
+            message_times: Mutex::new(HashMap::new()),
+            delayed_messages: Mutex::new(Vec::new()),
+            queue_waker: Mutex::new(None),
+            client_thread: Mutex::new(None),
+            connection_closer: Mutex::new(None),
+            stop_requested: Mutex::new(false),
+            queue_exhausted: Mutex::new(false),
+            last_send_time: Mutex::new(None),
+            clock_offset: Mutex::new(0),
+            clock_synchronized: Mutex::new(false),
+            simulation_start: Mutex::new(None),
+            clock_advance: Mutex::new(0),
+            id_sequence: Mutex::new(0),
+            random_sequence: Mutex::new(0),
+            registration_status: Mutex::new(""),
+            boot_rejections: Mutex::new(0),
+            boot_reason: Mutex::new(String::from("PowerUp")),
+            reconnect: Mutex::new(false),
+            online: Mutex::new(false),
+            offline_since: Mutex::new(None),
+            connection_opened: Mutex::new(false),
+            tunneled_endpoint: Mutex::new(None),
+            protocol: Mutex::new("ocpp2.0"),
+            network_slot: Mutex::new(0),
+            reachable_network_slot: Mutex::new(None),
+            basic_auth_password: Mutex::new(None),
+            previous_basic_auth_password: Mutex::new(None),
+            temperatures: Mutex::new(HashMap::new()),
+            derated_evses: Mutex::new(Vec::new()),
+            driver_sessions: Mutex::new(HashMap::new()),
+        }))
+    }
+}
+
+impl Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("State").field("config", &self.config).finish_non_exhaustive()
+    }
+}
+
+thread_local! {
+    // State of the station which runs on the current thread.
+    static CURRENT_STATE: Cell<Option<&'static State>> = const { Cell::new(None) };
+}
+
+/// Make the given state the state of the station which runs on the current thread.
+pub fn enter(state: &'static State) {
+    CURRENT_STATE.with(|current| current.set(Some(state)));
+}
+
+/// Get the state of the station which runs on the current thread.
+///
+/// Thread which doesn't run a station gets a state of its own, e.g. every test works with a separate state.
+pub fn current() -> &'static State {
+    CURRENT_STATE.with(|current| match current.get() {
+        Some(res) => res,
+        None => {
+            let state = State::create(HashMap::new());
+
+            current.set(Some(state));
+
+            state
+        },
+    })
+}
+
+/// Run a function with the given state and restore the state of the current thread afterwards.
+pub fn with_state<R>(state: &'static State, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT_STATE.with(|current| current.replace(Some(state)));
+    let result = f();
+
+    CURRENT_STATE.with(|current| current.set(previous));
+
+    result
+}
+
+/// Spawn a thread of the station which runs on the current thread, the thread works with the same state.
+pub fn spawn<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> JoinHandle<T> {
+    let state = current();
+
+    thread::spawn(move || {
+        enter(state);
+
+        f()
+    })
+}
+
+/// Get a configuration variable of the station, `None` if the station isn't configured with it.
+pub fn get_config_variable(key: &str) -> Option<String> {
+    current().config.get(key).cloned()
+}
+
+/// Replace the storage backend, e.g. with a persistent one.
+pub fn set_backend(backend: Box<dyn Storage>) {
+    *current().backend.lock().unwrap() = backend;
 }
 
 pub fn set_message(key: String, value: String) {
-    MESSAGES.lock().unwrap().insert(key, value);
+    current().message_times.lock().unwrap().insert(key.to_owned(), clock::local_now().timestamp_millis());
+
+    current().backend.lock().unwrap().set_message(key, value);
 }
 
 pub fn get_message(key: &str) -> String {
-    match MESSAGES.lock().unwrap().get(key) {
-        Some(value) => value.to_string(),
-        None => String::from(""),
+    current().backend.lock().unwrap().get_message(key).unwrap_or_default()
+}
+
+pub fn get_messages() -> Vec<(String, String)> {
+    current().backend.lock().unwrap().get_messages()
+}
+
+/// Release a sent message whose response has been handled.
+///
+/// Message is removed right away unless it's kept for `MESSAGE_RETENTION` seconds or it's sent again.
+pub fn release_message(key: &str) {
+    if message_retention() == 0 && !pending_messages().iter().any(|id| id == key) {
+        delete_message(key);
+    } else {
+        current().message_times.lock().unwrap().insert(key.to_string(), clock::local_now().timestamp_millis());
     }
 }
 
+/// Remove messages which are answered or orphaned, e.g. dropped after failed attempts, for longer than
+/// `MESSAGE_RETENTION` seconds.
+pub fn sweep_messages() {
+    let expired = clock::local_now().timestamp_millis() - message_retention() * 1000;
+
+    let keys: Vec<String> = current().message_times.lock().unwrap().iter()
+        .filter(|(_, moment)| **moment <= expired)
+        .map(|(key, _)| key.to_string())
+        .collect();
+
+    if keys.is_empty() {
+        return;
+    }
+
+    let pending = pending_messages();
+
+    for key in keys.iter().filter(|key| !pending.contains(key)) {
+        delete_message(key);
+    }
+}
+
+fn delete_message(key: &str) {
+    current().message_times.lock().unwrap().remove(key);
+    current().message_attempts.lock().unwrap().remove(key);
+
+    current().backend.lock().unwrap().delete_message(key);
+}
+
+/// Get ids of messages which are queued, wait for their retry or for their response.
+fn pending_messages() -> Vec<String> {
+    let mut messages = queue_messages();
+
+    messages.extend(current().delayed_messages.lock().unwrap().iter().map(|(_, msg)| msg.to_string()));
+
+    let mut ids: Vec<String> = messages.iter().filter_map(|msg| json::parse(msg).ok().map(|parsed_msg| parsed_msg[1].to_string())).collect();
+
+    ids.extend(current().last_sent_message.lock().unwrap().id.clone());
+
+    ids
+}
+
+fn message_retention() -> i64 {
+    config::var("MESSAGE_RETENTION").ok().and_then(|var| var.parse::<i64>().ok()).unwrap_or(0).max(0)
+}
+
 pub fn set_transaction(key: String, value: String) {
-    TRANSACTIONS.lock().unwrap().insert(key, value);
+    current().backend.lock().unwrap().set_transaction(key, value);
 }
 
 pub fn get_transaction(key: &str) -> String {
-    match TRANSACTIONS.lock().unwrap().get(key) {
-        Some(value) => value.to_string(),
-        None => String::from(""),
-    }
+    current().backend.lock().unwrap().get_transaction(key).unwrap_or_default()
+}
+
+pub fn get_transactions() -> Vec<(String, String)> {
+    current().backend.lock().unwrap().get_transactions()
 }
 
 pub fn delete_transaction(key: &str) {
-    TRANSACTIONS.lock().unwrap().remove(key);
+    current().backend.lock().unwrap().delete_transaction(key);
 }
 
 pub fn set_connector_status(evse_index: usize, connector_index: usize, value: &'static str) {
-    EVSES.lock().unwrap()[evse_index][connector_index].status = value;
+    update_connector(evse_index, connector_index, |connector| connector.status = value);
+}
+pub fn set_connector_charging_state(evse_index: usize, connector_index: usize, value: &'static str) {
+    update_connector(evse_index, connector_index, |connector| connector.charging_state = value);
+}
+pub fn set_connector_ev(evse_index: usize, connector_index: usize, value: Option<Ev>) {
+    update_connector(evse_index, connector_index, |connector| connector.ev = value);
+}
+pub fn set_connector_power(evse_index: usize, connector_index: usize, value: f64) {
+    update_connector(evse_index, connector_index, |connector| connector.power = value);
+}
+
+pub fn add_connector_energy(evse_index: usize, connector_index: usize, value: f64) {
+    update_connector(evse_index, connector_index, |connector| connector.energy += value);
+}
+
+pub fn add_connector_export_energy(evse_index: usize, connector_index: usize, value: f64) {
+    update_connector(evse_index, connector_index, |connector| connector.energy_export += value);
 }
-// NOTE Unused.
-// pub fn set_connector_operational_status(evse_index: usize, connector_index: usize, value: bool) {
-//     EVSES.lock().unwrap()[evse_index][connector_index].operational = value;
-// }
 
-pub fn get_connector(evse_index: usize, connector_index: usize) -> Connector {
-    EVSES.lock().unwrap()[evse_index][connector_index].clone()
+pub fn set_connector_operational_status(evse_index: usize, connector_index: usize, value: bool) {
+    update_connector(evse_index, connector_index, |connector| connector.operational = value);
+}
+
+/// Change a connector while the backend is locked.
+fn update_connector<F: FnOnce(&mut Connector)>(evse_index: usize, connector_index: usize, update: F) {
+    let mut backend = current().backend.lock().unwrap();
+
+    if let Some(mut connector) = backend.get_connector(evse_index, connector_index) {
+        update(&mut connector);
+
+        backend.set_connector(evse_index, connector_index, connector);
+    }
+}
+
+pub fn set_connector(evse_index: usize, connector_index: usize, value: Connector) {
+    update_connector(evse_index, connector_index, |connector| *connector = value);
+}
+
+/// Get the connector, `None` if the topology has no such connector, e.g. for indexes from a request of CSMS.
+pub fn get_connector(evse_index: usize, connector_index: usize) -> Option<Connector> {
+    current().backend.lock().unwrap().get_connector(evse_index, connector_index)
+}
+
+pub fn set_evses(value: Vec<Vec<Connector>>) {
+    current().backend.lock().unwrap().set_evses(value);
+}
+
+pub fn evse_count() -> usize {
+    current().backend.lock().unwrap().evse_count()
+}
+
+pub fn connector_count(evse_index: usize) -> usize {
+    current().backend.lock().unwrap().connector_count(evse_index)
+}
+
+pub fn get_connectors(evse_index: usize) -> Vec<Connector> {
+    let backend = current().backend.lock().unwrap();
+
+    (0..backend.connector_count(evse_index)).filter_map(|connector_index| backend.get_connector(evse_index, connector_index)).collect()
+}
+
+/// Get the index of the first operational connector of the EVSE which has one of the given statuses.
+pub fn find_connector(evse_index: usize, statuses: &[&str]) -> Option<usize> {
+    get_connectors(evse_index).iter().position(|connector| connector.operational && statuses.contains(&connector.status))
 }
 
 pub fn queue_size() -> usize {
-    QUEUE.lock().unwrap().size()
+    current().backend.lock().unwrap().get_queued_messages().len()
 }
 
+/// Get the maximum number of queued messages from `MESSAGE_QUEUE_CAPACITY`.
+pub fn queue_capacity() -> usize {
+    config::var("MESSAGE_QUEUE_CAPACITY").ok()
+        .and_then(|var| var.parse::<usize>().ok())
+        .filter(|capacity| *capacity > 0)
+        .unwrap_or(DEFAULT_QUEUE_CAPACITY)
+}
+
+/// Add a message to the queue.
+///
+/// While the station is offline transaction related messages are always kept, other messages are dropped
+/// once the queue holds `OFFLINE_QUEUE_SIZE` of them.
+/// Full queue is handled according to `MESSAGE_QUEUE_OVERFLOW`: `drop-oldest` (default) drops the oldest message
+/// which isn't related to a transaction, `reject` drops the new message and `block` waits up to
+/// `MESSAGE_QUEUE_BLOCK_TIMEOUT` seconds until messages are sent. Only background threads are blocked, messages
+/// of the client thread which sends the queue are rejected. Overflow is reported with MemoryExhaustion security event.
 pub fn queue_add(s: String) {
-    match QUEUE.lock().unwrap().add(s) {
-        Err(e) => println!("{:?}", e),
-        _ => (),
-    };
+    let overflowed = enqueue(s);
+
+    // Overflow is reported once until messages fit into the queue again, the report itself may overflow it.
+    let reported = std::mem::replace(&mut *current().queue_exhausted.lock().unwrap(), overflowed);
+
+    if overflowed && !reported {
+        if let Err(e) = security_events::notify("MemoryExhaustion", Some("Message queue is full")) {
+            println!("Memory exhaustion couldn't be reported ({})", e);
+        }
+    }
 }
 
-pub fn queue_pop() -> String {
-    match QUEUE.lock().unwrap().remove() {
-        Ok(res) => res,
-        Err(_) => String::from(""),
+/// Add a message to the queue according to the overflow policy.
+///
+/// Returns `true` if the queue was full.
+fn enqueue(s: String) -> bool {
+    if !is_online() && !is_transaction_message(&s) {
+        let queued = queue_messages().iter().filter(|msg| !is_transaction_message(msg)).count();
+
+        if queued >= OFFLINE_QUEUE_SIZE {
+            println!("Offline queue is full, message is dropped: {}", s);
+
+            return true;
+        }
+    }
+
+    let capacity = queue_capacity();
+    let mut backend = current().backend.lock().unwrap();
+    let overflowed = backend.get_queued_messages().len() >= capacity;
+
+    if overflowed {
+        match config::var("MESSAGE_QUEUE_OVERFLOW").as_deref() {
+            Ok("reject") => {
+                println!("Message queue is full, message is rejected: {}", s);
+
+                return true;
+            },
+            // Queue can't be sent while the client thread waits for space in it.
+            Ok("block") if is_client_thread() => {
+                println!("Message queue is full, message of the client is rejected: {}", s);
+
+                return true;
+            },
+            Ok("block") => {
+                let timeout = config::var("MESSAGE_QUEUE_BLOCK_TIMEOUT").ok().and_then(|var| var.parse::<u64>().ok()).unwrap_or(DEFAULT_QUEUE_BLOCK_TIMEOUT);
+
+                backend = current().queue_space.wait_timeout_while(backend, Duration::from_secs(timeout), |backend| backend.get_queued_messages().len() >= capacity).unwrap().0;
+
+                if backend.get_queued_messages().len() >= capacity {
+                    println!("Message queue is still full, message is rejected: {}", s);
+
+                    return true;
+                }
+            },
+            _ => {
+                let index = backend.get_queued_messages().iter().position(|msg| !is_transaction_message(msg));
+
+                // Transaction related messages give way only to each other.
+                let index = match index {
+                    Some(res) => res,
+                    None if !is_transaction_message(&s) => {
+                        println!("Message queue is full, message is dropped: {}", s);
+
+                        return true;
+                    },
+                    None => 0,
+                };
+
+                if let Some(dropped) = backend.remove_queued_message(index) {
+                    println!("Message queue is full, oldest message is dropped: {}", dropped);
+                }
+            },
+        }
+    }
+
+    backend.add_queued_message(s);
+
+    drop(backend);
+
+    // Messages queued by background tasks are sent without waiting for the next event of the client.
+    if let Some(wake) = current().queue_waker.lock().unwrap().as_ref() {
+        wake();
     }
+
+    overflowed
+}
+
+/// Set the callback which is called whenever a message is queued.
+pub fn set_queue_waker(waker: Option<Box<dyn Fn() + Send>>) {
+    *current().queue_waker.lock().unwrap() = waker;
+}
+
+/// Mark the current thread as the one which runs the WebSocket client.
+pub fn set_client_thread() {
+    *current().client_thread.lock().unwrap() = Some(thread::current().id());
+}
+
+fn is_client_thread() -> bool {
+    *current().client_thread.lock().unwrap() == Some(thread::current().id())
+}
+
+/// Set the callback which closes the open connection to CSMS.
+pub fn set_connection_closer(closer: Option<Box<dyn Fn() + Send>>) {
+    *current().connection_closer.lock().unwrap() = closer;
+}
+
+/// Stop the station, the open connection is closed and the station doesn't reconnect.
+pub fn request_stop() {
+    *current().stop_requested.lock().unwrap() = true;
+
+    if let Some(close) = current().connection_closer.lock().unwrap().as_ref() {
+        close();
+    }
+}
+
+pub fn set_stop_requested(value: bool) {
+    *current().stop_requested.lock().unwrap() = value;
+}
+
+pub fn is_stop_requested() -> bool {
+    *current().stop_requested.lock().unwrap()
+}
+
+fn queue_priority(msg: &str) -> u8 {
+    let action = json::parse(msg).map(|parsed_msg| parsed_msg[2].to_string()).unwrap_or_default();
+
+    if URGENT_ACTIONS.contains(&action.as_str()) {
+        0
+    } else if DEFERRED_ACTIONS.contains(&action.as_str()) {
+        2
+    } else {
+        1
+    }
+}
+
+fn is_transaction_message(msg: &str) -> bool {
+    json::parse(msg).is_ok_and(|parsed_msg| parsed_msg[2] == "TransactionEvent")
+}
+
+/// Remove the next message to send from the queue.
+///
+/// Transaction events and status notifications come first and heartbeats with meter values last, e.g. when
+/// the backlog is sent after reconnecting. Messages of the same priority keep their order.
+pub fn queue_pop() -> String {
+    let mut backend = current().backend.lock().unwrap();
+
+    let index = backend.get_queued_messages().iter().enumerate()
+        .min_by_key(|(index, msg)| (queue_priority(msg), *index))
+        .map_or(0, |(index, _)| index);
+
+    let msg = backend.remove_queued_message(index).unwrap_or_default();
+
+    drop(backend);
+
+    current().queue_space.notify_all();
+
+    msg
+}
+
+/// Remove the first queued message with the given action.
+pub fn queue_remove_action(action: &str) -> Option<String> {
+    let mut backend = current().backend.lock().unwrap();
+
+    let position = backend.get_queued_messages().iter().position(|msg| json::parse(msg).is_ok_and(|parsed_msg| parsed_msg[2] == action));
+
+    let removed = backend.remove_queued_message(position?);
+
+    current().queue_space.notify_all();
+
+    removed
+}
+
+pub fn queue_messages() -> Vec<String> {
+    current().backend.lock().unwrap().get_queued_messages()
 }
 
 pub fn set_last_sent_message(id: String, timestamp: u64) {
-    LAST_SENT_MESSAGE.lock().unwrap().id = Some(id);
-    LAST_SENT_MESSAGE.lock().unwrap().timestamp = Some(timestamp);
+    current().last_sent_message.lock().unwrap().id = Some(id);
+    current().last_sent_message.lock().unwrap().timestamp = Some(timestamp);
 }
 
 pub fn get_last_sent_message() -> SentMessage {
-    LAST_SENT_MESSAGE.lock().unwrap().clone()
+    current().last_sent_message.lock().unwrap().clone()
+}
+
+/// Forget the last sent message once its response has arrived.
+///
+/// Returns `false` if another message was sent last.
+pub fn clear_last_sent_message(id: &str) -> bool {
+    let mut last_sent_message = current().last_sent_message.lock().unwrap();
+
+    if last_sent_message.id.as_deref() != Some(id) {
+        return false;
+    }
+
+    *last_sent_message = SentMessage { id: None, timestamp: None };
+
+    true
+}
+
+pub fn set_local_list_entry(key: String, value: String) {
+    current().local_list.lock().unwrap().insert(key, value);
+}
+
+pub fn get_local_list_entry(key: &str) -> String {
+    match current().local_list.lock().unwrap().get(key) {
+        Some(value) => value.to_string(),
+        None => String::from(""),
+    }
+}
+
+pub fn delete_local_list_entry(key: &str) {
+    current().local_list.lock().unwrap().remove(key);
+}
+
+pub fn clear_local_list() {
+    current().local_list.lock().unwrap().clear();
+}
+
+pub fn set_local_list_version(version: u64) {
+    *current().local_list_version.lock().unwrap() = version;
+}
+
+pub fn get_local_list_version() -> u64 {
+    *current().local_list_version.lock().unwrap()
+}
+
+pub fn set_reservation(key: u64, value: String) {
+    current().reservations.lock().unwrap().insert(key, value);
+}
+
+pub fn get_reservation(key: u64) -> String {
+    match current().reservations.lock().unwrap().get(&key) {
+        Some(value) => value.to_string(),
+        None => String::from(""),
+    }
+}
+
+pub fn get_reservations() -> Vec<(u64, String)> {
+    current().reservations.lock().unwrap().iter().map(|(key, value)| (*key, value.to_string())).collect()
+}
+
+pub fn delete_reservation(key: u64) {
+    current().reservations.lock().unwrap().remove(&key);
+}
+
+pub fn set_charging_profile(key: u64, value: String) {
+    current().charging_profiles.lock().unwrap().insert(key, value);
+}
+
+pub fn get_charging_profiles() -> Vec<(u64, String)> {
+    current().charging_profiles.lock().unwrap().iter().map(|(key, value)| (*key, value.to_string())).collect()
+}
+
+pub fn delete_charging_profile(key: u64) {
+    current().charging_profiles.lock().unwrap().remove(&key);
+}
+
+pub fn set_display_message(key: u64, value: String) {
+    current().display_messages.lock().unwrap().insert(key, value);
+}
+
+pub fn get_display_message(key: u64) -> String {
+    match current().display_messages.lock().unwrap().get(&key) {
+        Some(value) => value.to_string(),
+        None => String::from(""),
+    }
+}
+
+pub fn get_display_messages() -> Vec<(u64, String)> {
+    current().display_messages.lock().unwrap().iter().map(|(key, value)| (*key, value.to_string())).collect()
+}
+
+pub fn delete_display_message(key: u64) {
+    current().display_messages.lock().unwrap().remove(&key);
+}
+
+pub fn set_firmware_request(request_id: Option<u64>) {
+    *current().firmware_request.lock().unwrap() = request_id;
+}
+
+pub fn get_firmware_request() -> Option<u64> {
+    *current().firmware_request.lock().unwrap()
+}
+
+pub fn set_published_firmware(value: Option<String>) {
+    *current().published_firmware.lock().unwrap() = value;
+}
+
+pub fn get_published_firmware() -> Option<String> {
+    current().published_firmware.lock().unwrap().clone()
+}
+
+pub fn set_log_request(request_id: Option<u64>) {
+    *current().log_request.lock().unwrap() = request_id;
+}
+
+pub fn get_log_request() -> Option<u64> {
+    *current().log_request.lock().unwrap()
+}
+
+pub fn transcript_add(direction: &str, msg: &str) {
+    let now = clock::now();
+    let mut transcript = current().transcript.lock().unwrap();
+
+    if transcript.len() >= TRANSCRIPT_SIZE {
+        transcript.remove(0);
+    }
+
+    transcript.push((now.timestamp(), format!("{} {} {}", now.to_rfc3339(), direction, msg)));
+}
+
+pub fn get_transcript() -> Vec<(i64, String)> {
+    current().transcript.lock().unwrap().clone()
+}
+
+pub fn set_monitor(key: u64, value: String) {
+    current().monitors.lock().unwrap().insert(key, value);
+}
+
+pub fn get_monitor(key: u64) -> String {
+    match current().monitors.lock().unwrap().get(&key) {
+        Some(value) => value.to_string(),
+        None => String::from(""),
+    }
+}
+
+pub fn get_monitors() -> Vec<(u64, String)> {
+    current().monitors.lock().unwrap().iter().map(|(key, value)| (*key, value.to_string())).collect()
+}
+
+pub fn delete_monitor(key: u64) {
+    current().monitors.lock().unwrap().remove(&key);
+}
+
+pub fn set_monitoring_level(severity: u8) {
+    *current().monitoring_level.lock().unwrap() = severity;
+}
+
+pub fn get_monitoring_level() -> u8 {
+    *current().monitoring_level.lock().unwrap()
+}
+
+pub fn next_event_id() -> u64 {
+    let mut event_id = current().event_id.lock().unwrap();
+
+    *event_id += 1;
+
+    *event_id
+}
+
+pub fn set_active_event(key: String, event_id: Option<u64>) {
+    match event_id {
+        Some(id) => current().active_events.lock().unwrap().insert(key, id),
+        None => current().active_events.lock().unwrap().remove(&key),
+    };
+}
+
+pub fn get_active_event(key: &str) -> Option<u64> {
+    current().active_events.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_certificate(key: String, value: String) {
+    current().certificates.lock().unwrap().insert(key, value);
+}
+
+pub fn get_certificates() -> Vec<(String, String)> {
+    current().certificates.lock().unwrap().iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+pub fn delete_certificate(key: &str) {
+    current().certificates.lock().unwrap().remove(key);
+}
+
+pub fn set_pending_private_key(key: String, value: Option<String>) {
+    match value {
+        Some(data) => current().pending_private_keys.lock().unwrap().insert(key, data),
+        None => current().pending_private_keys.lock().unwrap().remove(&key),
+    };
+}
+
+pub fn get_pending_private_key(key: &str) -> Option<String> {
+    current().pending_private_keys.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_station_certificate(key: String, value: Option<String>) {
+    match value {
+        Some(data) => current().station_certificates.lock().unwrap().insert(key, data),
+        None => current().station_certificates.lock().unwrap().remove(&key),
+    };
+}
+
+pub fn get_station_certificate(key: &str) -> Option<String> {
+    current().station_certificates.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_contract_certificate(key: String, value: String) {
+    current().contract_certificates.lock().unwrap().insert(key, value);
+}
+
+pub fn get_contract_certificate(key: &str) -> Option<String> {
+    current().contract_certificates.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_pending_authorization(key: String, value: Option<usize>) {
+    match value {
+        Some(data) => current().pending_authorizations.lock().unwrap().insert(key, data),
+        None => current().pending_authorizations.lock().unwrap().remove(&key),
+    };
+}
+
+pub fn get_pending_authorization(key: &str) -> Option<usize> {
+    current().pending_authorizations.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_pending_remote_start(key: String, value: Option<String>) {
+    match value {
+        Some(data) => current().pending_remote_starts.lock().unwrap().insert(key, data),
+        None => current().pending_remote_starts.lock().unwrap().remove(&key),
+    };
+}
+
+pub fn get_pending_remote_start(key: &str) -> Option<String> {
+    current().pending_remote_starts.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_auth_cache_entry(key: String, value: String) {
+    current().auth_cache.lock().unwrap().insert(key, value);
+}
+
+pub fn get_auth_cache_entry(key: &str) -> Option<String> {
+    current().auth_cache.lock().unwrap().get(key).cloned()
+}
+
+pub fn delete_auth_cache_entry(key: &str) {
+    current().auth_cache.lock().unwrap().remove(key);
+}
+
+pub fn set_meter_timestamp(key: String, value: i64) {
+    current().meter_timestamps.lock().unwrap().insert(key, value);
+}
+
+pub fn get_meter_timestamp(key: &str) -> Option<i64> {
+    current().meter_timestamps.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_enforced_limit(evse_index: usize, value: Option<String>) {
+    match value {
+        Some(data) => current().enforced_limits.lock().unwrap().insert(evse_index, data),
+        None => current().enforced_limits.lock().unwrap().remove(&evse_index),
+    };
+}
+
+pub fn get_enforced_limit(evse_index: usize) -> Option<String> {
+    current().enforced_limits.lock().unwrap().get(&evse_index).cloned()
+}
+
+pub fn set_external_limit(key: String, value: Option<String>) {
+    match value {
+        Some(data) => current().external_limits.lock().unwrap().insert(key, data),
+        None => current().external_limits.lock().unwrap().remove(&key),
+    };
+}
+
+pub fn get_external_limits() -> Vec<(String, String)> {
+    current().external_limits.lock().unwrap().iter().map(|(key, value)| (key.to_owned(), value.to_owned())).collect()
+}
+
+pub fn add_recent_response(key: String, value: String) {
+    let mut recent_responses = current().recent_responses.lock().unwrap();
+
+    if recent_responses.len() >= RECENT_RESPONSES_SIZE {
+        recent_responses.remove(0);
+    }
+
+    recent_responses.push((key, value));
+}
+
+pub fn get_recent_response(key: &str) -> Option<String> {
+    current().recent_responses.lock().unwrap().iter().find(|(id, _)| id == key).map(|(_, response)| response.to_owned())
+}
+
+pub fn set_received_call(key: String, value: String) {
+    current().received_calls.lock().unwrap().insert(key, value);
+}
+
+pub fn take_received_call(key: &str) -> Option<String> {
+    current().received_calls.lock().unwrap().remove(key)
+}
+
+pub fn set_schema(key: String, value: String) {
+    current().schemas.lock().unwrap().insert(key, value);
+}
+
+pub fn get_schema(key: &str) -> Option<String> {
+    current().schemas.lock().unwrap().get(key).cloned()
+}
+
+pub fn set_message_attempts(key: String, value: Option<u32>) {
+    match value {
+        Some(data) => current().message_attempts.lock().unwrap().insert(key, data),
+        None => current().message_attempts.lock().unwrap().remove(&key),
+    };
+}
+
+pub fn get_message_attempts(key: &str) -> u32 {
+    current().message_attempts.lock().unwrap().get(key).cloned().unwrap_or(0)
+}
+
+pub fn delay_message(moment: i64, msg: String) {
+    current().delayed_messages.lock().unwrap().push((moment, msg));
+}
+
+/// Get the moment (ms) when the next delayed message is due.
+pub fn next_delayed_message() -> Option<i64> {
+    current().delayed_messages.lock().unwrap().iter().map(|(moment, _)| *moment).min()
+}
+
+/// Cancel the retry of a message which CSMS has answered after its timeout.
+pub fn cancel_delayed_message(key: &str) {
+    current().delayed_messages.lock().unwrap().retain(|(_, msg)| json::parse(msg).map_or(true, |parsed_msg| parsed_msg[1] != key));
+    current().message_attempts.lock().unwrap().remove(key);
+}
+
+/// Move delayed messages which are due at the given moment to the queue.
+///
+/// Messages are queued after the lock is released since queueing may report an overflow which is queued too.
+pub fn queue_due_messages(now: i64) {
+    let due_messages: Vec<String> = {
+        let mut delayed_messages = current().delayed_messages.lock().unwrap();
+        let (due, delayed) = delayed_messages.drain(..).partition(|(moment, _)| *moment <= now);
+
+        *delayed_messages = delayed;
+
+        due.into_iter().map(|(_, msg)| msg).collect()
+    };
+
+    for msg in due_messages {
+        queue_add(msg);
+    }
+}
+
+pub fn set_last_send_time(value: i64) {
+    *current().last_send_time.lock().unwrap() = Some(value);
+}
+
+pub fn get_last_send_time() -> Option<i64> {
+    *current().last_send_time.lock().unwrap()
+}
+
+pub fn set_clock_offset(value: i64) {
+    *current().clock_offset.lock().unwrap() = value;
+}
+
+pub fn get_clock_offset() -> i64 {
+    *current().clock_offset.lock().unwrap()
+}
+
+pub fn set_clock_synchronized(value: bool) {
+    *current().clock_synchronized.lock().unwrap() = value;
+}
+
+pub fn is_clock_synchronized() -> bool {
+    *current().clock_synchronized.lock().unwrap()
+}
+
+pub fn set_simulation_start(value: i64) {
+    *current().simulation_start.lock().unwrap() = Some(value);
+}
+
+pub fn get_simulation_start() -> Option<i64> {
+    *current().simulation_start.lock().unwrap()
+}
+
+pub fn add_clock_advance(value: i64) {
+    *current().clock_advance.lock().unwrap() += value;
+}
+
+pub fn get_clock_advance() -> i64 {
+    *current().clock_advance.lock().unwrap()
+}
+
+pub fn next_id_sequence() -> u64 {
+    let mut sequence = current().id_sequence.lock().unwrap();
+
+    *sequence += 1;
+
+    *sequence
+}
+
+pub fn next_random_sequence() -> u64 {
+    let mut sequence = current().random_sequence.lock().unwrap();
+
+    *sequence += 1;
+
+    *sequence
+}
+
+pub fn set_registration_status(value: &'static str) {
+    *current().registration_status.lock().unwrap() = value;
+}
+
+pub fn get_registration_status() -> &'static str {
+    *current().registration_status.lock().unwrap()
+}
+
+pub fn set_boot_rejections(value: u32) {
+    *current().boot_rejections.lock().unwrap() = value;
+}
+
+pub fn get_boot_rejections() -> u32 {
+    *current().boot_rejections.lock().unwrap()
+}
+
+pub fn set_boot_reason(value: String) {
+    *current().boot_reason.lock().unwrap() = value;
+}
+
+pub fn get_boot_reason() -> String {
+    current().boot_reason.lock().unwrap().clone()
+}
+
+pub fn set_reconnect(value: bool) {
+    *current().reconnect.lock().unwrap() = value;
+}
+
+pub fn take_reconnect() -> bool {
+    let mut reconnect = current().reconnect.lock().unwrap();
+    let value = *reconnect;
+
+    *reconnect = false;
+
+    value
+}
+
+pub fn set_online(value: bool) {
+    let mut online = current().online.lock().unwrap();
+
+    if *online && !value {
+        *current().offline_since.lock().unwrap() = Some(clock::now().timestamp());
+    }
+
+    *online = value;
+}
+
+pub fn is_online() -> bool {
+    *current().online.lock().unwrap()
+}
+
+pub fn take_offline_since() -> Option<i64> {
+    current().offline_since.lock().unwrap().take()
+}
+
+pub fn set_connection_opened(value: bool) {
+    *current().connection_opened.lock().unwrap() = value;
+}
+
+pub fn take_connection_opened() -> bool {
+    let mut opened = current().connection_opened.lock().unwrap();
+    let value = *opened;
+
+    *opened = false;
+
+    value
+}
+
+pub fn set_network_slot(value: usize) {
+    *current().network_slot.lock().unwrap() = value;
+}
+
+pub fn get_network_slot() -> usize {
+    *current().network_slot.lock().unwrap()
+}
+
+pub fn set_reachable_network_slot(value: Option<usize>) {
+    *current().reachable_network_slot.lock().unwrap() = value;
+}
+
+pub fn take_reachable_network_slot() -> Option<usize> {
+    current().reachable_network_slot.lock().unwrap().take()
+}
+
+pub fn set_basic_auth_password(value: Option<String>) {
+    *current().basic_auth_password.lock().unwrap() = value;
+}
+
+pub fn get_basic_auth_password() -> Option<String> {
+    current().basic_auth_password.lock().unwrap().clone()
+}
+
+pub fn set_previous_basic_auth_password(value: Option<String>) {
+    *current().previous_basic_auth_password.lock().unwrap() = value;
+}
+
+pub fn take_previous_basic_auth_password() -> Option<String> {
+    current().previous_basic_auth_password.lock().unwrap().take()
+}
+
+pub fn set_protocol(value: &'static str) {
+    *current().protocol.lock().unwrap() = value;
+}
+
+pub fn get_protocol() -> &'static str {
+    *current().protocol.lock().unwrap()
+}
+
+pub fn set_tunneled_endpoint(value: Option<(String, u16)>) {
+    *current().tunneled_endpoint.lock().unwrap() = value;
+}
+
+pub fn get_tunneled_endpoint() -> Option<(String, u16)> {
+    current().tunneled_endpoint.lock().unwrap().clone()
+}
+
+pub fn set_device_model(value: Vec<String>) {
+    current().backend.lock().unwrap().set_device_model(value);
+}
+
+pub fn get_device_model() -> Vec<String> {
+    current().backend.lock().unwrap().get_device_model()
+}
+
+pub fn set_variable_value(key: String, value: String) {
+    current().backend.lock().unwrap().set_variable_value(key, value);
+}
+
+pub fn get_variable_value(key: &str) -> Option<String> {
+    current().backend.lock().unwrap().get_variable_value(key)
+}
+
+pub fn get_variable_values() -> Vec<(String, String)> {
+    current().backend.lock().unwrap().get_variable_values()
+}
+
+pub fn set_temperature(evse_index: usize, value: f64) {
+    current().temperatures.lock().unwrap().insert(evse_index, value);
+}
+
+pub fn get_temperature(evse_index: usize) -> Option<f64> {
+    current().temperatures.lock().unwrap().get(&evse_index).cloned()
+}
+
+pub fn set_derating(evse_index: usize, value: bool) {
+    let mut derated_evses = current().derated_evses.lock().unwrap();
+
+    derated_evses.retain(|index| *index != evse_index);
+
+    if value {
+        derated_evses.push(evse_index);
+    }
+}
+
+pub fn is_derating(evse_index: usize) -> bool {
+    current().derated_evses.lock().unwrap().contains(&evse_index)
+}
+
+pub fn set_driver_session(evse_index: usize, value: Option<String>) {
+    match value {
+        Some(data) => current().driver_sessions.lock().unwrap().insert(evse_index, data),
+        None => current().driver_sessions.lock().unwrap().remove(&evse_index),
+    };
+}
+
+pub fn get_driver_sessions() -> Vec<(usize, String)> {
+    current().driver_sessions.lock().unwrap().iter().map(|(evse_index, session)| (*evse_index, session.clone())).collect()
 }
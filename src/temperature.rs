@@ -0,0 +1,86 @@
+use json::JsonValue;
+
+use crate::charging_profiles;
+use crate::config;
+use crate::errors::EmulatorError;
+use crate::events;
+use crate::meter;
+use crate::storage;
+use crate::topology;
+
+// Default ambient temperature (°C) and temperature rise (°C) of the EVSE delivering its maximum power.
+const DEFAULT_AMBIENT_TEMPERATURE: f64 = 25.0;
+const DEFAULT_TEMPERATURE_RISE: f64 = 40.0;
+// Default temperature (°C) above which the power is derated and the derated power (% of the maximum power).
+const DEFAULT_DERATING_TEMPERATURE: f64 = 60.0;
+const DEFAULT_DERATING_POWER: f64 = 50.0;
+// Time constant (s) in which the temperature approaches its steady state.
+const TIME_CONSTANT: f64 = 300.0;
+// Temperature (°C) below the threshold at which derating ends.
+const HYSTERESIS: f64 = 5.0;
+
+/// Update the internal temperature of the EVSE after charging with its current power for the given time (s).
+///
+/// Temperature approaches `AMBIENT_TEMPERATURE` raised by `TEMPERATURE_RISE` at the maximum power of the EVSE.
+/// Power is derated to `DERATING_POWER` (%) once the temperature exceeds `DERATING_TEMPERATURE` and until it cools
+/// down, the start and the end of derating are reported with NotifyEvent.
+pub fn update(evse_index: usize, seconds: f64) -> Result<(), EmulatorError> {
+    let max_power = topology::max_power(evse_index);
+    let load = if max_power > 0.0 { meter::power(evse_index).abs() / max_power } else { 0.0 };
+    let target = env_f64("AMBIENT_TEMPERATURE", DEFAULT_AMBIENT_TEMPERATURE) + env_f64("TEMPERATURE_RISE", DEFAULT_TEMPERATURE_RISE) * load;
+
+    let current = get(evse_index);
+    let temperature = current + (target - current) * (1.0 - (-seconds / TIME_CONSTANT).exp());
+
+    storage::set_temperature(evse_index, temperature);
+
+    let threshold = env_f64("DERATING_TEMPERATURE", DEFAULT_DERATING_TEMPERATURE);
+    let derating = storage::is_derating(evse_index);
+
+    if !derating && temperature > threshold {
+        storage::set_derating(evse_index, true);
+        events::raise(component(evse_index), "Problem", "HighTemperature")?;
+
+        println!("Power of EVSE {} is derated at {:.1} °C.", evse_index + 1, temperature);
+    } else if derating && temperature < threshold - HYSTERESIS {
+        storage::set_derating(evse_index, false);
+        events::clear(component(evse_index), "Problem")?;
+    } else {
+        return Ok(());
+    }
+
+    charging_profiles::apply_limits();
+
+    Ok(())
+}
+
+/// Get the internal temperature (°C) of the EVSE.
+pub fn get(evse_index: usize) -> f64 {
+    storage::get_temperature(evse_index).unwrap_or_else(|| env_f64("AMBIENT_TEMPERATURE", DEFAULT_AMBIENT_TEMPERATURE))
+}
+
+/// Get the power (W) to which the overheated EVSE is derated.
+///
+/// Returns `None` if the EVSE isn't derated.
+pub fn derated_power(evse_index: usize) -> Option<f64> {
+    if !storage::is_derating(evse_index) {
+        return None;
+    }
+
+    Some(topology::max_power(evse_index) * env_f64("DERATING_POWER", DEFAULT_DERATING_POWER).clamp(0.0, 100.0) / 100.0)
+}
+
+/// Get the device model component of the temperature sensor of the EVSE.
+fn component(evse_index: usize) -> JsonValue {
+    object!{
+        "name" => "TemperatureSensor",
+        "evse" => object!{ "id" => evse_index + 1 },
+    }
+}
+
+fn env_f64(name: &str, default: f64) -> f64 {
+    match config::var(name) {
+        Ok(var) => var.parse::<f64>().unwrap_or(default),
+        _ => default,
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+
+use json::JsonValue;
+
+use crate::charging_profiles;
+use crate::config;
+use crate::storage;
+
+// Connector type which is used if the configuration doesn't specify it.
+const DEFAULT_CONNECTOR_TYPE: &str = "cType2";
+// Connector types which deliver DC power.
+const DC_CONNECTOR_TYPES: [&str; 4] = ["cCCS1", "cCCS2", "cG105", "cChaoJi"];
+
+/// Read the topology of the charging station and initialize its EVSE.
+///
+/// The file referenced by `TOPOLOGY_FILE` contains an array of EVSE objects with a `connectors` array,
+/// each connector has optional `type`, `maxCurrent` (A), `maxVoltage` (V), `phases` and `maxPower` (W) fields.
+/// Limits which aren't configured depend on the connector type. Without the file the station has
+/// `EVSE_COUNT` EVSE with `CONNECTORS_PER_EVSE` connectors each.
+pub fn load() {
+    let evses: Vec<Vec<storage::Connector>> = match config::var("TOPOLOGY_FILE") {
+        Ok(path) if !path.is_empty() => {
+            let content = match fs::read_to_string(&path) {
+                Ok(res) => res,
+                Err(e) => panic!("Couldn't read TOPOLOGY_FILE ({})", e),
+            };
+
+            let topology = match json::parse(&content) {
+                Ok(result) => result,
+                Err(e) => panic!("Error during parsing: {:?}", e),
+            };
+
+            topology.members()
+                .map(|evse| evse["connectors"].members().map(connector).collect())
+                .collect()
+        },
+        _ => {
+            let evse_count = count("EVSE_COUNT");
+            let connector_count = count("CONNECTORS_PER_EVSE");
+
+            (0..evse_count)
+                .map(|_| (0..connector_count).map(|_| connector(&JsonValue::Null)).collect())
+                .collect()
+        },
+    };
+
+    if evses.is_empty() || evses.iter().any(|connectors| connectors.is_empty()) {
+        panic!("Every EVSE of the station topology must have at least one connector.");
+    }
+
+    for (evse_index, connectors) in evses.iter().enumerate() {
+        let types: Vec<&str> = connectors.iter().map(|connector| connector.connector_type.as_str()).collect();
+
+        println!("EVSE {} connectors: {}", evse_index + 1, types.join(", "));
+    }
+
+    storage::set_evses(evses);
+}
+
+/// Get the maximum power (W) which the EVSE is able to deliver.
+///
+/// The connector which is used for charging bounds the power, otherwise the most powerful connector does.
+pub fn max_power(evse_index: usize) -> f64 {
+    let connectors = storage::get_connectors(evse_index);
+
+    match connectors.iter().find(|connector| connector.status == "Occupied") {
+        Some(connector) => connector.max_power,
+        None => connectors.iter().map(|connector| connector.max_power).fold(0.0, f64::max),
+    }
+}
+
+fn connector(config: &JsonValue) -> storage::Connector {
+    let connector_type = config["type"].as_str().unwrap_or(DEFAULT_CONNECTOR_TYPE);
+
+    // DC chargers deliver up to 50 kW, AC ones are limited by the supply.
+    let (default_current, default_voltage, default_phases) = match connector_type {
+        connector_type if DC_CONNECTOR_TYPES.contains(&connector_type) => (125.0, 400.0, 0),
+        "cType1" => (32.0, charging_profiles::NOMINAL_VOLTAGE, 1),
+        _ => (32.0, charging_profiles::NOMINAL_VOLTAGE, charging_profiles::DEFAULT_NUMBER_PHASES as u8),
+    };
+
+    let max_current = config["maxCurrent"].as_f64().unwrap_or(default_current);
+    let max_voltage = config["maxVoltage"].as_f64().unwrap_or(default_voltage);
+    let phases = config["phases"].as_u8().unwrap_or(default_phases);
+
+    storage::Connector {
+        status: "Inoperative",
+        operational: true,
+        power: 0.0,
+        charging_state: "Idle",
+        energy: 0.0,
+        energy_export: 0.0,
+        connector_type: connector_type.to_string(),
+        max_current,
+        max_voltage,
+        phases,
+        max_power: config["maxPower"].as_f64().unwrap_or(max_current * max_voltage * phases.max(1) as f64),
+        ev: None,
+    }
+}
+
+fn count(name: &str) -> usize {
+    match config::var(name) {
+        Ok(var) => var.parse::<usize>().unwrap_or(1),
+        _ => 1,
+    }
+}
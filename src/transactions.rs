@@ -0,0 +1,553 @@
+use chrono::{DateTime, Duration, Utc};
+use json::JsonValue;
+
+use crate::charging_profiles;
+use crate::clock;
+use crate::components;
+use crate::config;
+use crate::display_messages;
+use crate::errors::EmulatorError;
+use crate::ev;
+use crate::ids;
+use crate::journal;
+use crate::meter;
+use crate::requests;
+use crate::reservations;
+use crate::state_machine;
+use crate::state_machine::Event;
+use crate::storage;
+
+/// Start a transaction which was authorized at the charging station.
+///
+/// Reservation of the EVSE made for the id token is consumed by the transaction.
+/// Group id token of the token is stored to let other tokens of the group stop the transaction.
+/// Returns the id of the started transaction.
+pub fn start(evse_index: usize, id_token: &JsonValue, group_id_token: &JsonValue, trigger_reason: &str) -> Result<String, EmulatorError> {
+    let mut details = object!{ "idToken" => id_token.clone() };
+
+    if let Some(reservation_id) = reservations::consume(evse_index, &id_token["idToken"].to_string())? {
+        details["reservationId"] = reservation_id.into();
+    }
+
+    if !group_id_token.is_null() {
+        details["groupIdToken"] = group_id_token.clone();
+    }
+
+    let transaction_id = authorize(evse_index, details, trigger_reason)?;
+    let (evse_index, connector_index) = connector(&parse(&storage::get_transaction(&transaction_id))?);
+
+    connect(evse_index, connector_index, &transaction_id)?;
+
+    Ok(transaction_id)
+}
+
+/// Authorize a transaction on the connector returned by `free_connector`.
+///
+/// Transaction which was started by plugging in the cable gets the id token, otherwise a new transaction
+/// is created. Details are stored in the transaction, e.g. `idToken`, `remoteStartId` or `reservationId`.
+/// Returns the id of the transaction.
+pub fn authorize(evse_index: usize, details: JsonValue, trigger_reason: &str) -> Result<String, EmulatorError> {
+    let connector_index = free_connector(evse_index).unwrap_or(0);
+
+    let transaction_id = match find(evse_index, connector_index) {
+        Some(res) => res,
+        None => create(evse_index, connector_index),
+    };
+
+    let mut transaction = parse(&storage::get_transaction(&transaction_id))?;
+
+    for (key, value) in details.entries() {
+        transaction[key] = value.clone();
+    }
+
+    storage::set_transaction(transaction_id.clone(), transaction.dump());
+
+    // Other connectors of the EVSE are not reserved anymore.
+    state_machine::handle_evse(evse_index, Event::ReservationEnded);
+
+    notify(&transaction_id, trigger_reason, Some(details["idToken"].clone()))?;
+
+    Ok(transaction_id)
+}
+
+/// Start charging in an authorized transaction if the EV is connected already.
+///
+/// Cable of the EV is plugged in right away unless `AUTO_PLUG` is disabled,
+/// otherwise the transaction waits `TxCtrlr.EVConnectionTimeOut` seconds until the cable is plugged in.
+pub fn connect(evse_index: usize, connector_index: usize, transaction_id: &str) -> Result<(), EmulatorError> {
+    if state_machine::charging_state(evse_index, connector_index) != "Idle" {
+        start_charging(evse_index, connector_index, transaction_id, "ChargingStateChanged")?;
+    } else if auto_plug() {
+        plug_in(evse_index, connector_index)?;
+    } else {
+        let timeout: i64 = components::get_variable("TxCtrlr", "EVConnectionTimeOut").1
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+
+        let mut transaction = parse(&storage::get_transaction(transaction_id))?;
+        transaction["connectionDeadline"] = (clock::now() + Duration::seconds(timeout)).to_rfc3339().into();
+
+        storage::set_transaction(transaction_id.to_string(), transaction.dump());
+    }
+
+    Ok(())
+}
+
+/// End authorized transactions whose EV hasn't been connected before the connection timeout.
+pub fn cancel_timed_out() -> Result<(), EmulatorError> {
+    let now = clock::now();
+
+    for (transaction_id, transaction) in storage::get_transactions() {
+        let transaction = parse(&transaction)?;
+
+        let deadline = match transaction["connectionDeadline"].as_str() {
+            Some(res) => DateTime::parse_from_rfc3339(res)?.with_timezone(&Utc),
+            None => continue,
+        };
+
+        if deadline <= now {
+            println!("EV hasn't been connected in transaction {}.", transaction_id);
+
+            end(&transaction_id, "EVConnectTimeout", "Timeout")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Stop started transactions which have reached a target of the simulated session.
+///
+/// `SESSION_ENERGY_TARGET` is the energy (Wh) delivered in the transaction, `SESSION_DURATION_TARGET` is its
+/// duration (s) and `SESSION_SOC_TARGET` is the state of charge (%) of the EV. Transactions are stopped with
+/// reason `EnergyLimitReached`, `TimeLimitReached` or `SOCLimitReached`, there are no targets by default.
+pub fn stop_at_targets() -> Result<(), EmulatorError> {
+    let now = clock::now();
+
+    for (transaction_id, transaction) in storage::get_transactions() {
+        let transaction = parse(&transaction)?;
+
+        if transaction["started"] != true || !transaction["stoppedReason"].is_null() {
+            continue;
+        }
+
+        let (evse_index, _) = connector(&transaction);
+        let energy = meter::energy(evse_index) - transaction["startEnergy"].as_f64().unwrap_or(0.0);
+
+        let duration = match transaction["startedAt"].as_str().map(DateTime::parse_from_rfc3339) {
+            Some(Ok(res)) => (now - res.with_timezone(&Utc)).num_seconds() as f64,
+            _ => 0.0,
+        };
+
+        let (trigger_reason, stopped_reason) = if target("SESSION_ENERGY_TARGET").is_some_and(|target| energy >= target) {
+            ("EnergyLimitReached", "EnergyLimitReached")
+        } else if target("SESSION_DURATION_TARGET").is_some_and(|target| duration >= target) {
+            ("TimeLimitReached", "TimeLimitReached")
+        } else if target("SESSION_SOC_TARGET").is_some_and(|target| ev::soc(evse_index).is_some_and(|soc| soc >= target)) {
+            ("EnergyLimitReached", "SOCLimitReached")
+        } else {
+            continue;
+        };
+
+        println!("Transaction {} has reached its target ({}).", transaction_id, stopped_reason);
+
+        stop(&transaction_id, trigger_reason, stopped_reason)?;
+    }
+
+    Ok(())
+}
+
+/// Simulate the cable of an EV being plugged into the connector.
+///
+/// Authorized transaction which waits for the cable starts charging. If `TxCtrlr.TxStartPoint` contains
+/// `EVConnected`, the cable starts a transaction which is authorized later.
+/// Returns `false` if the connector can't be used.
+pub fn plug_in(evse_index: usize, connector_index: usize) -> Result<bool, EmulatorError> {
+    if !state_machine::handle(evse_index, connector_index, Event::PlugIn) {
+        return Ok(false);
+    }
+
+    let transaction_id = match find(evse_index, connector_index) {
+        Some(res) => {
+            // Transaction doesn't wait for the cable anymore.
+            let mut transaction = parse(&storage::get_transaction(&res))?;
+            transaction.remove("connectionDeadline");
+
+            storage::set_transaction(res.clone(), transaction.dump());
+
+            res
+        },
+        None if tx_points("TxStartPoint").iter().any(|point| point == "EVConnected") => create(evse_index, connector_index),
+        None => return Ok(true),
+    };
+
+    start_charging(evse_index, connector_index, &transaction_id, "CablePluggedIn")?;
+
+    Ok(true)
+}
+
+/// Simulate the cable of an EV being unplugged from the connector.
+///
+/// Stopped transaction ends because the EV has departed. Transaction which is still authorized ends because
+/// the EV is disconnected unless `TxCtrlr.TxStopPoint` only contains `Authorized`.
+/// Returns `false` if no EV is connected.
+pub fn plug_out(evse_index: usize, connector_index: usize) -> Result<bool, EmulatorError> {
+    if state_machine::charging_state(evse_index, connector_index) == "Idle" {
+        return Ok(false);
+    }
+
+    let transaction_id = find(evse_index, connector_index);
+
+    if let Some(transaction_id) = &transaction_id {
+        let transaction = parse(&storage::get_transaction(transaction_id))?;
+        let ends = transaction["idToken"].is_null() || tx_points("TxStopPoint").iter().any(|point| point != "Authorized");
+
+        if ends || !transaction["stoppedReason"].is_null() {
+            state_machine::handle(evse_index, connector_index, Event::StopCharging);
+
+            match transaction["stoppedReason"].as_str() {
+                Some(stopped_reason) => end(transaction_id, "EVDeparted", stopped_reason)?,
+                None => end(transaction_id, "EVCommunicationLost", "EVDisconnected")?,
+            };
+        }
+    }
+
+    let res = state_machine::handle(evse_index, connector_index, Event::PlugOut);
+
+    // Transaction goes on until the authorization is stopped.
+    if let Some(transaction_id) = transaction_id.filter(|transaction_id| !storage::get_transaction(transaction_id).is_empty()) {
+        notify(&transaction_id, "EVCommunicationLost", None)?;
+    }
+
+    Ok(res)
+}
+
+/// Stop energy transfer of the transaction.
+///
+/// Transaction ends right away unless `TxCtrlr.TxStopPoint` only contains `EVConnected`, then it ends when
+/// the EV departs. Cable is unplugged right away unless `AUTO_PLUG` is disabled.
+pub fn stop(transaction_id: &str, trigger_reason: &str, stopped_reason: &str) -> Result<(), EmulatorError> {
+    let mut transaction = parse(&storage::get_transaction(transaction_id))?;
+    let (evse_index, connector_index) = connector(&transaction);
+
+    state_machine::handle(evse_index, connector_index, Event::StopCharging);
+
+    if state_machine::charging_state(evse_index, connector_index) == "Idle" || tx_points("TxStopPoint").iter().any(|point| point != "EVConnected") {
+        end(transaction_id, trigger_reason, stopped_reason)?;
+    } else {
+        transaction["stoppedReason"] = stopped_reason.into();
+
+        storage::set_transaction(transaction_id.to_string(), transaction.dump());
+
+        notify(transaction_id, trigger_reason, None)?;
+    }
+
+    if auto_plug() {
+        plug_out(evse_index, connector_index)?;
+    }
+
+    Ok(())
+}
+
+/// Send "Ended" TransactionEvent request and remove the transaction together with its charging profiles and messages.
+///
+/// Transaction which hasn't reached its start point yet is removed silently.
+pub fn end(transaction_id: &str, trigger_reason: &str, stopped_reason: &str) -> Result<(), EmulatorError> {
+    let transaction = parse(&storage::get_transaction(transaction_id))?;
+    let (evse_index, connector_index) = connector(&transaction);
+
+    if transaction["started"] == true {
+        // Values collected during the transaction precede the final sample.
+        let mut meter_value = if transaction["endedMeterValues"].is_array() { transaction["endedMeterValues"].clone() } else { JsonValue::new_array() };
+
+        for value in meter::transaction_sample(evse_index, "TxEndedMeasurands", "Transaction.End")?.members() {
+            meter_value.push(value.clone())?;
+        }
+
+        queue_event(transaction_id, requests::TransactionEvent {
+            event_type: "Ended",
+            trigger_reason,
+            charging_state: Some(state_machine::charging_state(evse_index, connector_index)),
+            stopped_reason: Some(stopped_reason),
+            meter_value: Some(meter_value),
+            ..Default::default()
+        })?;
+    }
+
+    storage::delete_transaction(transaction_id);
+    charging_profiles::remove_transaction_profiles(transaction_id)?;
+    display_messages::remove_transaction_messages(transaction_id)?;
+
+    Ok(())
+}
+
+/// Keep a meter value which is sent at the end of the transaction.
+pub fn add_ended_meter_value(transaction_id: &str, meter_value: JsonValue) -> Result<(), EmulatorError> {
+    let mut transaction = parse(&storage::get_transaction(transaction_id))?;
+
+    if !transaction["endedMeterValues"].is_array() {
+        transaction["endedMeterValues"] = JsonValue::new_array();
+    }
+
+    transaction["endedMeterValues"].push(meter_value)?;
+
+    storage::set_transaction(transaction_id.to_string(), transaction.dump());
+
+    Ok(())
+}
+
+/// Get the power (W) which the connector offers in its ongoing transaction.
+///
+/// Transaction of a deauthorized id token is de-energized once it reaches its energy limit.
+/// Returns `None` if there is no authorized transaction or it's stopped already.
+pub fn offered_power(evse_index: usize, connector_index: usize) -> Option<f64> {
+    let transaction = parse(&storage::get_transaction(&find(evse_index, connector_index)?)).ok()?;
+
+    if transaction["idToken"].is_null() || !transaction["stoppedReason"].is_null() {
+        return None;
+    }
+
+    if transaction["energyLimit"].as_f64().is_some_and(|limit| meter::energy(evse_index) >= limit) {
+        return Some(0.0);
+    }
+
+    Some(charging_profiles::charging_power(evse_index))
+}
+
+/// Handle id token info from a TransactionEvent response.
+///
+/// Transaction of an id token which is not accepted anymore is stopped if `TxCtrlr.StopTxOnInvalidId` is set,
+/// otherwise it delivers at most `TxCtrlr.MaxEnergyOnInvalidId` (Wh) in total.
+pub fn id_token_info_received(transaction_id: &str, id_token_info: &JsonValue) -> Result<(), EmulatorError> {
+    if id_token_info["status"] == "Accepted" {
+        return Ok(());
+    }
+
+    let transaction = storage::get_transaction(transaction_id);
+
+    if transaction.is_empty() {
+        return Ok(());
+    }
+
+    let mut transaction = parse(&transaction)?;
+
+    if !transaction["stoppedReason"].is_null() {
+        return Ok(());
+    }
+
+    println!("Id token of transaction {} is not valid anymore: {}", transaction_id, id_token_info["status"]);
+
+    if components::get_variable("TxCtrlr", "StopTxOnInvalidId").1.as_deref() == Some("true") {
+        return stop(transaction_id, "Deauthorized", "DeAuthorized");
+    }
+
+    let max_energy: f64 = components::get_variable("TxCtrlr", "MaxEnergyOnInvalidId").1
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0.0);
+
+    transaction["energyLimit"] = (transaction["meterStart"].as_f64().unwrap_or(0.0) + max_energy).into();
+
+    storage::set_transaction(transaction_id.to_string(), transaction.dump());
+
+    charging_profiles::apply_limits();
+
+    Ok(())
+}
+
+/// Get the connector of the EVSE which can be used for a new transaction.
+///
+/// Connector whose transaction waits for authorization is preferred, then a connector with a connected EV,
+/// otherwise an available or reserved connector is used.
+pub fn free_connector(evse_index: usize) -> Option<usize> {
+    let connectors = storage::get_connectors(evse_index);
+    let free = |statuses: &[&str]| (0..connectors.len()).find(|connector_index| {
+        let connector = &connectors[*connector_index];
+
+        connector.operational && statuses.contains(&connector.status) && find(evse_index, *connector_index).is_none()
+    });
+
+    let pending = (0..connectors.len()).find(|connector_index| {
+        find(evse_index, *connector_index).is_some_and(|transaction_id| parse(&storage::get_transaction(&transaction_id)).is_ok_and(|transaction| transaction["idToken"].is_null()))
+    });
+
+    pending
+        .or_else(|| free(&["Occupied"]).filter(|connector_index| connectors[*connector_index].charging_state != "Idle"))
+        .or_else(|| free(&["Available", "Reserved"]))
+}
+
+/// Start charging with the power allowed by charging profiles and notify CSMS about it.
+///
+/// Transaction which isn't authorized yet doesn't deliver energy.
+fn start_charging(evse_index: usize, connector_index: usize, transaction_id: &str, trigger_reason: &str) -> Result<(), EmulatorError> {
+    if let Some(power) = offered_power(evse_index, connector_index) {
+        state_machine::set_power(evse_index, connector_index, power);
+    }
+
+    notify(transaction_id, trigger_reason, None)
+}
+
+/// Find the ongoing transaction which uses the connector.
+///
+/// Returns the id of the transaction.
+pub fn find(evse_index: usize, connector_index: usize) -> Option<String> {
+    storage::get_transactions().into_iter()
+        .find(|(_, transaction)| json::parse(transaction).is_ok_and(|transaction| connector(&transaction) == (evse_index, connector_index)))
+        .map(|(transaction_id, _)| transaction_id)
+}
+
+/// Queue an "Updated" TransactionEvent request with the changed charging state of the connector.
+pub fn charging_state_changed(evse_index: usize, connector_index: usize) -> Result<(), EmulatorError> {
+    match find(evse_index, connector_index) {
+        Some(transaction_id) => notify(&transaction_id, "ChargingStateChanged", None),
+        None => Ok(()),
+    }
+}
+
+/// Queue an "Updated" TransactionEvent request with the changed charging rate of the connector.
+pub fn charging_rate_changed(evse_index: usize, connector_index: usize) -> Result<(), EmulatorError> {
+    match find(evse_index, connector_index) {
+        Some(transaction_id) => notify(&transaction_id, "ChargingRateChanged", None),
+        None => Ok(()),
+    }
+}
+
+/// Create a transaction on the connector which isn't authorized yet.
+///
+/// Returns the id of the transaction.
+fn create(evse_index: usize, connector_index: usize) -> String {
+    // Generate transaction id.
+    let transaction_id: &str = &ids::uuid();
+
+    let transaction = object!{
+        "evseId" => evse_index + 1,
+        "connectorId" => connector_index + 1,
+        "startTime" => clock::now().to_rfc3339(),
+        "meterStart" => meter::energy(evse_index),
+        "started" => false,
+    };
+
+    storage::set_transaction(transaction_id.to_string(), transaction.dump());
+
+    transaction_id.to_string()
+}
+
+/// Notify CSMS about a change of the transaction.
+///
+/// "Started" TransactionEvent request is sent once the transaction reaches a point of `TxCtrlr.TxStartPoint`,
+/// "Updated" ones are sent afterwards. Nothing is sent before the start point.
+fn notify(transaction_id: &str, trigger_reason: &str, id_token: Option<JsonValue>) -> Result<(), EmulatorError> {
+    let mut transaction = parse(&storage::get_transaction(transaction_id))?;
+    let (evse_index, connector_index) = connector(&transaction);
+    let charging_state = state_machine::charging_state(evse_index, connector_index);
+
+    let event = if transaction["started"] == true {
+        requests::TransactionEvent {
+            event_type: "Updated",
+            trigger_reason,
+            charging_state: Some(charging_state),
+            id_token,
+            ..Default::default()
+        }
+    } else if start_point_reached(&transaction) {
+        transaction["started"] = true.into();
+        // Session targets are measured from the start of the transaction.
+        transaction["startedAt"] = clock::now().to_rfc3339().into();
+        transaction["startEnergy"] = meter::energy(evse_index).into();
+
+        storage::set_transaction(transaction_id.to_string(), transaction.dump());
+
+        requests::TransactionEvent {
+            event_type: "Started",
+            trigger_reason,
+            charging_state: Some(charging_state),
+            remote_start_id: transaction["remoteStartId"].as_u64(),
+            id_token: Some(transaction["idToken"].clone()).filter(|id_token| !id_token.is_null()),
+            meter_value: Some(meter::transaction_sample(evse_index, "TxStartedMeasurands", "Transaction.Begin")?),
+            reservation_id: transaction["reservationId"].as_u64(),
+            ..Default::default()
+        }
+    } else {
+        return Ok(());
+    };
+
+    queue_event(transaction_id, event)
+}
+
+/// Queue a TransactionEvent request of the transaction.
+///
+/// Sequence number, offline flag, connector details and the EVSE are filled in from the state of the transaction.
+pub fn queue_event(transaction_id: &str, mut event: requests::TransactionEvent) -> Result<(), EmulatorError> {
+    let mut transaction = parse(&storage::get_transaction(transaction_id))?;
+    let (evse_index, connector_index) = connector(&transaction);
+    let connector = storage::get_connector(evse_index, connector_index).ok_or(EmulatorError::UnknownConnector(evse_index, connector_index))?;
+
+    event.seq_no = transaction["seqNo"].as_u64().unwrap_or(0);
+    event.offline = !storage::is_online();
+    event.cable_max_current = Some(connector.max_current);
+    event.evse = Some(object!{ "id" => evse_index + 1, "connectorId" => connector_index + 1 });
+
+    // DC connectors don't report phases.
+    if connector.charging_state == "Charging" && connector.phases > 0 {
+        event.number_of_phases_used = Some(connector.phases);
+    }
+
+    transaction["seqNo"] = (event.seq_no + 1).into();
+
+    storage::set_transaction(transaction_id.to_string(), transaction.dump());
+
+    let msg_id: &str = &ids::uuid();
+    let msg = requests::transaction_event(msg_id, transaction_id, event)?;
+
+    storage::set_message(msg_id.to_string(), msg.to_owned());
+
+    // Request is journaled before it's queued to survive a crash.
+    journal::add_event(transaction_id, &transaction, &msg);
+
+    storage::queue_add(msg);
+
+    Ok(())
+}
+
+/// Check whether the transaction has reached a point of `TxCtrlr.TxStartPoint`.
+fn start_point_reached(transaction: &JsonValue) -> bool {
+    let (evse_index, connector_index) = connector(transaction);
+    let charging_state = state_machine::charging_state(evse_index, connector_index);
+
+    let ev_connected = charging_state != "Idle";
+    let authorized = !transaction["idToken"].is_null();
+
+    tx_points("TxStartPoint").iter().any(|point| match point.as_str() {
+        "EVConnected" => ev_connected,
+        "Authorized" => authorized,
+        "PowerPathClosed" => ev_connected && authorized,
+        "EnergyTransfer" => charging_state == "Charging",
+        _ => false,
+    })
+}
+
+/// Get the points of `TxCtrlr.TxStartPoint` or `TxCtrlr.TxStopPoint`.
+fn tx_points(variable_name: &str) -> Vec<String> {
+    components::get_variable("TxCtrlr", variable_name).1.unwrap_or_default()
+        .split(',')
+        .map(|point| point.trim().to_string())
+        .filter(|point| !point.is_empty())
+        .collect()
+}
+
+/// Get indexes of the EVSE and the connector which are used by the transaction.
+pub fn connector(transaction: &JsonValue) -> (usize, usize) {
+    (transaction["evseId"].as_usize().unwrap_or(1).max(1) - 1, transaction["connectorId"].as_usize().unwrap_or(1).max(1) - 1)
+}
+
+/// Get a target of simulated sessions.
+fn target(name: &str) -> Option<f64> {
+    config::var(name).ok().and_then(|var| var.parse::<f64>().ok()).filter(|target| *target > 0.0)
+}
+
+/// Check whether cable is plugged in and out together with starting and stopping a transaction.
+fn auto_plug() -> bool {
+    config::var("AUTO_PLUG").map_or(true, |var| var != "false")
+}
+
+fn parse(transaction: &str) -> Result<JsonValue, EmulatorError> {
+    Ok(json::parse(transaction)?)
+}